@@ -0,0 +1,145 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use glm::Vec3;
+
+// What a single ray saw: its color and which object it hit, if any
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    pub color: Vec3,
+    pub obj: Option<usize>,
+}
+
+// Ray counts for an adaptive render. Shared between the render threads.
+#[derive(Default)]
+pub struct AdaptiveStats {
+    rays: AtomicUsize,
+    budget: AtomicUsize,
+}
+
+#[allow(dead_code)]
+impl AdaptiveStats {
+    pub fn new() -> AdaptiveStats {
+        AdaptiveStats::default()
+    }
+
+    pub fn rays(&self) -> usize {
+        self.rays.load(Ordering::Relaxed)
+    }
+
+    // Rays a full subdivision of every pixel would have cast
+    pub fn budget(&self) -> usize {
+        self.budget.load(Ordering::Relaxed)
+    }
+
+    pub fn saved(&self) -> usize {
+        self.budget() - self.rays()
+    }
+}
+
+impl fmt::Display for AdaptiveStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let budget = self.budget().max(1);
+
+        write!(
+            f,
+            "cast {} of {} rays, saved {} ({:.1}%)",
+            self.rays(),
+            self.budget(),
+            self.saved(),
+            100.0 * self.saved() as f32 / budget as f32,
+        )
+    }
+}
+
+// Adaptive supersampling. Each pixel starts with a ray through each corner.
+// A square whose corners differ in color by more than `threshold`, or that hit
+// different objects, is split into four and each quarter is sampled the same
+// way, up to `max_depth` times.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Adaptive {
+    pub threshold: f32,
+    pub max_depth: usize,
+}
+
+#[allow(dead_code)]
+impl Adaptive {
+    pub fn new() -> Adaptive {
+        Adaptive {
+            threshold: 0.1,
+            max_depth: 3,
+        }
+    }
+
+    pub fn build(threshold: f32, max_depth: usize) -> Adaptive {
+        Adaptive {
+            threshold,
+            max_depth,
+        }
+    }
+
+    pub fn pixel<F>(&self, x: usize, y: usize, shade: &F, stats: &AdaptiveStats) -> Vec3
+    where
+        F: Fn(f32, f32) -> Sample,
+    {
+        // Corners live on a (2^depth + 1)^2 lattice over the pixel. Samples are
+        // cached on it so neighbouring squares share their corners.
+        let cells = 1usize << self.max_depth;
+        let mut cache: HashMap<(usize, usize), Sample> = HashMap::new();
+
+        let mut sample = |i: usize, j: usize| -> Sample {
+            *cache.entry((i, j)).or_insert_with(|| {
+                shade(
+                    x as f32 + i as f32 / cells as f32,
+                    y as f32 + j as f32 / cells as f32,
+                )
+            })
+        };
+
+        let color = self.square(0, 0, cells, &mut sample);
+
+        stats.rays.fetch_add(cache.len(), Ordering::Relaxed);
+        stats.budget.fetch_add((cells + 1) * (cells + 1), Ordering::Relaxed);
+
+        color
+    }
+
+    fn square<S>(&self, i: usize, j: usize, size: usize, sample: &mut S) -> Vec3
+    where
+        S: FnMut(usize, usize) -> Sample,
+    {
+        let corners = [
+            sample(i, j),
+            sample(i + size, j),
+            sample(i, j + size),
+            sample(i + size, j + size),
+        ];
+
+        if size > 1 && self.differ(&corners) {
+            let half = size / 2;
+
+            return (self.square(i, j, half, sample)
+                + self.square(i + half, j, half, sample)
+                + self.square(i, j + half, half, sample)
+                + self.square(i + half, j + half, half, sample))
+                / 4.0;
+        }
+
+        corners.iter().fold(Vec3::zeros(), |sum, s| sum + s.color) / 4.0
+    }
+
+    fn differ(&self, corners: &[Sample; 4]) -> bool {
+        if corners.iter().any(|s| s.obj != corners[0].obj) {
+            return true;
+        }
+
+        let lo = corners.iter().fold(corners[0].color, |m, s| glm::min2(&m, &s.color));
+        let hi = corners.iter().fold(corners[0].color, |m, s| glm::max2(&m, &s.color));
+
+        glm::comp_max(&(hi - lo)) > self.threshold
+    }
+}
@@ -0,0 +1,198 @@
+extern crate nalgebra_glm as glm;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use glm::Vec3;
+
+use crate::image::invalid;
+use crate::triangle::Triangle;
+
+// Bicubic Bézier patch from a 4x4 grid of control points, row by row: u runs
+// along a row and v down the rows. The surface passes through the four corner
+// points and is pulled toward the rest.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BezierPatch {
+    pub points: [Vec3; 16],
+}
+
+#[allow(dead_code)]
+impl BezierPatch {
+    pub fn build(points: [Vec3; 16]) -> BezierPatch {
+        BezierPatch { points }
+    }
+
+    pub fn point(&self, u: f32, v: f32) -> Vec3 {
+        self.blend(&bernstein(u), &bernstein(v))
+    }
+
+    // How the surface changes along u and along v
+    pub fn derivatives(&self, u: f32, v: f32) -> (Vec3, Vec3) {
+        (
+            self.blend(&bernstein_slope(u), &bernstein(v)),
+            self.blend(&bernstein(u), &bernstein_slope(v)),
+        )
+    }
+
+    // Facing along du x dv. Where a row of control points collapses to one,
+    // as at the top of the teapot's lid, that's zero, so the normal is taken
+    // a little way in from the edge instead.
+    pub fn normal(&self, u: f32, v: f32) -> Vec3 {
+        let nudge = |x: f32| if x < 0.5 { x + 0.001 } else { x - 0.001 };
+        let (du, dv) = self.derivatives(u, v);
+        let n = glm::cross(&du, &dv);
+
+        if n.norm() > 1e-6 {
+            return n.normalize();
+        }
+
+        let (du, dv) = self.derivatives(nudge(u), nudge(v));
+        let n = glm::cross(&du, &dv);
+
+        if n.norm() > 0.0 { n.normalize() } else { n }
+    }
+
+    // Smooth triangles on a `level` by `level` grid over the patch, facing
+    // the same way as normal(). Slivers where the patch pinches to a point
+    // are left out.
+    pub fn tessellate(&self, level: usize) -> Vec<Triangle> {
+        let n = level.max(1);
+        let uv = |i: usize| i as f32 / n as f32;
+
+        let mut triangles = vec![];
+
+        for row in 0..n {
+            for column in 0..n {
+                let (u0, u1, v0, v1) = (uv(column), uv(column + 1), uv(row), uv(row + 1));
+                let corner = |u: f32, v: f32| (self.point(u, v), self.normal(u, v));
+
+                let (p00, n00) = corner(u0, v0);
+                let (p10, n10) = corner(u1, v0);
+                let (p01, n01) = corner(u0, v1);
+                let (p11, n11) = corner(u1, v1);
+
+                for t in [
+                    Triangle::smooth(&p00, &p01, &p10, &n00, &n01, &n10),
+                    Triangle::smooth(&p10, &p01, &p11, &n10, &n01, &n11),
+                ] {
+                    if !t.is_degenerate() {
+                        triangles.push(t);
+                    }
+                }
+            }
+        }
+
+        triangles
+    }
+
+    fn blend(&self, bu: &[f32; 4], bv: &[f32; 4]) -> Vec3 {
+        (0..16).fold(Vec3::zeros(), |sum, k| sum + self.points[k] * (bv[k / 4] * bu[k % 4]))
+    }
+}
+
+// Patches in either of the usual teapot formats. Newell's original lists the
+// number of patches, then each patch as 16 comma separated indices into the
+// vertices counting from 1, then the number of vertices and each vertex as
+// x, y, z. The .bpt format lists the number of patches, then for each its
+// degrees "3 3" and its 16 points.
+pub fn load_patches(path: &Path) -> io::Result<Vec<BezierPatch>> {
+    parse_patches(&fs::read_to_string(path)?)
+}
+
+pub fn parse_patches(text: &str) -> io::Result<Vec<BezierPatch>> {
+    let lines: Vec<Vec<f32>> = text
+        .lines()
+        .map(|l| l.split([',', ' ', '\t']).filter(|s| !s.is_empty()).map(|s| s.parse::<f32>()).collect())
+        .collect::<Result<Vec<Vec<f32>>, _>>()
+        .map_err(|_| invalid("bad number in patch file"))?
+        .into_iter()
+        .filter(|l| !l.is_empty())
+        .collect();
+
+    let count = match lines.first() {
+        Some(l) if l.len() == 1 => l[0] as usize,
+        _ => return Err(invalid("patch file doesn't start with a patch count")),
+    };
+
+    let vertex = |l: &Vec<f32>| -> io::Result<Vec3> {
+        match l.as_slice() {
+            [x, y, z] => Ok(glm::vec3(*x, *y, *z)),
+            _ => Err(invalid("patch vertex should have three coordinates")),
+        }
+    };
+
+    let rest = &lines[1..];
+
+    // .bpt: a degree line then the points, for each patch
+    if rest.first().is_some_and(|l| l.len() == 2) {
+        if rest.len() < count * 17 {
+            return Err(invalid("patch file is truncated"));
+        }
+
+        return rest
+            .chunks(17)
+            .take(count)
+            .map(|chunk| {
+                if chunk[0] != [3.0, 3.0] {
+                    return Err(invalid("only bicubic patches are supported"));
+                }
+
+                let mut points = [Vec3::zeros(); 16];
+                for (p, l) in points.iter_mut().zip(&chunk[1..]) {
+                    *p = vertex(l)?;
+                }
+
+                Ok(BezierPatch::build(points))
+            })
+            .collect();
+    }
+
+    if rest.len() <= count {
+        return Err(invalid("patch file is truncated"));
+    }
+
+    let vertex_count = match rest[count].as_slice() {
+        [n] => *n as usize,
+        _ => return Err(invalid("patch file is missing its vertex count")),
+    };
+
+    let vertices: Vec<Vec3> = rest[count + 1..]
+        .iter()
+        .take(vertex_count)
+        .map(vertex)
+        .collect::<io::Result<Vec<Vec3>>>()?;
+
+    if vertices.len() < vertex_count {
+        return Err(invalid("patch file is truncated"));
+    }
+
+    rest[..count]
+        .iter()
+        .map(|indices| {
+            if indices.len() != 16 {
+                return Err(invalid("patch should have 16 control points"));
+            }
+
+            let mut points = [Vec3::zeros(); 16];
+            for (p, &i) in points.iter_mut().zip(indices) {
+                *p = *vertices
+                    .get((i as usize).wrapping_sub(1))
+                    .ok_or_else(|| invalid("patch index out of range"))?;
+            }
+
+            Ok(BezierPatch::build(points))
+        })
+        .collect()
+}
+
+fn bernstein(t: f32) -> [f32; 4] {
+    let s = 1.0 - t;
+    [s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t]
+}
+
+fn bernstein_slope(t: f32) -> [f32; 4] {
+    let s = 1.0 - t;
+    [-3.0 * s * s, 3.0 * s * s - 6.0 * t * s, 6.0 * t * s - 3.0 * t * t, 3.0 * t * t]
+}
@@ -0,0 +1,373 @@
+extern crate nalgebra_glm as glm;
+
+use std::f32::consts::{FRAC_1_PI, PI};
+
+use glm::Vec3;
+
+use crate::rng::Rng;
+use crate::sampling;
+
+// Below this GGX gets numerically unstable in f32, so roughness 0 is a very
+// sharp lobe rather than a perfect mirror
+const MIN_ALPHA: f32 = 0.001;
+
+// A direction picked by Bsdf::sample, with the BSDF value and pdf for it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BsdfSample {
+    pub wi: Vec3,
+    pub f: Vec3,
+    pub pdf: f32,
+}
+
+// How a surface scatters light. `wo` points back toward the viewer, `wi` toward
+// the light and `n` is the surface's outward normal; `wo` may be on either
+// side of it. Values don't include the cosine term.
+//
+// Roughness follows glTF: 0 is smooth, 1 is rough, and GGX's alpha is its
+// square.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bsdf {
+    Lambertian { albedo: Vec3 },
+    // Metal with reflectance `f0` at normal incidence (Schlick's Fresnel)
+    Conductor { f0: Vec3, roughness: f32 },
+    // Glass-like, reflecting and refracting with the exact Fresnel equations
+    Dielectric { ior: f32, roughness: f32 },
+    // glTF's metallic/roughness model: a diffuse base under a 4% specular
+    // layer, blending into a tinted metal as `metallic` goes to 1
+    MetallicRoughness { base_color: Vec3, metallic: f32, roughness: f32 },
+}
+
+#[allow(dead_code)]
+impl Bsdf {
+    pub fn lambertian(albedo: &Vec3) -> Bsdf {
+        Bsdf::Lambertian { albedo: *albedo }
+    }
+
+    pub fn conductor(f0: &Vec3, roughness: f32) -> Bsdf {
+        Bsdf::Conductor { f0: *f0, roughness }
+    }
+
+    pub fn dielectric(ior: f32, roughness: f32) -> Bsdf {
+        Bsdf::Dielectric { ior, roughness }
+    }
+
+    pub fn metallic_roughness(base_color: &Vec3, metallic: f32, roughness: f32) -> Bsdf {
+        Bsdf::MetallicRoughness {
+            base_color: *base_color,
+            metallic: metallic.clamp(0.0, 1.0),
+            roughness,
+        }
+    }
+
+    pub fn evaluate(&self, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> Vec3 {
+        let n = facing(normal, wo);
+
+        match *self {
+            Bsdf::Lambertian { albedo } => {
+                if glm::dot(wi, &n) <= 0.0 {
+                    return Vec3::zeros();
+                }
+
+                albedo * FRAC_1_PI
+            }
+            Bsdf::Conductor { f0, roughness } => {
+                let h = match half_vector(wo, wi, &n) {
+                    Some(h) => h,
+                    None => return Vec3::zeros(),
+                };
+
+                schlick(&f0, glm::dot(wi, &h)) * microfacet(wo, wi, &h, &n, alpha(roughness))
+            }
+            Bsdf::Dielectric { ior, roughness } => {
+                let value = dielectric_evaluate(wo, wi, &n, ior_ratio(wo, normal, ior), alpha(roughness));
+                glm::vec3(value, value, value)
+            }
+            Bsdf::MetallicRoughness { base_color, metallic, roughness } => {
+                let h = match half_vector(wo, wi, &n) {
+                    Some(h) => h,
+                    None => return Vec3::zeros(),
+                };
+
+                let i_dot_h = glm::dot(wi, &h);
+                let f0 = glm::lerp(&glm::vec3(0.04, 0.04, 0.04), &base_color, metallic);
+                let specular = schlick(&f0, i_dot_h) * microfacet(wo, wi, &h, &n, alpha(roughness));
+
+                // Light the specular layer reflects never reaches the base.
+                // glTF weights by 1 - F(i.h), which can gain energy at grazing
+                // angles; taking Fresnel on the way in and out doesn't.
+                let transmitted = (1.0 - schlick_scalar(0.04, glm::dot(wi, &n)))
+                    * (1.0 - schlick_scalar(0.04, glm::dot(wo, &n)));
+                let diffuse = base_color * ((1.0 - metallic) * transmitted * FRAC_1_PI);
+
+                specular + diffuse
+            }
+        }
+    }
+
+    pub fn sample(&self, wo: &Vec3, normal: &Vec3, rng: &mut Rng) -> Option<BsdfSample> {
+        let n = facing(normal, wo);
+
+        let wi = match *self {
+            Bsdf::Lambertian { .. } => sampling::cosine_hemisphere(&n, rng.next_f32(), rng.next_f32()),
+            Bsdf::Conductor { roughness, .. } => {
+                let h = sample_ggx(&n, alpha(roughness), rng.next_f32(), rng.next_f32());
+                glm::reflect_vec(&-wo, &h)
+            }
+            Bsdf::Dielectric { ior, roughness } => {
+                let eta = ior_ratio(wo, normal, ior);
+                let h = sample_ggx(&n, alpha(roughness), rng.next_f32(), rng.next_f32());
+                let o_dot_h = glm::dot(wo, &h);
+
+                if o_dot_h <= 0.0 {
+                    return None;
+                }
+
+                // Reflections must stay above the surface and refractions
+                // go below it, or they'd be counted as the other kind
+                if rng.next_f32() < fresnel_dielectric(o_dot_h, eta) {
+                    let wi = glm::reflect_vec(&-wo, &h);
+
+                    if glm::dot(&wi, &n) <= 0.0 {
+                        return None;
+                    }
+
+                    wi
+                }
+                else {
+                    let wi = refract(wo, &h, eta)?;
+
+                    if glm::dot(&wi, &n) >= 0.0 {
+                        return None;
+                    }
+
+                    wi
+                }
+            }
+            Bsdf::MetallicRoughness { metallic, roughness, .. } => {
+                if rng.next_f32() < specular_chance(metallic) {
+                    let h = sample_ggx(&n, alpha(roughness), rng.next_f32(), rng.next_f32());
+                    glm::reflect_vec(&-wo, &h)
+                }
+                else {
+                    sampling::cosine_hemisphere(&n, rng.next_f32(), rng.next_f32())
+                }
+            }
+        };
+
+        let pdf = self.pdf(wo, &wi, normal);
+
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            wi,
+            f: self.evaluate(wo, &wi, normal),
+            pdf,
+        })
+    }
+
+    // Density of Bsdf::sample picking `wi`, per unit solid angle
+    pub fn pdf(&self, wo: &Vec3, wi: &Vec3, normal: &Vec3) -> f32 {
+        let n = facing(normal, wo);
+
+        match *self {
+            Bsdf::Lambertian { .. } => glm::dot(wi, &n).max(0.0) * FRAC_1_PI,
+            Bsdf::Conductor { roughness, .. } => match half_vector(wo, wi, &n) {
+                Some(h) => reflection_pdf(wo, &h, &n, alpha(roughness)),
+                None => 0.0,
+            },
+            Bsdf::Dielectric { ior, roughness } => {
+                dielectric_pdf(wo, wi, &n, ior_ratio(wo, normal, ior), alpha(roughness))
+            }
+            Bsdf::MetallicRoughness { metallic, roughness, .. } => {
+                let specular = match half_vector(wo, wi, &n) {
+                    Some(h) => reflection_pdf(wo, &h, &n, alpha(roughness)),
+                    None => 0.0,
+                };
+                let diffuse = glm::dot(wi, &n).max(0.0) * FRAC_1_PI;
+                let p = specular_chance(metallic);
+
+                p * specular + (1.0 - p) * diffuse
+            }
+        }
+    }
+}
+
+fn alpha(roughness: f32) -> f32 {
+    (roughness * roughness).max(MIN_ALPHA)
+}
+
+// `n` flipped onto the same side as `v`
+fn facing(n: &Vec3, v: &Vec3) -> Vec3 {
+    if glm::dot(n, v) < 0.0 { -n } else { *n }
+}
+
+// Index of refraction on wo's side over the other side, given the outward normal
+fn ior_ratio(wo: &Vec3, n: &Vec3, ior: f32) -> f32 {
+    if glm::dot(wo, n) >= 0.0 { 1.0 / ior } else { ior }
+}
+
+// Chance of sampling the specular lobe of MetallicRoughness
+fn specular_chance(metallic: f32) -> f32 {
+    0.5 + 0.5 * metallic
+}
+
+// Half vector of a reflection, if both directions are above the surface
+fn half_vector(wo: &Vec3, wi: &Vec3, n: &Vec3) -> Option<Vec3> {
+    if glm::dot(wo, n) <= 0.0 || glm::dot(wi, n) <= 0.0 {
+        return None;
+    }
+
+    Some((wo + wi).normalize())
+}
+
+// GGX normal distribution
+fn ggx_d(h: &Vec3, n: &Vec3, alpha: f32) -> f32 {
+    let cos = glm::dot(h, n);
+
+    if cos <= 0.0 {
+        return 0.0;
+    }
+
+    let a2 = alpha * alpha;
+    let d = cos * cos * (a2 - 1.0) + 1.0;
+
+    a2 / (PI * d * d)
+}
+
+// Smith masking for one direction
+fn ggx_g1(v: &Vec3, n: &Vec3, alpha: f32) -> f32 {
+    let cos = glm::dot(v, n).abs();
+    let a2 = alpha * alpha;
+
+    2.0 * cos / (cos + (a2 + (1.0 - a2) * cos * cos).sqrt())
+}
+
+// D G / (4 cos_o cos_i), the reflection lobe without Fresnel
+fn microfacet(wo: &Vec3, wi: &Vec3, h: &Vec3, n: &Vec3, alpha: f32) -> f32 {
+    let (cos_o, cos_i) = (glm::dot(wo, n), glm::dot(wi, n));
+    let g = ggx_g1(wo, n, alpha) * ggx_g1(wi, n, alpha);
+
+    ggx_d(h, n, alpha) * g / (4.0 * cos_o * cos_i)
+}
+
+// Microfacet normal with density D(h) cos(theta_h)
+fn sample_ggx(n: &Vec3, alpha: f32, u: f32, v: f32) -> Vec3 {
+    let cos2 = (1.0 - u) / (1.0 + (alpha * alpha - 1.0) * u);
+    let cos = cos2.sqrt();
+    let sin = (1.0 - cos2).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    let (s, t) = sampling::orthonormal_basis(n);
+
+    (s * (sin * phi.cos()) + t * (sin * phi.sin()) + n * cos).normalize()
+}
+
+fn reflection_pdf(wo: &Vec3, h: &Vec3, n: &Vec3, alpha: f32) -> f32 {
+    ggx_d(h, n, alpha) * glm::dot(h, n) / (4.0 * glm::dot(wo, h))
+}
+
+fn schlick(f0: &Vec3, cos: f32) -> Vec3 {
+    let m = (1.0 - cos.clamp(0.0, 1.0)).powi(5);
+
+    f0 + (glm::vec3(1.0, 1.0, 1.0) - f0) * m
+}
+
+fn schlick_scalar(f0: f32, cos: f32) -> f32 {
+    f0 + (1.0 - f0) * (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+// Unpolarised Fresnel reflectance, with `eta` the index on the incident side
+// over the other. 1 under total internal reflection.
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let rp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+
+    (rs * rs + rp * rp) / 2.0
+}
+
+// `wo` refracted through a surface with normal `h`, or None under total
+// internal reflection
+fn refract(wo: &Vec3, h: &Vec3, eta: f32) -> Option<Vec3> {
+    let cos_i = glm::dot(wo, h);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+
+    if sin2_t >= 1.0 {
+        return None;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+
+    Some((-wo * eta + h * (eta * cos_i - cos_t)).normalize())
+}
+
+// Half vector of a refraction from `wo` to `wi`, turned to face `n`. `eta` is
+// the index on wo's side over wi's side.
+fn refraction_half_vector(wo: &Vec3, wi: &Vec3, n: &Vec3, eta: f32) -> Vec3 {
+    let h = -(wo * eta + wi).normalize();
+
+    facing(&h, n)
+}
+
+// Walter et al.'s rough dielectric, without the 1 / eta^2 radiance scaling so
+// it stays reciprocal and energy conserving
+fn dielectric_evaluate(wo: &Vec3, wi: &Vec3, n: &Vec3, eta: f32, alpha: f32) -> f32 {
+    let (cos_o, cos_i) = (glm::dot(wo, n), glm::dot(wi, n));
+
+    if cos_o <= 0.0 || cos_i == 0.0 {
+        return 0.0;
+    }
+
+    if cos_i > 0.0 {
+        let h = (wo + wi).normalize();
+        return fresnel_dielectric(glm::dot(wo, &h), eta) * microfacet(wo, wi, &h, n, alpha);
+    }
+
+    let h = refraction_half_vector(wo, wi, n, eta);
+    let (o_dot_h, i_dot_h) = (glm::dot(wo, &h), glm::dot(wi, &h));
+
+    if o_dot_h <= 0.0 || i_dot_h >= 0.0 {
+        return 0.0;
+    }
+
+    let f = fresnel_dielectric(o_dot_h, eta);
+    let g = ggx_g1(wo, n, alpha) * ggx_g1(wi, n, alpha);
+    let denom = eta * o_dot_h + i_dot_h;
+
+    (1.0 - f) * ggx_d(&h, n, alpha) * g * o_dot_h * -i_dot_h / (cos_o * -cos_i * denom * denom)
+}
+
+fn dielectric_pdf(wo: &Vec3, wi: &Vec3, n: &Vec3, eta: f32, alpha: f32) -> f32 {
+    let cos_i = glm::dot(wi, n);
+
+    if glm::dot(wo, n) <= 0.0 || cos_i == 0.0 {
+        return 0.0;
+    }
+
+    if cos_i > 0.0 {
+        let h = (wo + wi).normalize();
+        return fresnel_dielectric(glm::dot(wo, &h), eta) * reflection_pdf(wo, &h, n, alpha);
+    }
+
+    let h = refraction_half_vector(wo, wi, n, eta);
+    let (o_dot_h, i_dot_h) = (glm::dot(wo, &h), glm::dot(wi, &h));
+
+    if o_dot_h <= 0.0 || i_dot_h >= 0.0 {
+        return 0.0;
+    }
+
+    // Change of variables from the half vector to the refracted direction
+    let denom = eta * o_dot_h + i_dot_h;
+    let jacobian = -i_dot_h / (denom * denom);
+
+    (1.0 - fresnel_dielectric(o_dot_h, eta)) * ggx_d(&h, n, alpha) * glm::dot(&h, n) * jacobian
+}
@@ -0,0 +1,47 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Mat4;
+use glm::Vec3;
+
+use crate::ray::Ray;
+
+// Anything that can turn a pixel into a primary ray.
+//
+// Cameras work in camera space, looking down -z with +y up, and `transform`
+// is the view transform that moves the world around them. Pixel coordinates
+// are continuous, so the center of pixel (x, y) is (x + 0.5, y + 0.5).
+#[allow(dead_code)]
+pub trait Camera: Send + Sync {
+    fn hsize(&self) -> usize;
+
+    fn vsize(&self) -> usize;
+
+    fn transform(&self) -> &Mat4;
+
+    fn set_transform(&mut self, t: &Mat4);
+
+    fn ray_for_pixel(&self, px: f32, py: f32) -> Ray;
+
+    // The single ray through (px, py) that ignores any lens, for picking
+    fn center_ray(&self, px: f32, py: f32) -> Ray {
+        self.ray_for_pixel(px, py)
+    }
+
+    // Tells the camera how far away the subject is. Only lenses care.
+    fn focus(&mut self, _distance: f32) {}
+}
+
+// Orients the world relative to an eye at `from` looking at `to`.
+// This is the same matrix as the book's view_transform.
+pub fn view_transform(from: &Vec3, to: &Vec3, up: &Vec3) -> Mat4 {
+    glm::look_at_rh(from, to, up)
+}
+
+// Moves a ray from camera space to world space, given the inverse of the
+// camera's transform
+pub fn to_world(inverse: &Mat4, origin: &Vec3, direction: &Vec3) -> Ray {
+    let o = glm::vec4_to_vec3(&(inverse * glm::vec4(origin.x, origin.y, origin.z, 1.0)));
+    let d = glm::vec4_to_vec3(&(inverse * glm::vec4(direction.x, direction.y, direction.z, 0.0)));
+
+    Ray::build(&o, &d.normalize())
+}
@@ -0,0 +1,12 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Vec3;
+
+// Colors are plain Vec3s with r, g, b in x, y, z.
+
+// Packs a color into minifb's 0RGB pixel format, clamping each channel.
+pub fn to_u32(c: &Vec3) -> u32 {
+    let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u32;
+
+    (channel(c.x) << 16) | (channel(c.y) << 8) | channel(c.z)
+}
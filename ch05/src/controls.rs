@@ -0,0 +1,207 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Mat4;
+use glm::Vec3;
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window};
+
+const ORBIT_SPEED: f32 = 0.05;
+const PAN_SPEED: f32 = 0.02;
+const DOLLY_SPEED: f32 = 0.1;
+const MOUSE_ORBIT_SPEED: f32 = 0.01;
+const MOUSE_PAN_SPEED: f32 = 0.002;
+const MAX_PITCH: f32 = 1.55;
+
+// Orbit camera for the preview window. The eye sits `distance` away from
+// `target`, rotated by `yaw` around the up axis and tilted by `pitch`.
+//
+//   Left click                 inspect the object under the cursor
+//   Arrow keys / left drag     orbit around the target
+//   WASD / right drag          pan the target
+//   Q, E / scroll wheel        dolly in and out
+//   P                          print the current view_transform
+#[allow(dead_code)]
+pub struct OrbitControls {
+    pub target: Vec3,
+    pub up: Vec3,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    drag: Option<Drag>,
+    click: Option<(usize, usize)>,
+}
+
+#[derive(Clone, Copy)]
+struct Drag {
+    last: (f32, f32),
+    left: bool,
+    moved: bool,
+}
+
+#[allow(dead_code)]
+impl OrbitControls {
+    pub fn build(from: &Vec3, to: &Vec3, up: &Vec3) -> OrbitControls {
+        let offset = from - to;
+        let distance = offset.norm().max(f32::EPSILON);
+
+        OrbitControls {
+            target: *to,
+            up: up.normalize(),
+            distance,
+            yaw: f32::atan2(offset.x, offset.z),
+            pitch: f32::asin((offset.y / distance).clamp(-1.0, 1.0)),
+            drag: None,
+            click: None,
+        }
+    }
+
+    pub fn from(&self) -> Vec3 {
+        let dir = glm::vec3(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        );
+
+        self.target + dir * self.distance
+    }
+
+    pub fn view_transform(&self) -> Mat4 {
+        crate::camera::view_transform(&self.from(), &self.target, &self.up)
+    }
+
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    // Moves the target in the camera's image plane. Scaled by the distance so
+    // the scene moves at the same on-screen speed however far away it is.
+    pub fn pan(&mut self, right: f32, up: f32) {
+        let forward = (self.target - self.from()).normalize();
+        let r = glm::cross(&forward, &self.up).normalize();
+        let u = glm::cross(&r, &forward);
+
+        self.target += (r * right + u * up) * self.distance;
+    }
+
+    pub fn dolly(&mut self, amount: f32) {
+        self.distance = (self.distance * f32::exp(-amount)).max(0.01);
+    }
+
+    // Formatted so it can be pasted straight back into a scene
+    pub fn describe(&self) -> String {
+        let from = self.from();
+
+        format!(
+            "view_transform(&glm::vec3({:.4}, {:.4}, {:.4}), &glm::vec3({:.4}, {:.4}, {:.4}), &glm::vec3({:.4}, {:.4}, {:.4}))",
+            from.x, from.y, from.z,
+            self.target.x, self.target.y, self.target.z,
+            self.up.x, self.up.y, self.up.z,
+        )
+    }
+
+    // The pixel under the last left click that didn't turn into a drag
+    pub fn take_click(&mut self) -> Option<(usize, usize)> {
+        self.click.take()
+    }
+
+    // Applies this frame's keyboard and mouse input.
+    // Returns true if the camera moved.
+    pub fn update(&mut self, window: &Window) -> bool {
+        let mut moved = false;
+
+        let keys = [
+            (Key::Left, -ORBIT_SPEED, 0.0),
+            (Key::Right, ORBIT_SPEED, 0.0),
+            (Key::Up, 0.0, ORBIT_SPEED),
+            (Key::Down, 0.0, -ORBIT_SPEED),
+        ];
+
+        for (key, yaw, pitch) in keys {
+            if window.is_key_down(key) {
+                self.orbit(yaw, pitch);
+                moved = true;
+            }
+        }
+
+        let keys = [
+            (Key::A, -PAN_SPEED, 0.0),
+            (Key::D, PAN_SPEED, 0.0),
+            (Key::W, 0.0, PAN_SPEED),
+            (Key::S, 0.0, -PAN_SPEED),
+        ];
+
+        for (key, right, up) in keys {
+            if window.is_key_down(key) {
+                self.pan(right, up);
+                moved = true;
+            }
+        }
+
+        if window.is_key_down(Key::E) {
+            self.dolly(DOLLY_SPEED);
+            moved = true;
+        }
+
+        if window.is_key_down(Key::Q) {
+            self.dolly(-DOLLY_SPEED);
+            moved = true;
+        }
+
+        if let Some((_, scroll)) = window.get_scroll_wheel() {
+            if scroll != 0.0 {
+                self.dolly(scroll.signum() * DOLLY_SPEED);
+                moved = true;
+            }
+        }
+
+        let left = window.get_mouse_down(MouseButton::Left);
+        let right = window.get_mouse_down(MouseButton::Right);
+        let mouse = window.get_mouse_pos(MouseMode::Discard);
+
+        match (left || right, mouse, self.drag) {
+            (true, Some((x, y)), Some(drag)) => {
+                let (dx, dy) = (x - drag.last.0, y - drag.last.1);
+
+                if dx != 0.0 || dy != 0.0 {
+                    if drag.left {
+                        self.orbit(-dx * MOUSE_ORBIT_SPEED, dy * MOUSE_ORBIT_SPEED);
+                    }
+                    else {
+                        self.pan(-dx * MOUSE_PAN_SPEED, dy * MOUSE_PAN_SPEED);
+                    }
+
+                    self.drag = Some(Drag {
+                        last: (x, y),
+                        left: drag.left,
+                        moved: true,
+                    });
+                    moved = true;
+                }
+            }
+            (true, Some(pos), None) => {
+                self.drag = Some(Drag {
+                    last: pos,
+                    left,
+                    moved: false,
+                })
+            }
+            (true, None, _) => {}
+            (false, _, drag) => {
+                // A left press and release without moving is a click
+                if let Some(drag) = drag {
+                    if drag.left && !drag.moved {
+                        self.click = Some((drag.last.0 as usize, drag.last.1 as usize));
+                    }
+                }
+
+                self.drag = None;
+            }
+        }
+
+        if window.is_key_pressed(Key::P, KeyRepeat::No) {
+            println!("{}", self.describe());
+        }
+
+        moved
+    }
+}
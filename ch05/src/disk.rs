@@ -0,0 +1,85 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Mat4;
+use glm::Vec3;
+
+use crate::hittable::Hittable;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::ray::Ray;
+
+// Flat disk in the xz plane facing up y, centered on the origin. A nonzero
+// `inner` radius cuts a hole out of the middle, leaving an annulus.
+#[allow(dead_code)]
+pub struct Disk {
+    inner: f32,
+    outer: f32,
+    pub transform: Mat4,
+    pub material: Material,
+    pub name: String,
+}
+
+#[allow(dead_code)]
+impl Disk {
+    pub fn new() -> Disk {
+        Disk::build(0.0, 1.0, &Mat4::identity())
+    }
+
+    pub fn build(inner: f32, outer: f32, t: &Mat4) -> Disk {
+        Disk {
+            inner,
+            outer,
+            transform: *t,
+            material: Material::new(),
+            name: String::from("disk"),
+        }
+    }
+
+    pub fn inner(&self) -> f32 {
+        self.inner
+    }
+
+    pub fn outer(&self) -> f32 {
+        self.outer
+    }
+}
+
+impl Hittable for Disk {
+    fn intersect(&self, r: &Ray) -> Vec<Intersection<'_>> {
+        let r2 = Ray::transform(r, &glm::inverse(&self.transform_at(r.time)));
+
+        // Parallel rays never cross the plane
+        if r2.direction.y.abs() < f32::EPSILON {
+            return vec![];
+        }
+
+        let t = -r2.origin.y / r2.direction.y;
+        let p = r2.position(t);
+        let r_squared = p.x * p.x + p.z * p.z;
+
+        if r_squared > self.outer * self.outer || r_squared < self.inner * self.inner {
+            return vec![];
+        }
+
+        vec![Intersection::build(t, self)]
+    }
+
+    fn normal_at_time(&self, _p: &Vec3, time: f32) -> Vec3 {
+        let inverse = glm::inverse(&self.transform_at(time));
+        let world_normal = glm::transpose(&inverse) * glm::vec4(0.0, 1.0, 0.0, 0.0);
+
+        glm::vec4_to_vec3(&world_normal).normalize()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn transform(&self) -> &Mat4 {
+        &self.transform
+    }
+}
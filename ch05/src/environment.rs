@@ -0,0 +1,150 @@
+extern crate nalgebra_glm as glm;
+
+use std::f32::consts::{PI, TAU};
+use std::io;
+use std::path::Path;
+
+use glm::Vec3;
+
+use crate::image::{invalid, Image};
+use crate::light::{Light, LightSample};
+use crate::rng::Rng;
+use crate::sampling::Distribution2D;
+
+// Equirectangular HDR image all around the scene, seen by rays that miss
+// everything and lighting the scene like any other light.
+//
+// The layout matches PanoramicCamera with no transform: longitude across the
+// image with -z in the middle, latitude from straight up at the top row to
+// straight down at the bottom. Light samples pick pixels in proportion to
+// their luminance, so the sun in an outdoor map gets most of the shadow rays.
+#[allow(dead_code)]
+pub struct Environment {
+    pub image: Image,
+    // Multiplies every pixel, like an exposure
+    pub scale: f32,
+    // Light samples per shading point
+    pub samples: usize,
+    distribution: Distribution2D,
+}
+
+#[allow(dead_code)]
+impl Environment {
+    pub fn build(image: Image) -> Environment {
+        assert!(image.width > 0 && image.height > 0, "Environment image is empty.\n");
+
+        // Rows near the poles cover less of the sphere, so weigh by sin(theta)
+        let weights: Vec<f32> = image
+            .pixels
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let row = i / image.width.max(1);
+                let theta = PI * (row as f32 + 0.5) / image.height as f32;
+
+                luminance(c) * theta.sin()
+            })
+            .collect();
+
+        let distribution = Distribution2D::build(&weights, image.width, image.height);
+
+        Environment {
+            image,
+            scale: 1.0,
+            samples: 4,
+            distribution,
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Environment> {
+        let image = Image::load(path)?;
+
+        if image.width == 0 || image.height == 0 {
+            return Err(invalid("environment image is empty"));
+        }
+
+        Ok(Environment::build(image))
+    }
+
+    // Direction through the point (u, v) of the image, both in [0, 1)
+    pub fn direction(u: f32, v: f32) -> Vec3 {
+        let longitude = (u - 0.5) * TAU;
+        let latitude = (0.5 - v) * PI;
+
+        glm::vec3(
+            -latitude.cos() * longitude.sin(),
+            latitude.sin(),
+            -latitude.cos() * longitude.cos(),
+        )
+    }
+
+    // Image coordinates of a unit direction
+    pub fn uv(direction: &Vec3) -> (f32, f32) {
+        let longitude = (-direction.x).atan2(-direction.z);
+        let latitude = direction.y.clamp(-1.0, 1.0).asin();
+
+        ((longitude / TAU + 0.5).rem_euclid(1.0), (0.5 - latitude / PI).clamp(0.0, 1.0))
+    }
+
+    pub fn radiance(&self, direction: &Vec3) -> Vec3 {
+        let (u, v) = Environment::uv(&direction.normalize());
+        let x = ((u * self.image.width as f32) as usize).min(self.image.width - 1);
+        let y = ((v * self.image.height as f32) as usize).min(self.image.height - 1);
+
+        self.image.get(x, y) * self.scale
+    }
+
+    // Direction picked by luminance and its density per unit solid angle
+    pub fn sample(&self, u: f32, v: f32) -> (Vec3, f32) {
+        let ((x, y), pdf) = self.distribution.sample(u, v);
+        let sin_theta = (PI * y).sin();
+
+        if sin_theta <= 0.0 {
+            return (Environment::direction(x, y), 0.0);
+        }
+
+        (Environment::direction(x, y), pdf / (2.0 * PI * PI * sin_theta))
+    }
+
+    pub fn pdf(&self, direction: &Vec3) -> f32 {
+        let (u, v) = Environment::uv(&direction.normalize());
+        let sin_theta = (PI * v).sin();
+
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+impl Light for Environment {
+    // Average radiance, for the ambient term
+    fn intensity(&self) -> Vec3 {
+        let count = self.image.pixels.len().max(1) as f32;
+        self.image.pixels.iter().fold(Vec3::zeros(), |sum, c| sum + c) * (self.scale / count)
+    }
+
+    fn samples(&self, _point: &Vec3, rng: &mut Rng) -> Vec<LightSample> {
+        (0..self.samples)
+            .filter_map(|_| {
+                let (direction, pdf) = self.sample(rng.next_f32(), rng.next_f32());
+                (pdf > 0.0).then(|| LightSample::from_radiance(&direction, &self.radiance(&direction), pdf))
+            })
+            .collect()
+    }
+
+    // Every direction reaches the environment unless something is in the way
+    fn hit(&self, _point: &Vec3, direction: &Vec3) -> Option<LightSample> {
+        let pdf = self.pdf(direction);
+        (pdf > 0.0).then(|| LightSample::from_radiance(direction, &self.radiance(direction), pdf))
+    }
+
+    fn background(&self, direction: &Vec3) -> Option<Vec3> {
+        Some(self.radiance(direction))
+    }
+}
+
+pub fn luminance(c: &Vec3) -> f32 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
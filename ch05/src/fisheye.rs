@@ -0,0 +1,75 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Mat4;
+
+use crate::camera::{self, Camera};
+use crate::ray::Ray;
+
+// Equidistant fisheye. The angle from the view axis grows linearly with the
+// distance from the image center, reaching `field_of_view / 2` at the edge of
+// the circle that fits the shorter side. Pixels outside that circle keep
+// going, so the corners see past the field of view up to straight behind.
+#[allow(dead_code)]
+pub struct FisheyeCamera {
+    hsize: usize,
+    vsize: usize,
+    field_of_view: f32,
+    transform: Mat4,
+    inverse: Mat4,
+}
+
+#[allow(dead_code)]
+impl FisheyeCamera {
+    pub fn new(hsize: usize, vsize: usize, field_of_view: f32) -> FisheyeCamera {
+        FisheyeCamera {
+            hsize,
+            vsize,
+            field_of_view,
+            transform: Mat4::identity(),
+            inverse: Mat4::identity(),
+        }
+    }
+
+    pub fn field_of_view(&self) -> f32 {
+        self.field_of_view
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn hsize(&self) -> usize {
+        self.hsize
+    }
+
+    fn vsize(&self) -> usize {
+        self.vsize
+    }
+
+    fn transform(&self) -> &Mat4 {
+        &self.transform
+    }
+
+    fn set_transform(&mut self, t: &Mat4) {
+        self.transform = *t;
+        self.inverse = glm::inverse(t);
+    }
+
+    fn ray_for_pixel(&self, px: f32, py: f32) -> Ray {
+        let radius = self.hsize.min(self.vsize) as f32 / 2.0;
+
+        // Same orientation as the perspective camera: +x is to the left
+        let x = (self.hsize as f32 / 2.0 - px) / radius;
+        let y = (self.vsize as f32 / 2.0 - py) / radius;
+        let r = (x * x + y * y).sqrt();
+
+        let theta = (r * self.field_of_view / 2.0).min(std::f32::consts::PI);
+
+        let direction = if r > 0.0 {
+            glm::vec3(x / r * theta.sin(), y / r * theta.sin(), -theta.cos())
+        }
+        else {
+            glm::vec3(0.0, 0.0, -1.0)
+        };
+
+        camera::to_world(&self.inverse, &glm::Vec3::zeros(), &direction)
+    }
+}
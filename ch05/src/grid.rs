@@ -0,0 +1,276 @@
+extern crate nalgebra_glm as glm;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use glm::Mat4;
+use glm::Vec3;
+
+use crate::image::{self, invalid};
+use crate::medium::{HenyeyGreenstein, Medium};
+use crate::ray::Ray;
+use crate::rng::Rng;
+
+// Densities on a regular 3D grid, such as a frame of a smoke or cloud
+// simulation. Voxels are stored with x changing fastest, then y, then z.
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DensityGrid {
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+    pub voxels: Vec<f32>,
+}
+
+#[allow(dead_code)]
+impl DensityGrid {
+    pub fn new(width: usize, height: usize, depth: usize) -> DensityGrid {
+        DensityGrid {
+            width,
+            height,
+            depth,
+            voxels: vec![0.0; width * height * depth],
+        }
+    }
+
+    pub fn build(width: usize, height: usize, depth: usize, voxels: Vec<f32>) -> DensityGrid {
+        assert_eq!(voxels.len(), width * height * depth);
+
+        DensityGrid {
+            width,
+            height,
+            depth,
+            voxels,
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.voxels[(z * self.height + y) * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, density: f32) {
+        self.voxels[(z * self.height + y) * self.width + x] = density;
+    }
+
+    pub fn max(&self) -> f32 {
+        self.voxels.iter().fold(0.0, |m, &d| m.max(d))
+    }
+
+    // Density at a point of the unit cube the grid fills, blended between the
+    // eight nearest voxel centers. Zero outside.
+    pub fn density(&self, p: &Vec3) -> f32 {
+        if self.voxels.is_empty() || p.iter().any(|&c| !(0.0..=1.0).contains(&c)) {
+            return 0.0;
+        }
+
+        let axis = |c: f32, n: usize| {
+            let x = (c * n as f32 - 0.5).clamp(0.0, (n - 1) as f32);
+            let i = (x as usize).min(n.saturating_sub(2));
+            (i, (i + 1).min(n - 1), x - i as f32)
+        };
+
+        let (x0, x1, fx) = axis(p.x, self.width);
+        let (y0, y1, fy) = axis(p.y, self.height);
+        let (z0, z1, fz) = axis(p.z, self.depth);
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let plane = |z: usize| {
+            lerp(
+                lerp(self.get(x0, y0, z), self.get(x1, y0, z), fx),
+                lerp(self.get(x0, y1, z), self.get(x1, y1, z), fx),
+                fy,
+            )
+        };
+
+        lerp(plane(z0), plane(z1), fz)
+    }
+
+    // Raw voxel file, laid out like a PFM: "VOL", width, height and depth as
+    // text, one whitespace byte, then little-endian f32 densities
+    pub fn to_raw(&self) -> Vec<u8> {
+        let mut bytes = format!("VOL\n{} {} {}\n", self.width, self.height, self.depth).into_bytes();
+
+        for d in self.voxels.iter() {
+            bytes.extend_from_slice(&d.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_raw())
+    }
+
+    pub fn load(path: &Path) -> io::Result<DensityGrid> {
+        DensityGrid::from_raw(&fs::read(path)?)
+    }
+
+    pub fn from_raw(bytes: &[u8]) -> io::Result<DensityGrid> {
+        let (tokens, data) = image::header_tokens(bytes, 4)?;
+
+        if tokens[0] != "VOL" {
+            return Err(invalid("not a voxel file"));
+        }
+
+        let width: usize = image::parse(&tokens[1])?;
+        let height: usize = image::parse(&tokens[2])?;
+        let depth: usize = image::parse(&tokens[3])?;
+        let count = image::checked_size(&[width, height, depth, 4], "voxel file is too big")? / 4;
+
+        if data.len() / 4 < count {
+            return Err(invalid("voxel file is truncated"));
+        }
+
+        let voxels: Vec<f32> = data
+            .chunks_exact(4)
+            .take(count)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+
+        // An infinite voxel would make the majorant infinite, and tracking
+        // would then never get anywhere
+        if voxels.iter().any(|d| !d.is_finite()) {
+            return Err(invalid("voxel file has a density that isn't a finite number"));
+        }
+
+        let voxels = voxels.into_iter().map(|d| d.max(0.0)).collect();
+
+        Ok(DensityGrid::build(width, height, depth, voxels))
+    }
+}
+
+// A density grid placed in the scene, filling the box `transform` makes of
+// the unit cube. A voxel of density 1 stops light at `extinction` per unit
+// distance and scatters `albedo` of what it stops.
+//
+// Free paths are picked by delta tracking and shadow rays attenuated by
+// ratio tracking, both against the grid's densest voxel: tentative
+// collisions are spaced as if the whole box were that dense, and each is
+// kept, or weighs the transmittance down, by how dense the grid really is
+// there.
+#[allow(dead_code)]
+pub struct GridVolume {
+    pub grid: DensityGrid,
+    pub extinction: f32,
+    pub albedo: Vec3,
+    pub phase: HenyeyGreenstein,
+    transform: Mat4,
+    inverse: Mat4,
+    majorant: f32,
+}
+
+#[allow(dead_code)]
+impl GridVolume {
+    // Fails if the densest voxel times `extinction` is too big for an f32,
+    // as tracking couldn't step through an infinitely dense majorant
+    pub fn build(grid: DensityGrid, transform: &Mat4, extinction: f32, albedo: &Vec3, g: f32) -> io::Result<GridVolume> {
+        let majorant = grid.max() * extinction;
+
+        if !majorant.is_finite() {
+            return Err(invalid("grid volume is too dense"));
+        }
+
+        Ok(GridVolume {
+            grid,
+            extinction,
+            albedo: *albedo,
+            phase: HenyeyGreenstein::build(g),
+            transform: *transform,
+            inverse: glm::inverse(transform),
+            majorant,
+        })
+    }
+
+    pub fn transform(&self) -> &Mat4 {
+        &self.transform
+    }
+
+    // Extinction per unit distance at a point in world space
+    pub fn extinction_at(&self, p: &Vec3) -> f32 {
+        let local = glm::vec4_to_vec3(&(self.inverse * glm::vec4(p.x, p.y, p.z, 1.0)));
+        self.grid.density(&local) * self.extinction
+    }
+
+    // The medium at a point, as if it were the same all around
+    pub fn medium_at(&self, p: &Vec3) -> Medium {
+        let scattering = self.albedo * self.extinction_at(p);
+        let absorption = glm::vec3(1.0, 1.0, 1.0) * self.extinction_at(p) - scattering;
+
+        Medium {
+            absorption,
+            scattering,
+            phase: self.phase,
+        }
+    }
+
+    // Part of the ray between 0 and `t_max` inside the box, by slabs
+    pub fn span(&self, r: &Ray, t_max: f32) -> Option<(f32, f32)> {
+        let local = Ray::transform(r, &self.inverse);
+        let (mut start, mut end) = (0.0_f32, t_max);
+
+        for axis in 0..3 {
+            let (o, d) = (local.origin[axis], local.direction[axis]);
+
+            if d == 0.0 {
+                if !(0.0..=1.0).contains(&o) {
+                    return None;
+                }
+
+                continue;
+            }
+
+            let (t0, t1) = ((0.0 - o) / d, (1.0 - o) / d);
+            start = start.max(t0.min(t1));
+            end = end.min(t0.max(t1));
+        }
+
+        (start < end).then_some((start, end))
+    }
+
+    // Where along the ray, before `t_max`, it first scatters or is absorbed,
+    // by delta tracking. The chance of getting through is the transmittance.
+    pub fn sample_distance(&self, r: &Ray, t_max: f32, rng: &mut Rng) -> Option<f32> {
+        let (start, end) = self.span(r, t_max)?;
+
+        if self.majorant <= 0.0 {
+            return None;
+        }
+
+        let mut t = start;
+
+        loop {
+            t -= (1.0 - rng.next_f32()).ln() / self.majorant;
+
+            if t >= end {
+                return None;
+            }
+
+            if rng.next_f32() * self.majorant < self.extinction_at(&r.position(t)) {
+                return Some(t);
+            }
+        }
+    }
+
+    // Unbiased estimate of the transmittance along the ray up to `t_max`,
+    // by ratio tracking
+    pub fn transmittance(&self, r: &Ray, t_max: f32, rng: &mut Rng) -> f32 {
+        let (start, end) = match self.span(r, t_max) {
+            Some(span) if self.majorant > 0.0 => span,
+            _ => return 1.0,
+        };
+
+        let mut t = start;
+        let mut tr = 1.0;
+
+        loop {
+            t -= (1.0 - rng.next_f32()).ln() / self.majorant;
+
+            if t >= end {
+                return tr;
+            }
+
+            tr *= 1.0 - self.extinction_at(&r.position(t)) / self.majorant;
+        }
+    }
+}
@@ -0,0 +1,272 @@
+extern crate nalgebra_glm as glm;
+
+use std::io;
+use std::path::Path;
+
+use glm::Mat4;
+use glm::Vec3;
+
+use crate::hittable::Hittable;
+use crate::image::{self, Image};
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::ray::Ray;
+
+// Slack when matching a hit to the cell it was found in, so one on the line
+// between two cells isn't lost to rounding
+const EPSILON: f32 = 1e-5;
+
+// Terrain from a grid of heights: sample (i, j) sits at x = i / (width - 1),
+// z = j / (depth - 1) on the unit square with its height as y. Each cell is
+// two triangles split corner to corner, but rays only visit the cells under
+// them, so a big grid costs little more than a small one.
+#[allow(dead_code)]
+pub struct Heightfield {
+    width: usize,
+    depth: usize,
+    heights: Vec<f32>,
+    normals: Vec<Vec3>,
+    low: f32,
+    high: f32,
+    pub transform: Mat4,
+    pub material: Material,
+    pub name: String,
+}
+
+#[allow(dead_code)]
+impl Heightfield {
+    pub fn build(width: usize, depth: usize, heights: Vec<f32>, t: &Mat4) -> Heightfield {
+        assert!(width >= 2 && depth >= 2, "Heightfield needs at least 2 by 2 samples.\n");
+        assert_eq!(heights.len(), width * depth, "Heightfield size doesn't match its heights.\n");
+
+        let mut field = Heightfield {
+            width,
+            depth,
+            low: heights.iter().copied().fold(f32::INFINITY, f32::min),
+            high: heights.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+            heights,
+            normals: vec![],
+            transform: *t,
+            material: Material::new(),
+            name: String::from("heightfield"),
+        };
+
+        field.normals = (0..width * depth).map(|k| field.sample_normal(k % width, k / width)).collect();
+        field
+    }
+
+    // Heights from an image's brightness, its top row at z = 0
+    pub fn from_image(image: &Image, t: &Mat4) -> io::Result<Heightfield> {
+        if image.width < 2 || image.height < 2 {
+            return Err(image::invalid("heightfield image should be at least 2 by 2"));
+        }
+
+        let heights = image.pixels.iter().map(|p| (p.x + p.y + p.z) / 3.0).collect();
+
+        Ok(Heightfield::build(image.width, image.height, heights, t))
+    }
+
+    pub fn load(path: &Path, t: &Mat4) -> io::Result<Heightfield> {
+        Heightfield::from_image(&Image::load(path)?, t)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn height(&self, i: usize, j: usize) -> f32 {
+        self.heights[j * self.width + i]
+    }
+
+    // Corner points of cell (i, j) in object space
+    fn corner(&self, i: usize, j: usize) -> Vec3 {
+        glm::vec3(i as f32 / (self.width - 1) as f32, self.height(i, j), j as f32 / (self.depth - 1) as f32)
+    }
+
+    // From the slope between the neighbors on either side, or just the one
+    // neighbor along an edge
+    fn sample_normal(&self, i: usize, j: usize) -> Vec3 {
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.width - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.depth - 1));
+
+        let dx = (self.height(i1, j) - self.height(i0, j)) * (self.width - 1) as f32 / (i1 - i0) as f32;
+        let dz = (self.height(i, j1) - self.height(i, j0)) * (self.depth - 1) as f32 / (j1 - j0) as f32;
+
+        glm::vec3(-dx, 1.0, -dz).normalize()
+    }
+
+    // Hits on the two triangles of cell (i, j)
+    fn intersect_cell(&self, r: &Ray, i: usize, j: usize) -> Vec<f32> {
+        let (p00, p10) = (self.corner(i, j), self.corner(i + 1, j));
+        let (p01, p11) = (self.corner(i, j + 1), self.corner(i + 1, j + 1));
+
+        [(p00, p10, p11), (p00, p11, p01)]
+            .iter()
+            .filter_map(|(a, b, c)| intersect_triangle(r, a, b, c))
+            .collect()
+    }
+
+    // Where the ray is inside the box around the heights, if anywhere
+    fn bounds(&self, r: &Ray) -> Option<(f32, f32)> {
+        let (min, max) = (glm::vec3(0.0, self.low, 0.0), glm::vec3(1.0, self.high, 1.0));
+        let (mut t0, mut t1) = (f32::NEG_INFINITY, f32::INFINITY);
+
+        for a in 0..3 {
+            if r.direction[a] == 0.0 {
+                if r.origin[a] < min[a] || r.origin[a] > max[a] {
+                    return None;
+                }
+
+                continue;
+            }
+
+            let (near, far) = ((min[a] - r.origin[a]) / r.direction[a], (max[a] - r.origin[a]) / r.direction[a]);
+            t0 = t0.max(near.min(far));
+            t1 = t1.min(near.max(far));
+        }
+
+        if t0 <= t1 { Some((t0, t1)) } else { None }
+    }
+}
+
+impl Hittable for Heightfield {
+    // Walks the grid cell by cell along the ray's path over it, Amanatides
+    // and Woo style, skipping cells the ray passes wholly above or below
+    fn intersect(&self, r: &Ray) -> Vec<Intersection<'_>> {
+        let r2 = Ray::transform(r, &glm::inverse(&self.transform_at(r.time)));
+
+        let (t_start, t_end) = match self.bounds(&r2) {
+            Some(span) => span,
+            None => return vec![],
+        };
+
+        let cells = glm::vec2((self.width - 1) as f32, (self.depth - 1) as f32);
+        let start = r2.position(t_start);
+        let (o, d) = (glm::vec2(r2.origin.x, r2.origin.z), glm::vec2(r2.direction.x, r2.direction.z));
+
+        let mut cell = [0_i64; 2];
+        let mut step = [0_i64; 2];
+        let mut next = [f32::INFINITY; 2];
+        let mut delta = [f32::INFINITY; 2];
+
+        for (a, s) in [start.x, start.z].iter().enumerate() {
+            cell[a] = ((s * cells[a]).floor() as i64).clamp(0, cells[a] as i64 - 1);
+
+            if d[a] != 0.0 {
+                step[a] = if d[a] > 0.0 { 1 } else { -1 };
+                let edge = (cell[a] + i64::from(d[a] > 0.0)) as f32 / cells[a];
+                next[a] = (edge - o[a]) / d[a];
+                delta[a] = 1.0 / (cells[a] * d[a].abs());
+            }
+        }
+
+        let mut ts = vec![];
+        let mut t = t_start;
+
+        loop {
+            let exit = next[0].min(next[1]).min(t_end);
+            let (i, j) = (cell[0] as usize, cell[1] as usize);
+
+            let (y0, y1) = (r2.position(t).y, r2.position(exit).y);
+            let cell_heights = [self.height(i, j), self.height(i + 1, j), self.height(i, j + 1), self.height(i + 1, j + 1)];
+            let cell_low = cell_heights.iter().copied().fold(f32::INFINITY, f32::min);
+            let cell_high = cell_heights.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+
+            if y0.max(y1) >= cell_low - EPSILON && y0.min(y1) <= cell_high + EPSILON {
+                ts.extend(
+                    self.intersect_cell(&r2, i, j)
+                        .into_iter()
+                        .filter(|&hit| hit >= t - EPSILON && hit <= exit + EPSILON),
+                );
+            }
+
+            if exit >= t_end {
+                break;
+            }
+
+            let a = if next[0] < next[1] { 0 } else { 1 };
+            cell[a] += step[a];
+
+            if cell[a] < 0 || cell[a] >= cells[a] as i64 {
+                break;
+            }
+
+            t = next[a];
+            next[a] += delta[a];
+        }
+
+        // A hit on the line between two cells is found from both
+        ts.sort_by(|a, b| a.total_cmp(b));
+        ts.dedup_by(|a, b| (*a - *b).abs() < EPSILON);
+
+        ts.into_iter().map(|t| Intersection::build(t, self)).collect()
+    }
+
+    // Blended from the normals at the corners of the triangle the point is on
+    fn normal_at_time(&self, p: &Vec3, time: f32) -> Vec3 {
+        let inverse = glm::inverse(&self.transform_at(time));
+        let object_point = inverse * glm::vec4(p.x, p.y, p.z, 1.0);
+
+        let x = object_point.x.clamp(0.0, 1.0) * (self.width - 1) as f32;
+        let z = object_point.z.clamp(0.0, 1.0) * (self.depth - 1) as f32;
+        let (i, j) = ((x as usize).min(self.width - 2), (z as usize).min(self.depth - 2));
+        let (fx, fz) = (x - i as f32, z - j as f32);
+
+        let n = |i: usize, j: usize| self.normals[j * self.width + i];
+        let object_normal = if fx >= fz {
+            n(i, j) * (1.0 - fx) + n(i + 1, j) * (fx - fz) + n(i + 1, j + 1) * fz
+        }
+        else {
+            n(i, j) * (1.0 - fz) + n(i, j + 1) * (fz - fx) + n(i + 1, j + 1) * fx
+        };
+
+        let world_normal = glm::transpose(&inverse) * glm::vec4(object_normal.x, object_normal.y, object_normal.z, 0.0);
+
+        glm::vec4_to_vec3(&world_normal).normalize()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn transform(&self) -> &Mat4 {
+        &self.transform
+    }
+}
+
+// Möller and Trumbore's test against one triangle, as in triangle.rs but
+// with a finer parallel cutoff since the cells of a big grid are tiny
+fn intersect_triangle(r: &Ray, a: &Vec3, b: &Vec3, c: &Vec3) -> Option<f32> {
+    let (e1, e2) = (b - a, c - a);
+    let dir_cross_e2 = glm::cross(&r.direction, &e2);
+    let det = glm::dot(&e1, &dir_cross_e2);
+
+    if det.abs() < 1e-9 {
+        return None;
+    }
+
+    let f = 1.0 / det;
+    let a_to_origin = r.origin - a;
+    let u = f * glm::dot(&a_to_origin, &dir_cross_e2);
+
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let origin_cross_e1 = glm::cross(&a_to_origin, &e1);
+    let v = f * glm::dot(&r.direction, &origin_cross_e1);
+
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    Some(f * glm::dot(&e2, &origin_cross_e1))
+}
@@ -1,6 +1,39 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Mat4;
+use glm::Vec3;
+
 use crate::intersection::Intersection;
+use crate::material::Material;
 use crate::ray::Ray;
 
-pub trait Hittable {
-    fn intersect(&self, r: &Ray) -> Vec<Intersection>;
+// Objects are shared between the render threads, so they have to be Send + Sync.
+#[allow(dead_code)]
+pub trait Hittable: Send + Sync {
+    fn intersect(&self, r: &Ray) -> Vec<Intersection<'_>>;
+
+    // Surface normal at a point on the object, in world space
+    fn normal_at(&self, p: &Vec3) -> Vec3 {
+        self.normal_at_time(p, 0.0)
+    }
+
+    // Surface normal where the object was at `time`, for moving objects
+    fn normal_at_time(&self, p: &Vec3, time: f32) -> Vec3;
+
+    fn name(&self) -> &str;
+
+    fn material(&self) -> &Material;
+
+    fn transform(&self) -> &Mat4;
+
+    // Object transform at `time`. Same as transform() unless the object moves.
+    fn transform_at(&self, _time: f32) -> Mat4 {
+        *self.transform()
+    }
+
+    // Every transform between world space and the object, outermost first.
+    // Just the object's own transform until objects can be nested.
+    fn transform_chain(&self) -> Vec<Mat4> {
+        vec![*self.transform()]
+    }
 }
@@ -0,0 +1,394 @@
+extern crate nalgebra_glm as glm;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use glm::Vec3;
+
+// A grid of linear colors, row by row from the top left
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vec3>,
+}
+
+#[allow(dead_code)]
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![Vec3::zeros(); width * height],
+        }
+    }
+
+    pub fn build(width: usize, height: usize, pixels: Vec<Vec3>) -> Image {
+        assert_eq!(pixels.len(), width * height, "Image size doesn't match its pixels.\n");
+
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Vec3 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, c: &Vec3) {
+        self.pixels[y * self.width + x] = *c;
+    }
+
+    // Copies `other` in with its top left corner at (x, y)
+    pub fn blit(&mut self, other: &Image, x: usize, y: usize) {
+        for row in 0..other.height {
+            for col in 0..other.width {
+                self.set(x + col, y + row, &other.get(col, row));
+            }
+        }
+    }
+
+    pub fn to_u32(&self) -> Vec<u32> {
+        self.pixels.iter().map(crate::color::to_u32).collect()
+    }
+
+    // Plain PPM from chapter 2, lines kept under 70 characters
+    pub fn to_ppm(&self) -> String {
+        let mut out = format!("P3\n{} {}\n255\n", self.width, self.height);
+
+        for row in self.pixels.chunks(self.width.max(1)) {
+            let mut line = String::new();
+
+            for c in row {
+                for v in [c.x, c.y, c.z] {
+                    let s = ((v.clamp(0.0, 1.0) * 255.0).round() as u32).to_string();
+
+                    if line.len() + s.len() + 1 > 70 {
+                        out.push_str(&line);
+                        out.push('\n');
+                        line.clear();
+                    }
+
+                    if !line.is_empty() {
+                        line.push(' ');
+                    }
+
+                    line.push_str(&s);
+                }
+            }
+
+            out.push_str(&line);
+            out.push('\n');
+        }
+
+        out
+    }
+
+    pub fn save_ppm(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_ppm())
+    }
+
+    // Little-endian color PFM, which keeps the full float range
+    pub fn to_pfm(&self) -> Vec<u8> {
+        let mut out = format!("PF\n{} {}\n-1.0\n", self.width, self.height).into_bytes();
+
+        // Rows are stored bottom to top
+        for row in self.pixels.chunks(self.width.max(1)).rev() {
+            for c in row {
+                for v in [c.x, c.y, c.z] {
+                    out.extend_from_slice(&v.to_le_bytes());
+                }
+            }
+        }
+
+        out
+    }
+
+    pub fn save_pfm(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_pfm())
+    }
+
+    // Reads a Radiance .hdr, a .pfm or a .pgm, going by the file extension
+    pub fn load(path: &Path) -> io::Result<Image> {
+        let bytes = fs::read(path)?;
+
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+            Some("hdr") => Image::from_hdr(&bytes),
+            Some("pfm") => Image::from_pfm(&bytes),
+            Some("pgm") => Image::from_pgm(&bytes),
+            _ => Err(invalid("expected a .hdr, .pfm or .pgm image")),
+        }
+    }
+
+    // Portable gray map, "P2" with the values written out or "P5" with them
+    // as bytes, two a value big-endian if the maximum is over 255. Rows go
+    // from the top down and values are scaled by the maximum to 0 to 1, with
+    // no gamma applied since they're usually heights rather than colors.
+    pub fn from_pgm(bytes: &[u8]) -> io::Result<Image> {
+        let (tokens, data) = header_tokens(bytes, 4)?;
+
+        let width: usize = parse(&tokens[1])?;
+        let height: usize = parse(&tokens[2])?;
+        let max: u32 = parse(&tokens[3])?;
+        let count = checked_size(&[width, height], "PGM file is too big")?;
+
+        if max == 0 || max > 65535 {
+            return Err(invalid("PGM maximum should be from 1 to 65535"));
+        }
+
+        let values: Vec<u32> = match tokens[0].as_str() {
+            "P2" => {
+                let (values, _) = header_tokens(data, count).map_err(|_| invalid("PGM file is truncated"))?;
+                values.iter().map(|v| parse(v)).collect::<io::Result<Vec<u32>>>()?
+            }
+            "P5" => {
+                let size = if max > 255 { 2 } else { 1 };
+
+                if data.len() < checked_size(&[count, size], "PGM file is too big")? {
+                    return Err(invalid("PGM file is truncated"));
+                }
+
+                data.chunks_exact(size)
+                    .take(count)
+                    .map(|b| if size == 2 { u32::from(b[0]) << 8 | u32::from(b[1]) } else { u32::from(b[0]) })
+                    .collect()
+            }
+            _ => return Err(invalid("not a PGM file")),
+        };
+
+        let pixels = values
+            .iter()
+            .map(|&v| {
+                let grey = v.min(max) as f32 / max as f32;
+                glm::vec3(grey, grey, grey)
+            })
+            .collect();
+
+        Ok(Image::build(width, height, pixels))
+    }
+
+    // Portable float map: "PF" for color or "Pf" for grey, the size, then a
+    // scale whose sign gives the byte order (negative is little-endian), then
+    // rows of floats from the bottom up
+    pub fn from_pfm(bytes: &[u8]) -> io::Result<Image> {
+        let (tokens, data) = header_tokens(bytes, 4)?;
+
+        let channels = match tokens[0].as_str() {
+            "PF" => 3,
+            "Pf" => 1,
+            _ => return Err(invalid("not a PFM file")),
+        };
+
+        let width: usize = parse(&tokens[1])?;
+        let height: usize = parse(&tokens[2])?;
+        let scale: f32 = parse(&tokens[3])?;
+
+        let count = checked_size(&[width, height, channels], "PFM file is too big")?;
+
+        if data.len() / 4 < count {
+            return Err(invalid("PFM file is truncated"));
+        }
+
+        let floats: Vec<f32> = data
+            .chunks_exact(4)
+            .take(count)
+            .map(|b| {
+                let b = [b[0], b[1], b[2], b[3]];
+                if scale < 0.0 { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) }
+            })
+            .collect();
+
+        let mut pixels = Vec::with_capacity(width * height);
+
+        for row in floats.chunks(width.max(1) * channels).rev() {
+            for c in row.chunks(channels) {
+                pixels.push(if channels == 3 { glm::vec3(c[0], c[1], c[2]) } else { glm::vec3(c[0], c[0], c[0]) });
+            }
+        }
+
+        Ok(Image::build(width, height, pixels))
+    }
+
+    // Radiance RGBE: text header lines up to a blank line, a "-Y h +X w"
+    // resolution line, then scanlines either flat or with the newer per
+    // channel run-length encoding
+    pub fn from_hdr(bytes: &[u8]) -> io::Result<Image> {
+        let mut pos = 0;
+        let mut line = |bytes: &[u8]| -> io::Result<String> {
+            let end = bytes[pos..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .ok_or_else(|| invalid("HDR header is truncated"))?;
+            let text = String::from_utf8_lossy(&bytes[pos..pos + end]).trim().to_string();
+            pos += end + 1;
+            Ok(text)
+        };
+
+        if !line(bytes)?.starts_with("#?") {
+            return Err(invalid("not a Radiance HDR file"));
+        }
+
+        loop {
+            let l = line(bytes)?;
+
+            if l.is_empty() {
+                break;
+            }
+
+            if l.starts_with("FORMAT=") && l != "FORMAT=32-bit_rle_rgbe" {
+                return Err(invalid("only RGBE HDR files are supported"));
+            }
+        }
+
+        let resolution = line(bytes)?;
+        let parts: Vec<&str> = resolution.split_whitespace().collect();
+
+        if parts.len() != 4 || parts[0] != "-Y" || parts[2] != "+X" {
+            return Err(invalid("only -Y +X HDR files are supported"));
+        }
+
+        let height: usize = parse(parts[1])?;
+        let width: usize = parse(parts[3])?;
+        checked_size(&[width, height, 4], "HDR file is too big")?;
+
+        // Grown a scanline at a time, as the header's size can't be trusted
+        // until the data has been read
+        let mut data = &bytes[pos..];
+        let mut pixels = vec![];
+
+        for _ in 0..height {
+            let (scanline, rest) = hdr_scanline(data, width)?;
+            pixels.extend(scanline.iter().map(rgbe_to_vec3));
+            data = rest;
+        }
+
+        Ok(Image::build(width, height, pixels))
+    }
+}
+
+pub fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Product of sizes read from a header, or an error if it's too big to be a
+// real file rather than overflowing
+pub fn checked_size(factors: &[usize], message: &str) -> io::Result<usize> {
+    factors.iter().try_fold(1_usize, |size, &f| size.checked_mul(f)).ok_or_else(|| invalid(message))
+}
+
+pub fn parse<T: std::str::FromStr>(s: &str) -> io::Result<T> {
+    s.parse().map_err(|_| invalid("bad number in header"))
+}
+
+// First `count` whitespace separated header tokens and the data after the
+// single whitespace byte that ends the last one. Netpbm headers can have
+// comments from a # to the end of the line between tokens.
+pub fn header_tokens(bytes: &[u8], count: usize) -> io::Result<(Vec<String>, &[u8])> {
+    let mut tokens = vec![];
+    let mut pos = 0;
+
+    while tokens.len() < count {
+        while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b'#') {
+            if bytes[pos] == b'#' {
+                while pos < bytes.len() && bytes[pos] != b'\n' {
+                    pos += 1;
+                }
+            }
+            else {
+                pos += 1;
+            }
+        }
+
+        let start = pos;
+
+        while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+
+        if start == pos {
+            return Err(invalid("image header is truncated"));
+        }
+
+        tokens.push(String::from_utf8_lossy(&bytes[start..pos]).to_string());
+    }
+
+    Ok((tokens, &bytes[(pos + 1).min(bytes.len())..]))
+}
+
+// One scanline of RGBE pixels and the bytes after it
+fn hdr_scanline(data: &[u8], width: usize) -> io::Result<(Vec<[u8; 4]>, &[u8])> {
+    let truncated = || invalid("HDR pixel data is truncated");
+
+    let rle = (8..=0x7fff).contains(&width)
+        && data.len() >= 4
+        && data[0] == 2
+        && data[1] == 2
+        && ((data[2] as usize) << 8 | data[3] as usize) == width;
+
+    if !rle {
+        let size = checked_size(&[width, 4], "HDR file is too big")?;
+        let bytes = data.get(..size).ok_or_else(truncated)?;
+        let scanline = bytes.chunks_exact(4).map(|c| [c[0], c[1], c[2], c[3]]).collect();
+
+        return Ok((scanline, &data[size..]));
+    }
+
+    let mut scanline = vec![[0u8; 4]; width];
+    let mut pos = 4;
+
+    // Each channel is stored separately as runs and literal spans
+    for channel in 0..4 {
+        let mut x = 0;
+
+        while x < width {
+            let count = *data.get(pos).ok_or_else(truncated)? as usize;
+            pos += 1;
+
+            if count > 128 {
+                let count = count - 128;
+                let value = *data.get(pos).ok_or_else(truncated)?;
+                pos += 1;
+
+                if x + count > width {
+                    return Err(invalid("HDR run is too long"));
+                }
+
+                for p in &mut scanline[x..x + count] {
+                    p[channel] = value;
+                }
+
+                x += count;
+            }
+            else {
+                if count == 0 || x + count > width {
+                    return Err(invalid("HDR run is too long"));
+                }
+
+                let values = data.get(pos..pos + count).ok_or_else(truncated)?;
+
+                for (p, &v) in scanline[x..x + count].iter_mut().zip(values) {
+                    p[channel] = v;
+                }
+
+                pos += count;
+                x += count;
+            }
+        }
+    }
+
+    Ok((scanline, &data[pos..]))
+}
+
+fn rgbe_to_vec3(rgbe: &[u8; 4]) -> Vec3 {
+    if rgbe[3] == 0 {
+        return Vec3::zeros();
+    }
+
+    let f = 2.0_f32.powi(rgbe[3] as i32 - (128 + 8));
+
+    glm::vec3(rgbe[0] as f32 * f, rgbe[1] as f32 * f, rgbe[2] as f32 * f)
+}
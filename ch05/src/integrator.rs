@@ -0,0 +1,258 @@
+extern crate nalgebra_glm as glm;
+
+use std::f32::consts::PI;
+
+use glm::Vec3;
+
+use crate::adaptive::Sample;
+use crate::bsdf::{Bsdf, BsdfSample};
+use crate::intersection::Computations;
+use crate::light::LightSample;
+use crate::medium::{HenyeyGreenstein, Interaction};
+use crate::ray::Ray;
+use crate::rng::Rng;
+use crate::sampling;
+use crate::world::World;
+
+// Turns a ray into the light arriving along it
+#[allow(dead_code)]
+pub trait Integrator: Send + Sync {
+    fn li(&self, world: &World, r: &Ray) -> Vec3;
+
+    // Color plus the object that was hit, for adaptive sampling
+    fn sample(&self, world: &World, r: &Ray) -> Sample {
+        Sample {
+            color: self.li(world, r),
+            obj: world.hit(r).map(|i| i.obj_id()),
+        }
+    }
+}
+
+// The book's renderer: direct Phong lighting and shadows only
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Whitted;
+
+impl Integrator for Whitted {
+    fn li(&self, world: &World, r: &Ray) -> Vec3 {
+        world.color_at(r)
+    }
+}
+
+// Unidirectional path tracer. Each hit adds its emission and light sampled
+// straight from the lights (next-event estimation, with multiple importance
+// sampling), then bounces in a direction picked by the material's BSDF. After `roulette_depth` bounces
+// paths are randomly ended with a chance that follows their throughput.
+//
+// Rays through fog or a volume may scatter partway, picking a distance by the
+// medium's density; there light is gathered and the path goes on the same way,
+// with the phase function standing in for the BSDF.
+//
+// Lights are measured like the book's: a white light shining straight onto a
+// white Lambertian surface gives its albedo, the same as Phong without
+// specular. That makes a light sample worth pi times its intensity.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathTracer {
+    pub max_depth: usize,
+    pub roulette_depth: usize,
+}
+
+#[allow(dead_code)]
+impl PathTracer {
+    pub fn new() -> PathTracer {
+        PathTracer {
+            max_depth: 16,
+            roulette_depth: 3,
+        }
+    }
+
+    pub fn build(max_depth: usize, roulette_depth: usize) -> PathTracer {
+        PathTracer {
+            max_depth,
+            roulette_depth,
+        }
+    }
+
+    // Light reaching the eye from the lights by way of the hit.
+    //
+    // Lights with area are estimated twice, from their own samples and from
+    // `bsdf_sample` if it happens to hit them, and the two are blended with
+    // the power heuristic. Light samples handle small lights and rough
+    // surfaces well, BSDF samples big lights and glossy surfaces. Seen from a
+    // point, such a light has radiance pi * intensity * pdf, which keeps
+    // light samples as bright as the Whitted renderer's.
+    pub fn direct(
+        &self,
+        world: &World,
+        comps: &Computations,
+        bsdf: &Bsdf,
+        bsdf_sample: Option<&BsdfSample>,
+        time: f32,
+        rng: &mut Rng,
+    ) -> Vec3 {
+        let n = comps.outward_normal();
+        let transmittance = |s: &LightSample| world.transmittance(&comps.spawn_point(&s.direction), s, time);
+
+        world.lights.iter().fold(Vec3::zeros(), |sum, l| {
+            let samples = l.samples(&comps.over_point, rng);
+            let count = samples.len().max(1) as f32;
+
+            let from_light = samples.iter().fold(Vec3::zeros(), |total, s| {
+                let f = bsdf.evaluate(&comps.eyev, &s.direction, &n);
+
+                // Saves a shadow ray
+                if f == Vec3::zeros() {
+                    return total;
+                }
+
+                let cos = glm::dot(&s.direction, &n).abs();
+                let weight = if s.is_delta() {
+                    1.0
+                }
+                else {
+                    sampling::power_heuristic(count, s.pdf, 1.0, bsdf.pdf(&comps.eyev, &s.direction, &n))
+                };
+
+                total + f.component_mul(&s.intensity).component_mul(&transmittance(s)) * (cos * PI * weight)
+            }) / count;
+
+            let from_bsdf = bsdf_sample
+                .and_then(|b| l.hit(&comps.spawn_point(&b.wi), &b.wi).map(|s| (b, s)))
+                .map_or(Vec3::zeros(), |(b, s)| {
+                    let cos = glm::dot(&b.wi, &n).abs();
+                    let weight = sampling::power_heuristic(1.0, b.pdf, count, s.pdf);
+
+                    b.f.component_mul(&s.intensity).component_mul(&transmittance(&s)) * (PI * s.pdf * cos * weight / b.pdf)
+                });
+
+            sum + from_light + from_bsdf
+        })
+    }
+
+    // Light scattered toward `wo` inside a medium, the same way as direct()
+    // but with the phase function in place of the BSDF and no cosine.
+    // `scattered` starts where the light scatters and heads the way the phase
+    // function picked.
+    pub fn direct_in_medium(
+        &self,
+        world: &World,
+        scattered: &Ray,
+        wo: &Vec3,
+        phase: &HenyeyGreenstein,
+        rng: &mut Rng,
+    ) -> Vec3 {
+        let (point, time) = (&scattered.origin, scattered.time);
+
+        world.lights.iter().fold(Vec3::zeros(), |sum, l| {
+            let samples = l.samples(point, rng);
+            let count = samples.len().max(1) as f32;
+
+            let from_light = samples.iter().fold(Vec3::zeros(), |total, s| {
+                let f = phase.evaluate(wo, &s.direction);
+                let weight = if s.is_delta() { 1.0 } else { sampling::power_heuristic(count, s.pdf, 1.0, f) };
+
+                total + s.intensity.component_mul(&world.transmittance(point, s, time)) * (f * PI * weight)
+            }) / count;
+
+            // The phase function's sample has a pdf equal to its value
+            let from_phase = l.hit(point, &scattered.direction).map_or(Vec3::zeros(), |s| {
+                let pdf = phase.evaluate(wo, &scattered.direction);
+                let weight = sampling::power_heuristic(1.0, pdf, count, s.pdf);
+
+                s.intensity.component_mul(&world.transmittance(point, &s, time)) * (PI * s.pdf * weight)
+            });
+
+            sum + from_light + from_phase
+        })
+    }
+
+    // Russian roulette after `roulette_depth` bounces. Returns false if the
+    // path should end, otherwise makes up for the ones that did.
+    fn survives(&self, depth: usize, throughput: &mut Vec3, rng: &mut Rng) -> bool {
+        if depth + 1 < self.roulette_depth {
+            return true;
+        }
+
+        let survive = glm::comp_max(throughput).min(0.95);
+
+        if rng.next_f32() >= survive {
+            return false;
+        }
+
+        *throughput /= survive;
+        true
+    }
+}
+
+impl Integrator for PathTracer {
+    fn li(&self, world: &World, r: &Ray) -> Vec3 {
+        let mut rng = Rng::seeded(&r.seed());
+        let mut ray = Ray::build_at(&r.origin, &r.direction.normalize(), r.time);
+        let mut throughput = glm::vec3(1.0, 1.0, 1.0);
+        let mut radiance = Vec3::zeros();
+        let mut scattered = false;
+
+        for depth in 0..self.max_depth {
+            let hit = world.hit(&ray);
+            let t_max = hit.map_or(f32::INFINITY, |h| h.t());
+
+            if let Some(interaction) = world.sample_media(&ray, t_max, &mut rng) {
+                match interaction {
+                    // Absorbed
+                    Interaction::Scatter { weight, .. } if weight == Vec3::zeros() => break,
+                    Interaction::Scatter { t, weight, medium } => {
+                        let wo = -ray.direction;
+                        let wi = medium.phase.sample(&wo, rng.next_f32(), rng.next_f32());
+                        let next = Ray::build_at(&ray.position(t), &wi, ray.time);
+
+                        throughput = throughput.component_mul(&weight);
+                        radiance +=
+                            throughput.component_mul(&self.direct_in_medium(world, &next, &wo, &medium.phase, &mut rng));
+
+                        // The phase function's value and pdf cancel out
+                        if !self.survives(depth, &mut throughput, &mut rng) {
+                            break;
+                        }
+
+                        scattered = true;
+                        ray = next;
+                        continue;
+                    }
+                    Interaction::Pass { weight } => throughput = throughput.component_mul(&weight),
+                }
+            }
+
+            // Paths that escape after a bounce were already counted as light
+            // by `direct`'s BSDF sample
+            let comps = match hit {
+                Some(hit) => hit.prepare(&ray),
+                None if !scattered => return radiance + throughput.component_mul(&world.background(&ray.direction)),
+                None => break,
+            };
+
+            let material = comps.obj.material();
+            let bsdf = material.effective_bsdf();
+            let n = comps.outward_normal();
+            let sample = bsdf.sample(&comps.eyev, &n, &mut rng);
+
+            radiance += throughput.component_mul(&material.emissive);
+            radiance += throughput.component_mul(&self.direct(world, &comps, &bsdf, sample.as_ref(), ray.time, &mut rng));
+
+            let s = match sample {
+                Some(s) => s,
+                None => break,
+            };
+
+            throughput = throughput.component_mul(&s.f) * (glm::dot(&s.wi, &n).abs() / s.pdf);
+
+            if !self.survives(depth, &mut throughput, &mut rng) {
+                break;
+            }
+
+            scattered = true;
+            ray = Ray::build_at(&comps.spawn_point(&s.wi), &s.wi, ray.time);
+        }
+
+        radiance
+    }
+}
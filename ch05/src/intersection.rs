@@ -1,6 +1,13 @@
 extern crate nalgebra_glm as glm;
 
+use glm::Vec3;
+
 use crate::hittable::Hittable;
+use crate::ray::Ray;
+
+// How far secondary rays start above the surface, so a point doesn't shadow
+// or hit itself
+pub const SHADOW_EPSILON: f32 = 0.001;
 
 #[allow(dead_code)]
 #[derive(Clone, Copy)]
@@ -11,15 +18,15 @@ pub struct Intersection<'a> {
 
 #[allow(dead_code)]
 impl<'a> Intersection<'a> {
-    pub fn build(_t: f32, object: &'a (impl Hittable + 'static)) -> Intersection<'a> {
+    pub fn build(_t: f32, object: &'a (dyn Hittable + 'static)) -> Intersection<'a> {
         Intersection { t: _t, obj: object }
     }
 
-    pub fn hit(intersections: &Vec<Intersection<'a>>) -> Option<Intersection<'a>> {
+    pub fn hit(intersections: &[Intersection<'a>]) -> Option<Intersection<'a>> {
         let mut t_min: f32 = f32::MAX;
         let mut hit: Option<Intersection<'a>> = None;
 
-        for i in intersections.into_iter() {
+        for i in intersections.iter() {
             if i.t() < t_min && i.t() > 0.0 {
                 t_min = i.t();
                 hit = Some(*i);
@@ -36,4 +43,61 @@ impl<'a> Intersection<'a> {
     pub fn obj(&self) -> &'a dyn Hittable {
         self.obj
     }
+
+    // The book's prepare_computations: everything shading needs about the hit
+    pub fn prepare(&self, r: &Ray) -> Computations<'a> {
+        let point = r.position(self.t);
+        let eyev = -r.direction.normalize();
+        let mut normalv = self.obj.normal_at_time(&point, r.time);
+        let inside = glm::dot(&normalv, &eyev) < 0.0;
+
+        if inside {
+            normalv = -normalv;
+        }
+
+        Computations {
+            t: self.t,
+            obj: self.obj,
+            point,
+            over_point: point + normalv * SHADOW_EPSILON,
+            under_point: point - normalv * SHADOW_EPSILON,
+            eyev,
+            normalv,
+            inside,
+        }
+    }
+
+    // Identifies the object by its address, for telling apart what two rays hit
+    pub fn obj_id(&self) -> usize {
+        self.obj as *const dyn Hittable as *const () as usize
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub struct Computations<'a> {
+    pub t: f32,
+    pub obj: &'a dyn Hittable,
+    pub point: Vec3,
+    // Just above the surface, where secondary rays start
+    pub over_point: Vec3,
+    // Just below it, where refracted rays start
+    pub under_point: Vec3,
+    pub eyev: Vec3,
+    // Facing the eye, flipped if the ray started inside the object
+    pub normalv: Vec3,
+    pub inside: bool,
+}
+
+#[allow(dead_code)]
+impl Computations<'_> {
+    // Normal pointing out of the object, whichever side the eye is on
+    pub fn outward_normal(&self) -> Vec3 {
+        if self.inside { -self.normalv } else { self.normalv }
+    }
+
+    // Where a ray leaving the hit in `direction` should start
+    pub fn spawn_point(&self, direction: &Vec3) -> Vec3 {
+        if glm::dot(direction, &self.normalv) >= 0.0 { self.over_point } else { self.under_point }
+    }
 }
@@ -0,0 +1,473 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Vec3;
+
+use crate::material::Material;
+use crate::rng::Rng;
+use crate::sampling;
+
+// Where light arrives at a shading point from: the unit direction toward the
+// light, how far away it is (infinite for the sun) and how much gets there
+// before shadows are taken into account.
+//
+// `pdf` is the density of the direction per unit solid angle for lights with
+// area, and 0 for lights that sit at a single point or direction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightSample {
+    pub direction: Vec3,
+    pub distance: f32,
+    pub intensity: Vec3,
+    pub pdf: f32,
+}
+
+#[allow(dead_code)]
+impl LightSample {
+    pub fn build(direction: &Vec3, distance: f32, intensity: &Vec3) -> LightSample {
+        LightSample {
+            direction: *direction,
+            distance,
+            intensity: *intensity,
+            pdf: 0.0,
+        }
+    }
+
+    // Light from `position` reaching `point` with `intensity`
+    pub fn toward(point: &Vec3, position: &Vec3, intensity: &Vec3) -> LightSample {
+        let v = position - point;
+        let distance = glm::length(&v);
+
+        LightSample::build(&(v / distance), distance, intensity)
+    }
+
+    // Light from `position` on a surface of `area` facing along `normal`. Its
+    // pdf is that of picking the position uniformly, turned into solid angle.
+    pub fn from_area(point: &Vec3, position: &Vec3, normal: &Vec3, area: f32, intensity: &Vec3) -> LightSample {
+        let mut s = LightSample::toward(point, position, intensity);
+        let cos = glm::dot(&s.direction, normal).abs();
+
+        s.pdf = if cos > 0.0 { s.distance * s.distance / (area * cos) } else { f32::INFINITY };
+        s
+    }
+
+    // Light with `radiance` from infinitely far away in `direction`, picked
+    // with density `pdf`. Stored as the intensity that PathTracer::direct
+    // turns back into that radiance.
+    pub fn from_radiance(direction: &Vec3, radiance: &Vec3, pdf: f32) -> LightSample {
+        let mut s = LightSample::build(direction, f32::INFINITY, &(radiance / (std::f32::consts::PI * pdf)));
+        s.pdf = pdf;
+        s
+    }
+
+    pub fn is_delta(&self) -> bool {
+        self.pdf == 0.0
+    }
+}
+
+// Anything that lights the world. Each sample gets its own shadow ray.
+#[allow(dead_code)]
+pub trait Light: Send + Sync {
+    // Color and brightness, also used for the ambient term
+    fn intensity(&self) -> Vec3;
+
+    fn samples(&self, point: &Vec3, rng: &mut Rng) -> Vec<LightSample>;
+
+    // The sample the light would give for `direction` from `point`, if a ray
+    // that way hits it. Only lights with area can be hit.
+    fn hit(&self, _point: &Vec3, _direction: &Vec3) -> Option<LightSample> {
+        None
+    }
+
+    // What rays going off in `direction` without hitting anything see of the
+    // light. Only lights around the whole scene have a background.
+    fn background(&self, _direction: &Vec3) -> Option<Vec3> {
+        None
+    }
+}
+
+// How a light falls off with distance d: 1 / (constant + linear d + quadratic d^2)
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+#[allow(dead_code)]
+impl Attenuation {
+    // The book's lights don't fall off at all
+    pub fn none() -> Attenuation {
+        Attenuation::build(1.0, 0.0, 0.0)
+    }
+
+    pub fn inverse_square() -> Attenuation {
+        Attenuation::build(0.0, 0.0, 1.0)
+    }
+
+    pub fn build(constant: f32, linear: f32, quadratic: f32) -> Attenuation {
+        Attenuation {
+            constant,
+            linear,
+            quadratic,
+        }
+    }
+
+    pub fn factor(&self, distance: f32) -> f32 {
+        let d = self.constant + self.linear * distance + self.quadratic * distance * distance;
+
+        if d > 0.0 { 1.0 / d } else { 1.0 }
+    }
+}
+
+// Point light from chapter 6
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub intensity: Vec3,
+    pub attenuation: Attenuation,
+}
+
+#[allow(dead_code)]
+impl PointLight {
+    pub fn build(position: &Vec3, intensity: &Vec3) -> PointLight {
+        PointLight {
+            position: *position,
+            intensity: *intensity,
+            attenuation: Attenuation::none(),
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn intensity(&self) -> Vec3 {
+        self.intensity
+    }
+
+    fn samples(&self, point: &Vec3, _rng: &mut Rng) -> Vec<LightSample> {
+        let mut s = LightSample::toward(point, &self.position, &self.intensity);
+        s.intensity *= self.attenuation.factor(s.distance);
+
+        vec![s]
+    }
+}
+
+// Point light that only shines inside a cone around `direction`. Full
+// strength inside `inner`, nothing outside `outer`, and a smoothstep between.
+// Angles are half angles in radians.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub inner: f32,
+    pub outer: f32,
+    pub intensity: Vec3,
+    pub attenuation: Attenuation,
+}
+
+#[allow(dead_code)]
+impl SpotLight {
+    pub fn build(position: &Vec3, direction: &Vec3, inner: f32, outer: f32, intensity: &Vec3) -> SpotLight {
+        SpotLight {
+            position: *position,
+            direction: direction.normalize(),
+            inner,
+            outer: outer.max(inner),
+            intensity: *intensity,
+            attenuation: Attenuation::none(),
+        }
+    }
+
+    // How much of the light reaches `point`, from 0 outside the cone to 1
+    pub fn falloff(&self, point: &Vec3) -> f32 {
+        let cos_angle = glm::dot(&(point - self.position).normalize(), &self.direction);
+        let (cos_inner, cos_outer) = (self.inner.cos(), self.outer.cos());
+
+        if cos_angle >= cos_inner {
+            return 1.0;
+        }
+
+        if cos_angle <= cos_outer {
+            return 0.0;
+        }
+
+        let x = (cos_angle - cos_outer) / (cos_inner - cos_outer);
+        x * x * (3.0 - 2.0 * x)
+    }
+}
+
+impl Light for SpotLight {
+    fn intensity(&self) -> Vec3 {
+        self.intensity
+    }
+
+    fn samples(&self, point: &Vec3, _rng: &mut Rng) -> Vec<LightSample> {
+        let mut s = LightSample::toward(point, &self.position, &self.intensity);
+        s.intensity *= self.attenuation.factor(s.distance) * self.falloff(point);
+
+        vec![s]
+    }
+}
+
+// Sun-like light: parallel rays travelling along `direction` from infinitely
+// far away
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirectionalLight {
+    pub direction: Vec3,
+    pub intensity: Vec3,
+}
+
+#[allow(dead_code)]
+impl DirectionalLight {
+    pub fn build(direction: &Vec3, intensity: &Vec3) -> DirectionalLight {
+        DirectionalLight {
+            direction: direction.normalize(),
+            intensity: *intensity,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn intensity(&self) -> Vec3 {
+        self.intensity
+    }
+
+    fn samples(&self, _point: &Vec3, _rng: &mut Rng) -> Vec<LightSample> {
+        vec![LightSample::build(&-self.direction, f32::INFINITY, &self.intensity)]
+    }
+}
+
+// Rectangular light from the book's area light bonus chapter. The rectangle
+// spans `full_uvec` and `full_vvec` from `corner` and is split into
+// usteps x vsteps cells with one shadow ray per cell, jittered inside it.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AreaLight {
+    pub corner: Vec3,
+    pub uvec: Vec3,
+    pub usteps: usize,
+    pub vvec: Vec3,
+    pub vsteps: usize,
+    pub intensity: Vec3,
+    pub jitter: bool,
+}
+
+#[allow(dead_code)]
+impl AreaLight {
+    pub fn build(
+        corner: &Vec3,
+        full_uvec: &Vec3,
+        usteps: usize,
+        full_vvec: &Vec3,
+        vsteps: usize,
+        intensity: &Vec3,
+    ) -> AreaLight {
+        let usteps = usteps.max(1);
+        let vsteps = vsteps.max(1);
+
+        AreaLight {
+            corner: *corner,
+            uvec: full_uvec / usteps as f32,
+            usteps,
+            vvec: full_vvec / vsteps as f32,
+            vsteps,
+            intensity: *intensity,
+            jitter: true,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.usteps * self.vsteps
+    }
+
+    pub fn center(&self) -> Vec3 {
+        self.corner + self.uvec * (self.usteps as f32 / 2.0) + self.vvec * (self.vsteps as f32 / 2.0)
+    }
+
+    // Unnormalized normal of the rectangle; its length is the area
+    pub fn normal(&self) -> Vec3 {
+        glm::cross(&(self.uvec * self.usteps as f32), &(self.vvec * self.vsteps as f32))
+    }
+
+    pub fn area(&self) -> f32 {
+        glm::length(&self.normal())
+    }
+
+    pub fn point_on_light(&self, u: usize, v: usize, rng: &mut Rng) -> Vec3 {
+        let (ju, jv) = if self.jitter { (rng.next_f32(), rng.next_f32()) } else { (0.5, 0.5) };
+
+        self.corner + self.uvec * (u as f32 + ju) + self.vvec * (v as f32 + jv)
+    }
+}
+
+impl Light for AreaLight {
+    fn intensity(&self) -> Vec3 {
+        self.intensity
+    }
+
+    // Jittered cells still cover the rectangle uniformly, so every sample has
+    // the pdf of a uniformly picked point
+    fn samples(&self, point: &Vec3, rng: &mut Rng) -> Vec<LightSample> {
+        let normal = self.normal().normalize();
+        let area = self.area();
+
+        (0..self.vsteps)
+            .flat_map(|v| (0..self.usteps).map(move |u| (u, v)))
+            .map(|(u, v)| LightSample::from_area(point, &self.point_on_light(u, v, rng), &normal, area, &self.intensity))
+            .collect()
+    }
+
+    fn hit(&self, point: &Vec3, direction: &Vec3) -> Option<LightSample> {
+        let (edge_u, edge_v) = (self.uvec * self.usteps as f32, self.vvec * self.vsteps as f32);
+        let normal = self.normal();
+        let denom = glm::dot(direction, &normal);
+
+        if denom == 0.0 {
+            return None;
+        }
+
+        let t = glm::dot(&(self.corner - point), &normal) / denom;
+
+        if t <= 0.0 {
+            return None;
+        }
+
+        // Coordinates of the hit along the two edges
+        let q = point + direction * t - self.corner;
+        let n2 = glm::dot(&normal, &normal);
+        let a = glm::dot(&glm::cross(&q, &edge_v), &normal) / n2;
+        let b = glm::dot(&glm::cross(&edge_u, &q), &normal) / n2;
+
+        if !(0.0..=1.0).contains(&a) || !(0.0..=1.0).contains(&b) {
+            return None;
+        }
+
+        Some(LightSample::from_area(point, &(point + direction * t), &normal.normalize(), self.area(), &self.intensity))
+    }
+}
+
+// Spherical light. Seen from any point a sphere covers a disk, so shadow rays
+// aim at a stratified, jittered grid over the disk of the sphere's radius
+// that faces the shading point.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SphereLight {
+    pub center: Vec3,
+    pub radius: f32,
+    pub steps: usize,
+    pub intensity: Vec3,
+}
+
+#[allow(dead_code)]
+impl SphereLight {
+    pub fn build(center: &Vec3, radius: f32, steps: usize, intensity: &Vec3) -> SphereLight {
+        SphereLight {
+            center: *center,
+            radius,
+            steps: steps.max(1),
+            intensity: *intensity,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.steps * self.steps
+    }
+
+    pub fn point_on_light(&self, point: &Vec3, u: usize, v: usize, rng: &mut Rng) -> Vec3 {
+        let (s, t) = sampling::orthonormal_basis(&(point - self.center).normalize());
+
+        let n = self.steps as f32;
+        let (x, y) = sampling::concentric_disk(
+            (u as f32 + rng.next_f32()) / n,
+            (v as f32 + rng.next_f32()) / n,
+        );
+
+        self.center + (s * x + t * y) * self.radius
+    }
+}
+
+impl Light for SphereLight {
+    fn intensity(&self) -> Vec3 {
+        self.intensity
+    }
+
+    fn samples(&self, point: &Vec3, rng: &mut Rng) -> Vec<LightSample> {
+        let normal = (point - self.center).normalize();
+        let area = std::f32::consts::PI * self.radius * self.radius;
+
+        (0..self.steps)
+            .flat_map(|v| (0..self.steps).map(move |u| (u, v)))
+            .map(|(u, v)| LightSample::from_area(point, &self.point_on_light(point, u, v, rng), &normal, area, &self.intensity))
+            .collect()
+    }
+
+    // Hits the same disk that samples are taken from
+    fn hit(&self, point: &Vec3, direction: &Vec3) -> Option<LightSample> {
+        let normal = (point - self.center).normalize();
+        let denom = glm::dot(direction, &normal);
+
+        if denom >= 0.0 {
+            return None;
+        }
+
+        let t = glm::dot(&(self.center - point), &normal) / denom;
+        let position = point + direction * t;
+
+        if t <= 0.0 || glm::distance(&position, &self.center) > self.radius {
+            return None;
+        }
+
+        let area = std::f32::consts::PI * self.radius * self.radius;
+        Some(LightSample::from_area(point, &position, &normal, area, &self.intensity))
+    }
+}
+
+// Phong diffuse + specular from one light sample, without ambient
+pub fn phong(material: &Material, sample: &LightSample, eyev: &Vec3, normalv: &Vec3) -> Vec3 {
+    let effective_color = material.color.component_mul(&sample.intensity);
+    let lightv = sample.direction;
+
+    let light_dot_normal = glm::dot(&lightv, normalv);
+
+    if light_dot_normal < 0.0 {
+        return Vec3::zeros();
+    }
+
+    let diffuse = effective_color * material.diffuse * light_dot_normal;
+
+    let reflectv = glm::reflect_vec(&-lightv, normalv);
+    let reflect_dot_eye = glm::dot(&reflectv, eyev);
+
+    let specular = if reflect_dot_eye <= 0.0 {
+        Vec3::zeros()
+    }
+    else {
+        sample.intensity * material.specular * reflect_dot_eye.powf(material.shininess)
+    };
+
+    diffuse + specular
+}
+
+// Chapter 6 lighting generalised to several samples of a light. `visible`
+// says which of `samples` the point can see, so a partly shadowed point gets a
+// fraction of the light.
+pub fn lighting(
+    material: &Material,
+    light: &dyn Light,
+    samples: &[LightSample],
+    visible: &[bool],
+    eyev: &Vec3,
+    normalv: &Vec3,
+) -> Vec3 {
+    let ambient = material.color.component_mul(&light.intensity()) * material.ambient;
+
+    let sum = samples
+        .iter()
+        .zip(visible.iter())
+        .filter(|(_, &v)| v)
+        .fold(Vec3::zeros(), |sum, (s, _)| sum + phong(material, s, eyev, normalv));
+
+    ambient + sum / samples.len().max(1) as f32
+}
@@ -1,25 +1,300 @@
+mod adaptive;
+mod bezier;
+mod bsdf;
+mod camera;
+mod color;
+mod controls;
+mod disk;
+mod environment;
+mod fisheye;
+mod grid;
+mod heightfield;
+mod image;
 mod hittable;
+mod integrator;
 mod intersection;
+mod light;
+mod material;
+mod medium;
+mod mesh;
+mod motion;
+mod orthographic;
+mod panoramic;
+mod perspective;
+mod pick;
+mod polynomial;
+mod progressive;
+mod quadric;
 mod ray;
+mod render;
+mod rig;
+mod rng;
+mod sampling;
+mod sdf;
+mod sky;
 mod sphere;
+// The book's original tests predate these lints and are kept as written
+#[allow(clippy::needless_borrow, clippy::useless_vec)]
 mod tests;
+mod torus;
+mod triangle;
+mod world;
 
-use minifb::{Key, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
 extern crate nalgebra_glm as glm;
 
+use crate::adaptive::{Adaptive, AdaptiveStats};
+use crate::camera::Camera;
+use crate::fisheye::FisheyeCamera;
+use crate::grid::{DensityGrid, GridVolume};
+use crate::heightfield::Heightfield;
+use crate::orthographic::OrthographicCamera;
+use crate::panoramic::PanoramicCamera;
+use crate::perspective::PerspectiveCamera;
+use crate::controls::OrbitControls;
+use crate::environment::Environment;
+use crate::integrator::{Integrator, PathTracer, Whitted};
+use crate::light::{AreaLight, SphereLight};
+use crate::material::Material;
+use crate::medium::{Fog, Medium, Volume};
+use crate::mesh::{MeshSpec, Subdivision};
+use crate::motion::Motion;
 use crate::sphere::Sphere;
-use crate::ray::Ray;
-use crate::intersection::Intersection;
-use crate::hittable::Hittable;
+use crate::progressive::Progressive;
+use crate::rig::{CubeMapRig, StereoOutput, StereoRig};
+use crate::sampling::Sampler;
+use crate::sdf::{Sdf, SdfObject};
+use crate::sky::Sky;
+use crate::world::World;
 
-const WIDTH: usize = 100;
-const HEIGHT: usize = 100;
+const WIDTH: usize = 400;
+const HEIGHT: usize = 300;
+const TITLE: &str = "Test - ESC to exit";
+const APERTURE: f32 = 0.05;
+const ORTHOGRAPHIC_WIDTH: f32 = 6.0;
+const INTEROCULAR: f32 = 0.065;
+
+// Value following `--name` on the command line
+fn arg(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
+
+// Values following every `--name` on the command line, for flags that can be
+// given more than once
+fn args(name: &str) -> Vec<String> {
+    let args: Vec<String> = std::env::args().collect();
+
+    args.windows(2).filter(|w| w[0] == name).map(|w| w[1].clone()).collect()
+}
+
+// Picks the camera from `--camera perspective|orthographic|fisheye|panorama`
+fn build_camera() -> Box<dyn Camera> {
+    match arg("--camera").as_deref().unwrap_or("perspective") {
+        "orthographic" => Box::new(OrthographicCamera::new(WIDTH, HEIGHT, ORTHOGRAPHIC_WIDTH)),
+        "fisheye" => Box::new(FisheyeCamera::new(WIDTH, HEIGHT, std::f32::consts::PI)),
+        "panorama" => Box::new(PanoramicCamera::new(WIDTH, HEIGHT)),
+        _ => {
+            let mut c = PerspectiveCamera::new(WIDTH, HEIGHT, std::f32::consts::FRAC_PI_3);
+            c.aperture = APERTURE;
+            c.shutter_close = 1.0;
+            Box::new(c)
+        }
+    }
+}
+
+// Picks how rays are shaded from `--integrator whitted|path`
+fn build_integrator() -> Box<dyn Integrator> {
+    match arg("--integrator").as_deref().unwrap_or("whitted") {
+        "path" => Box::new(PathTracer::new()),
+        _ => Box::new(Whitted),
+    }
+}
 
 fn main() -> std::io::Result<()> {
-    let mut buffer: Vec<u32> = vec![0; WIDTH * HEIGHT];
+    let mut world = World::new();
+
+    let mut middle = Sphere::new();
+    middle.name = String::from("middle");
+    middle.material = Material::metallic_roughness(&glm::vec3(1.0, 0.0, 0.0), 0.0, 0.4);
+    world.add(middle);
+
+    let mut right = Sphere::new();
+    right.name = String::from("right");
+    right.transform = glm::translation(&glm::vec3(2.0, 0.5, 1.0)) * glm::scaling(&glm::vec3(0.5, 0.5, 0.5));
+    right.motion = Some(Motion::build(
+        &right.transform,
+        &(glm::translation(&glm::vec3(2.0, 1.0, 1.0)) * glm::scaling(&glm::vec3(0.5, 0.5, 0.5))),
+    ));
+    right.material.color = glm::vec3(0.0, 1.0, 0.0);
+    world.add(right);
+
+    let mut left = Sphere::new();
+    left.name = String::from("left");
+    left.transform = glm::translation(&glm::vec3(-1.5, -0.5, 2.0)) * glm::scaling(&glm::vec3(0.5, 0.5, 0.5));
+    left.material.color = glm::vec3(0.0, 0.0, 1.0);
+    world.add(left);
+
+    let mut floor = Sphere::new();
+    floor.name = String::from("floor");
+    floor.transform = glm::translation(&glm::vec3(0.0, -1001.0, 0.0)) * glm::scaling(&glm::vec3(1000.0, 1000.0, 1000.0));
+    floor.material.color = glm::vec3(0.8, 0.8, 0.8);
+    floor.material.specular = 0.0;
+    world.add(floor);
+
+    // Small glowing ball that only lights its surroundings with `--integrator path`
+    let mut lamp = Sphere::new();
+    lamp.name = String::from("lamp");
+    lamp.transform = glm::translation(&glm::vec3(1.0, -0.75, -1.0)) * glm::scaling(&glm::vec3(0.25, 0.25, 0.25));
+    lamp.material.color = glm::vec3(1.0, 0.9, 0.7);
+    lamp.material.emissive = glm::vec3(4.0, 3.0, 2.0);
+    world.add(lamp);
+
+    // `--sdf k` adds a ray-marched box with a hole bored through it and a ring
+    // melted onto its top, with the seams blended over `k`
+    if let Some(k) = arg("--sdf").and_then(|k| k.parse().ok()) {
+        let shape = Sdf::cuboid(&glm::vec3(0.5, 0.5, 0.5))
+            .smooth_subtraction(Sdf::capsule(&glm::vec3(0.0, 0.0, -1.0), &glm::vec3(0.0, 0.0, 1.0), 0.3), k)
+            .smooth_union(Sdf::torus(0.35, 0.1).translate(&glm::vec3(0.0, 0.55, 0.0)), k);
+
+        let mut sdf = SdfObject::build(shape, &glm::translation(&glm::vec3(-2.5, -0.5, 0.5)));
+        sdf.name = String::from("sdf");
+        sdf.material.color = glm::vec3(0.9, 0.6, 0.2);
+        world.add(sdf);
+    }
+
+    // `--teapot file` adds the Utah teapot, or any other patches in the same
+    // format, cut into `--tessellation n` by n triangles a patch. The data
+    // has z up, so it's turned to stand on the floor.
+    if let Some(path) = arg("--teapot") {
+        let level = arg("--tessellation").and_then(|n| n.parse().ok()).unwrap_or(8);
+        let transform = glm::translation(&glm::vec3(2.5, -1.0, 3.0))
+            * glm::scaling(&glm::vec3(0.4, 0.4, 0.4))
+            * glm::rotation(-std::f32::consts::FRAC_PI_2, &glm::vec3(1.0, 0.0, 0.0));
+
+        for patch in bezier::load_patches(std::path::Path::new(&path))? {
+            for mut t in patch.tessellate(level) {
+                t.name = String::from("teapot");
+                t.transform = transform;
+                t.material.color = glm::vec3(0.9, 0.9, 0.85);
+                world.add(t);
+            }
+        }
+    }
+
+    // Each `--obj path[,scheme[,levels]]` adds a Wavefront OBJ model, in a
+    // row from left to right. The scheme, one of none, loop or catmull-clark,
+    // smooths that model's coarse cage into the surface it stands for.
+    for (i, spec) in args("--obj").iter().enumerate() {
+        let spec = MeshSpec::parse(spec)?;
+        let transform = glm::translation(&glm::vec3(-1.5 + 1.25 * i as f32, -0.5, 3.0))
+            * glm::scaling(&glm::vec3(0.5, 0.5, 0.5));
+
+        for mut t in spec.load()?.to_triangles(spec.scheme != Subdivision::None) {
+            t.name = String::from("mesh");
+            t.transform = transform;
+            t.material.color = glm::vec3(0.5, 0.7, 0.9);
+            world.add(t);
+        }
+    }
+
+    // `--heightfield file` adds terrain behind the scene with its heights
+    // from a grayscale .pgm, .pfm or .hdr image
+    if let Some(path) = arg("--heightfield") {
+        let transform = glm::translation(&glm::vec3(-6.0, -1.0, 6.0)) * glm::scaling(&glm::vec3(12.0, 2.0, 6.0));
+        let mut terrain = Heightfield::load(std::path::Path::new(&path), &transform)?;
+        terrain.name = String::from("terrain");
+        terrain.material.color = glm::vec3(0.45, 0.55, 0.3);
+        world.add(terrain);
+    }
+
+    // Soft key light up and to the left, and a dim fill from the right
+    world.add_light(AreaLight::build(
+        &glm::vec3(-5.0, 4.0, -6.0),
+        &glm::vec3(2.0, 0.0, 0.0),
+        4,
+        &glm::vec3(0.0, 2.0, 0.0),
+        4,
+        &glm::vec3(1.0, 1.0, 1.0),
+    ));
+    world.add_light(SphereLight::build(
+        &glm::vec3(6.0, 1.0, -3.0),
+        0.5,
+        2,
+        &glm::vec3(0.3, 0.3, 0.3),
+    ));
+
+    // `--environment file.hdr|file.pfm` surrounds the scene with an HDR map
+    if let Some(path) = arg("--environment") {
+        world.add_light(Environment::load(std::path::Path::new(&path))?);
+    }
+
+    // `--sky elevation,azimuth,turbidity` adds a daylight sky and its sun,
+    // with the angles in degrees
+    if let Some(sky) = arg("--sky") {
+        let v: Vec<f32> = sky.split(',').filter_map(|v| v.trim().parse().ok()).collect();
+        let get = |i: usize, default: f32| v.get(i).copied().unwrap_or(default);
+        let sky = Sky::build(get(0, 30.0).to_radians(), get(1, 45.0).to_radians(), get(2, 3.0));
+
+        world.add_light(sky.sun_light(1.0));
+        world.add_light(sky);
+    }
+
+    // `--fog density,height` fills the air below `height` with thin grey fog,
+    // for light shafts with `--sky`
+    if let Some(fog) = arg("--fog") {
+        let v: Vec<f32> = fog.split(',').filter_map(|v| v.trim().parse().ok()).collect();
+        let medium = Medium::from_albedo(v.first().copied().unwrap_or(0.05), &glm::vec3(0.9, 0.9, 0.9), 0.6);
+        world.fog = Some(Fog::build(medium, v.get(1).copied().unwrap_or(f32::INFINITY)));
+    }
+
+    // `--smoke density` adds a ball of smoke on the left
+    if let Some(density) = arg("--smoke").and_then(|d| d.parse().ok()) {
+        let mut ball = Sphere::new();
+        ball.transform = glm::translation(&glm::vec3(-2.5, 0.3, 1.0)) * glm::scaling(&glm::vec3(0.8, 0.8, 0.8));
+        world.add_volume(Volume::build(ball, Medium::from_albedo(density, &glm::vec3(0.8, 0.8, 0.8), 0.3)));
+    }
+
+    // `--grid file.vol` hangs the densities from a raw voxel file in a box
+    // behind the middle sphere, like a cloud
+    if let Some(path) = arg("--grid") {
+        let grid = DensityGrid::load(std::path::Path::new(&path))?;
+        let transform = glm::translation(&glm::vec3(-1.0, 0.2, 1.5)) * glm::scaling(&glm::vec3(2.0, 1.5, 2.0));
+        world.add_grid(GridVolume::build(grid, &transform, 4.0, &glm::vec3(0.95, 0.95, 0.95), 0.5)?);
+    }
+
+    let from = glm::vec3(0.0, 0.0, -5.0);
+    let to = glm::vec3(0.0, 0.0, 0.0);
+    let up = glm::vec3(0.0, 1.0, 0.0);
+    let threads = render::default_threads();
+    let integrator = build_integrator();
+
+    // `--rig stereo|anaglyph|cubemap` renders once to `--output` and exits
+    if let Some(rig) = arg("--rig") {
+        let sampler = Sampler::new();
+        let trace = |r: &ray::Ray| integrator.li(&world, r);
+
+        let image = match rig.as_str() {
+            "cubemap" => CubeMapRig::build(HEIGHT / 3).render(&from, &sampler, threads, &trace),
+            "anaglyph" => StereoRig::build(WIDTH, HEIGHT, std::f32::consts::FRAC_PI_3, INTEROCULAR, StereoOutput::Anaglyph)
+                .render(&from, &to, &up, &sampler, threads, &trace),
+            _ => StereoRig::build(WIDTH, HEIGHT, std::f32::consts::FRAC_PI_3, INTEROCULAR, StereoOutput::SideBySide)
+                .render(&from, &to, &up, &sampler, threads, &trace),
+        };
+
+        let output = arg("--output").unwrap_or(String::from("rig.ppm"));
+        image.save_ppm(std::path::Path::new(&output))?;
+        println!("wrote {}x{} {} image to {}", image.width, image.height, rig, output);
+
+        return Ok(());
+    }
 
     let mut window = Window::new(
-        "Test - ESC to exit",
+        TITLE,
         WIDTH,
         HEIGHT,
         WindowOptions::default(),
@@ -29,37 +304,63 @@ fn main() -> std::io::Result<()> {
     // Limit to max ~60 fps update rate
     window.set_target_fps(60);
 
-    let s = Sphere::new();
-    let mut r = Ray::build(
-        &glm::vec3(0.0, 0.0, -5.0),
-        &(glm::vec3(0.0, 0.0, 1.0) - glm::vec3(0.0, 0.0, -5.0)).normalize(),
-    );
+    let mut controls = OrbitControls::build(&from, &to, &up);
 
-    let wall_z = 10.0;
-    let wall_size: f32 = 7.0;
-    let pixel_h_size: f32 = wall_size / WIDTH as f32;
-    let pixel_v_size: f32 = wall_size / HEIGHT as f32;
-    let half: f32 = wall_size / 2.0;
+    // Keep the orbit target in focus
+    let mut camera = build_camera();
+    camera.focus(controls.distance);
+    camera.set_transform(&controls.view_transform());
 
-    while window.is_open() && !window.is_key_down(Key::Escape) {
-        for i in 0..WIDTH {
-            let world_y = half - pixel_h_size * i as f32;
+    let mut preview = Progressive::build(WIDTH, HEIGHT, threads, Sampler::new());
 
-            for j in 0..HEIGHT  {
-                let world_x = -half + pixel_v_size * j as f32;
+    // Adaptive render of the current view, shown until the camera moves
+    let mut adaptive_frame: Option<Vec<u32>> = None;
 
-                r.direction = (glm::vec3(world_x, world_y, wall_z) - r.origin).normalize();
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        if controls.update(&window) {
+            camera.focus(controls.distance);
+            camera.set_transform(&controls.view_transform());
+            preview.restart();
+            adaptive_frame = None;
+        }
 
-                if Intersection::hit(&s.intersect(&r)).is_some() {
-                    buffer[i + j * WIDTH] = 0x00FF0000;
-                }
-                else {
-                    buffer[i + j * WIDTH] = 0;
-                }
+        if let Some((x, y)) = controls.take_click() {
+            match pick::pick(&world, camera.as_ref(), x, y) {
+                Some(p) => print!("{}", p),
+                None => println!("pixel ({}, {}): nothing", x, y),
             }
         }
 
-        window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
+        // F renders the view with adaptive supersampling
+        if window.is_key_pressed(Key::F, KeyRepeat::No) {
+            let adaptive = Adaptive::new();
+            let stats = AdaptiveStats::new();
+            let sample = |x: f32, y: f32| integrator.sample(&world, &camera.ray_for_pixel(x, y));
+
+            let frame = render::render(WIDTH, HEIGHT, threads, |x, y| {
+                color::to_u32(&adaptive.pixel(x, y, &sample, &stats))
+            });
+
+            println!("adaptive: {}", stats);
+            window.set_title(&format!("{} - adaptive", TITLE));
+            adaptive_frame = Some(frame);
+        }
+
+        if let Some(frame) = &adaptive_frame {
+            window.update_with_buffer(frame, WIDTH, HEIGHT).unwrap();
+            continue;
+        }
+
+        let shade = |x: f32, y: f32| integrator.li(&world, &camera.ray_for_pixel(x, y));
+
+        // One pass per frame keeps the window responsive while refining
+        if preview.step(shade) {
+            window.set_title(&format!("{} - {}", TITLE, preview.progress()));
+            window.update_with_buffer(preview.buffer(), WIDTH, HEIGHT).unwrap();
+        }
+        else {
+            window.update();
+        }
     }
 
     Ok(())
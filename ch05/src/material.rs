@@ -0,0 +1,62 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Vec3;
+
+use crate::bsdf::Bsdf;
+
+// Phong material from chapter 6
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+    pub color: Vec3,
+    pub ambient: f32,
+    pub diffuse: f32,
+    pub specular: f32,
+    pub shininess: f32,
+    // Light given off by the surface itself
+    pub emissive: Vec3,
+    // How the path tracer scatters light off the surface. The Phong values
+    // above still drive the Whitted renderer.
+    pub bsdf: Option<Bsdf>,
+}
+
+#[allow(dead_code)]
+impl Material {
+    pub fn new() -> Material {
+        Material {
+            color: glm::vec3(1.0, 1.0, 1.0),
+            ambient: 0.1,
+            diffuse: 0.9,
+            specular: 0.9,
+            shininess: 200.0,
+            emissive: Vec3::zeros(),
+            bsdf: None,
+        }
+    }
+
+    pub fn build(color: &Vec3, ambient: f32, diffuse: f32, specular: f32, shininess: f32) -> Material {
+        Material {
+            color: *color,
+            ambient,
+            diffuse,
+            specular,
+            shininess,
+            emissive: Vec3::zeros(),
+            bsdf: None,
+        }
+    }
+
+    // glTF-style material, with the base color doubling as the Phong color
+    pub fn metallic_roughness(base_color: &Vec3, metallic: f32, roughness: f32) -> Material {
+        let mut m = Material::new();
+        m.color = *base_color;
+        m.bsdf = Some(Bsdf::metallic_roughness(base_color, metallic, roughness));
+        m
+    }
+
+    // The BSDF set on the material, or a Lambertian one made from its Phong
+    // color and diffuse
+    pub fn effective_bsdf(&self) -> Bsdf {
+        self.bsdf.unwrap_or(Bsdf::lambertian(&(self.color * self.diffuse)))
+    }
+}
@@ -0,0 +1,264 @@
+extern crate nalgebra_glm as glm;
+
+use std::f32::consts::PI;
+
+use glm::Vec3;
+
+use crate::hittable::Hittable;
+use crate::ray::Ray;
+use crate::rng::Rng;
+use crate::sampling;
+
+// Henyey and Greenstein's phase function: how much of the light a medium
+// scatters goes off at each angle. `g` is the mean cosine of the bend, from
+// -1 (all thrown back) through 0 (every direction alike) to 1 (straight on).
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HenyeyGreenstein {
+    pub g: f32,
+}
+
+#[allow(dead_code)]
+impl HenyeyGreenstein {
+    pub fn build(g: f32) -> HenyeyGreenstein {
+        HenyeyGreenstein { g: g.clamp(-0.99, 0.99) }
+    }
+
+    // Density of light arriving from `wi` leaving toward `wo`, both pointing
+    // away from the scattering point. Integrates to one over the sphere.
+    pub fn evaluate(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        let cos = -glm::dot(wo, wi);
+        let denom = 1.0 + self.g * self.g - 2.0 * self.g * cos;
+
+        (1.0 - self.g * self.g) / (4.0 * PI * denom * denom.max(0.0).sqrt())
+    }
+
+    // Direction light came from, picked in proportion to evaluate(), which is
+    // also its pdf
+    pub fn sample(&self, wo: &Vec3, u: f32, v: f32) -> Vec3 {
+        let g = self.g;
+        let cos = if g.abs() < 0.001 {
+            1.0 - 2.0 * u
+        }
+        else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let phi = 2.0 * PI * v;
+        let (s, t) = sampling::orthonormal_basis(wo);
+
+        // Light travelling along -wi is bent by the angle onto wo
+        -(wo * cos + (s * phi.cos() + t * phi.sin()) * sin)
+    }
+}
+
+// Homogeneous participating medium, like smoke or fog. Coefficients are per
+// unit of distance and per color channel.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Medium {
+    pub absorption: Vec3,
+    pub scattering: Vec3,
+    pub phase: HenyeyGreenstein,
+}
+
+#[allow(dead_code)]
+impl Medium {
+    pub fn build(absorption: &Vec3, scattering: &Vec3, g: f32) -> Medium {
+        Medium {
+            absorption: *absorption,
+            scattering: *scattering,
+            phase: HenyeyGreenstein::build(g),
+        }
+    }
+
+    // Medium of the given density scattering `albedo` of the light it stops
+    pub fn from_albedo(density: f32, albedo: &Vec3, g: f32) -> Medium {
+        let scattering = albedo * density;
+        Medium::build(&(glm::vec3(density, density, density) - scattering), &scattering, g)
+    }
+
+    pub fn extinction(&self) -> Vec3 {
+        self.absorption + self.scattering
+    }
+
+    // Fraction of light getting through `distance` of the medium
+    pub fn transmittance(&self, distance: f32) -> Vec3 {
+        optical_depth(&self.extinction(), distance).map(|d| (-d).exp())
+    }
+}
+
+// Medium filling the inside of a closed object, which only bounds it and is
+// never drawn itself
+#[allow(dead_code)]
+pub struct Volume {
+    pub boundary: Box<dyn Hittable>,
+    pub medium: Medium,
+}
+
+#[allow(dead_code)]
+impl Volume {
+    pub fn build(boundary: impl Hittable + 'static, medium: Medium) -> Volume {
+        Volume {
+            boundary: Box::new(boundary),
+            medium,
+        }
+    }
+
+    // Stretches of the ray between 0 and `t_max` inside the boundary. Ray
+    // crossings pair up as in and out, so the boundary must be closed.
+    pub fn spans(&self, r: &Ray, t_max: f32) -> Vec<Span> {
+        let mut ts: Vec<f32> = self.boundary.intersect(r).iter().map(|i| i.t()).collect();
+        ts.sort_by(|a, b| a.total_cmp(b));
+
+        ts.chunks_exact(2)
+            .map(|pair| (pair[0].max(0.0), pair[1].min(t_max)))
+            .filter(|(start, end)| start < end)
+            .map(|(start, end)| Span {
+                start,
+                end,
+                medium: self.medium,
+            })
+            .collect()
+    }
+}
+
+// Medium filling everything below `height`, thinning out whatever is seen
+// through it exponentially with distance
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fog {
+    pub medium: Medium,
+    pub height: f32,
+}
+
+#[allow(dead_code)]
+impl Fog {
+    // Fog everywhere
+    pub fn new(medium: Medium) -> Fog {
+        Fog {
+            medium,
+            height: f32::INFINITY,
+        }
+    }
+
+    pub fn build(medium: Medium, height: f32) -> Fog {
+        Fog { medium, height }
+    }
+
+    pub fn spans(&self, r: &Ray, t_max: f32) -> Vec<Span> {
+        let (start, end) = if r.direction.y == 0.0 {
+            if r.origin.y < self.height { (0.0, t_max) } else { (0.0, 0.0) }
+        }
+        else {
+            let t = (self.height - r.origin.y) / r.direction.y;
+            if r.direction.y > 0.0 { (0.0, t.min(t_max)) } else { (t.max(0.0), t_max) }
+        };
+
+        if start < end {
+            vec![Span {
+                start,
+                end,
+                medium: self.medium,
+            }]
+        }
+        else {
+            vec![]
+        }
+    }
+}
+
+// Part of a ray, in units of its length, that runs through a medium
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    pub start: f32,
+    pub end: f32,
+    pub medium: Medium,
+}
+
+// What happened to a ray crossing some media
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interaction {
+    // Scattered at `t` by `medium`. The path's throughput gets multiplied by
+    // `weight`, which includes the scattering coefficient.
+    Scatter { t: f32, weight: Vec3, medium: Medium },
+    // Made it through; `weight` is transmittance over its probability
+    Pass { weight: Vec3 },
+}
+
+// Fraction of light getting along a ray through `spans`, which may overlap
+pub fn transmittance(spans: &[Span]) -> Vec3 {
+    spans
+        .iter()
+        .fold(Vec3::zeros(), |depth, s| depth + optical_depth(&s.medium.extinction(), s.end - s.start))
+        .map(|d| (-d).exp())
+}
+
+// Picks how far a ray gets through `spans` before scattering, for a color
+// channel picked at random, with the distance's pdf averaged over all three
+// so that colored media don't make fireflies. Where media overlap their
+// extinction adds up, and the one that scatters is picked in proportion to
+// its scattering.
+pub fn sample_distance(spans: &[Span], rng: &mut Rng) -> Interaction {
+    let channel = ((rng.next_f32() * 3.0) as usize).min(2);
+    let target = -(1.0 - rng.next_f32()).ln();
+
+    // Pieces of the ray along which the same media overlap
+    let mut cuts: Vec<f32> = spans.iter().flat_map(|s| [s.start, s.end]).collect();
+    cuts.sort_by(|a, b| a.total_cmp(b));
+
+    let mut depth = Vec3::zeros();
+
+    for piece in cuts.windows(2) {
+        let (start, end) = (piece[0], piece[1]);
+        let inside: Vec<&Span> = spans.iter().filter(|s| s.start <= start && s.end >= end).collect();
+        let extinction = inside.iter().fold(Vec3::zeros(), |sum, s| sum + s.medium.extinction());
+        let piece_depth = optical_depth(&extinction, end - start);
+
+        if extinction[channel] > 0.0 && depth[channel] + piece_depth[channel] >= target {
+            let t = start + (target - depth[channel]) / extinction[channel];
+            let tr = (depth + extinction * (t - start)).map(|d| (-d).exp());
+            let pdf = extinction.component_mul(&tr).mean();
+
+            let total: f32 = inside.iter().map(|s| s.medium.scattering.mean()).sum();
+            let mut pick = rng.next_f32() * total;
+            let medium = inside
+                .iter()
+                .find(|s| {
+                    pick -= s.medium.scattering.mean();
+                    pick < 0.0
+                })
+                .unwrap_or(&inside[inside.len() - 1])
+                .medium;
+
+            let chance = if total > 0.0 { medium.scattering.mean() / total } else { 1.0 };
+            let weight = if total > 0.0 && pdf > 0.0 {
+                medium.scattering.component_mul(&tr) / (pdf * chance)
+            }
+            else {
+                Vec3::zeros()
+            };
+
+            return Interaction::Scatter { t, weight, medium };
+        }
+
+        depth += piece_depth;
+    }
+
+    let tr = depth.map(|d| (-d).exp());
+    let chance = tr.mean();
+
+    Interaction::Pass {
+        weight: if chance > 0.0 { tr / chance } else { Vec3::zeros() },
+    }
+}
+
+// Coefficient times distance, taking a clear channel as clear even over an
+// endless distance
+fn optical_depth(coefficient: &Vec3, distance: f32) -> Vec3 {
+    coefficient.map(|c| if c == 0.0 { 0.0 } else { c * distance })
+}
@@ -0,0 +1,420 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use glm::Vec3;
+
+use crate::image::invalid;
+use crate::triangle::Triangle;
+
+// How a mesh is smoothed before it's cut into triangles
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Subdivision {
+    None,
+    // Loop's scheme, for triangle meshes; other polygons are split first
+    Loop,
+    // Catmull and Clark's scheme, which turns any polygons into quads
+    CatmullClark,
+}
+
+#[allow(dead_code)]
+impl Subdivision {
+    pub fn parse(name: &str) -> Option<Subdivision> {
+        match name {
+            "none" => Some(Subdivision::None),
+            "loop" => Some(Subdivision::Loop),
+            "catmull-clark" => Some(Subdivision::CatmullClark),
+            _ => None,
+        }
+    }
+}
+
+// A model to load and how to smooth it, written `path[,scheme[,levels]]`.
+// Leaving out the scheme loads it as it is; leaving out the levels smooths it
+// twice.
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshSpec {
+    pub path: PathBuf,
+    pub scheme: Subdivision,
+    pub levels: usize,
+}
+
+#[allow(dead_code)]
+impl MeshSpec {
+    pub fn parse(spec: &str) -> io::Result<MeshSpec> {
+        let mut parts = spec.split(',');
+        let path = PathBuf::from(parts.next().unwrap_or_default());
+
+        let scheme = match parts.next() {
+            Some(name) => Subdivision::parse(name).ok_or_else(|| invalid("unknown subdivision scheme"))?,
+            None => Subdivision::None,
+        };
+
+        let levels = match parts.next() {
+            Some(n) => n.parse().map_err(|_| invalid("bad subdivision level"))?,
+            None if scheme == Subdivision::None => 0,
+            None => 2,
+        };
+
+        if parts.next().is_some() {
+            return Err(invalid("mesh should be path[,scheme[,levels]]"));
+        }
+
+        Ok(MeshSpec { path, scheme, levels })
+    }
+
+    // The model, smoothed as asked
+    pub fn load(&self) -> io::Result<Mesh> {
+        Ok(Mesh::load_obj(&self.path)?.subdivide(self.scheme, self.levels))
+    }
+}
+
+// Polygon mesh: shared vertices and faces listing them counterclockwise seen
+// from outside, as in an OBJ file
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mesh {
+    pub positions: Vec<Vec3>,
+    pub faces: Vec<Vec<usize>>,
+}
+
+#[allow(dead_code)]
+impl Mesh {
+    pub fn new() -> Mesh {
+        Mesh {
+            positions: vec![],
+            faces: vec![],
+        }
+    }
+
+    pub fn build(positions: Vec<Vec3>, faces: Vec<Vec<usize>>) -> Mesh {
+        Mesh { positions, faces }
+    }
+
+    pub fn load_obj(path: &Path) -> io::Result<Mesh> {
+        Mesh::parse_obj(&fs::read_to_string(path)?)
+    }
+
+    // Vertices and faces of a Wavefront OBJ file. Face corners may be written
+    // v, v/vt, v//vn or v/vt/vn, counting from 1 or back from the latest
+    // vertex if negative. Everything else in the file is skipped.
+    pub fn parse_obj(text: &str) -> io::Result<Mesh> {
+        let mut mesh = Mesh::new();
+
+        for line in text.lines() {
+            let mut tokens = line.split_whitespace();
+
+            match tokens.next() {
+                Some("v") => {
+                    let xyz: Vec<f32> = tokens
+                        .take(3)
+                        .map(|t| t.parse().map_err(|_| invalid("bad number in OBJ vertex")))
+                        .collect::<io::Result<Vec<f32>>>()?;
+
+                    if xyz.len() < 3 {
+                        return Err(invalid("OBJ vertex should have three coordinates"));
+                    }
+
+                    mesh.positions.push(glm::vec3(xyz[0], xyz[1], xyz[2]));
+                }
+                Some("f") => {
+                    let count = mesh.positions.len() as i64;
+                    let face = tokens
+                        .map(|t| {
+                            let i: i64 = t
+                                .split('/')
+                                .next()
+                                .and_then(|i| i.parse().ok())
+                                .ok_or_else(|| invalid("bad index in OBJ face"))?;
+                            let index = if i < 0 { count + i } else { i - 1 };
+
+                            if index < 0 || index >= count {
+                                return Err(invalid("OBJ face index out of range"));
+                            }
+
+                            Ok(index as usize)
+                        })
+                        .collect::<io::Result<Vec<usize>>>()?;
+
+                    if face.len() < 3 {
+                        return Err(invalid("OBJ face needs at least three corners"));
+                    }
+
+                    // Exporters sometimes repeat a corner for a collapsed
+                    // face; the repeats add nothing, and a face left with
+                    // under three corners has no area at all
+                    let mut corners: Vec<usize> = vec![];
+                    for i in face {
+                        if !corners.contains(&i) {
+                            corners.push(i);
+                        }
+                    }
+
+                    if corners.len() >= 3 {
+                        mesh.faces.push(corners);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(mesh)
+    }
+
+    // Every face split into a fan of triangles
+    pub fn triangulated(&self) -> Mesh {
+        let faces = self
+            .faces
+            .iter()
+            .flat_map(|f| (1..f.len() - 1).map(move |i| vec![f[0], f[i], f[i + 1]]))
+            .collect();
+
+        Mesh::build(self.positions.clone(), faces)
+    }
+
+    pub fn subdivide(&self, scheme: Subdivision, levels: usize) -> Mesh {
+        (0..levels).fold(self.clone(), |mesh, _| match scheme {
+            Subdivision::None => mesh,
+            Subdivision::Loop => mesh.loop_step(),
+            Subdivision::CatmullClark => mesh.catmull_clark_step(),
+        })
+    }
+
+    // One round of Loop subdivision: every triangle becomes four, new points
+    // go on the edges and old ones move toward their neighbors. Edges with
+    // only one face are a boundary, which is smoothed as a curve of its own.
+    pub fn loop_step(&self) -> Mesh {
+        let mesh = self.triangulated();
+        let edges = mesh.edges();
+        let around = mesh.neighbors(&edges);
+        let faces_around = mesh.vertex_faces();
+
+        let mut positions: Vec<Vec3> = (0..mesh.positions.len())
+            .map(|v| {
+                let p = mesh.positions[v];
+
+                match mesh.boundary_vertex(v, &around[v], &faces_around[v]) {
+                    Some(moved) => moved,
+                    None => {
+                        let n = around[v].len() as f32;
+                        let beta = if around[v].len() == 3 { 3.0 / 16.0 } else { 3.0 / (8.0 * n) };
+                        let sum = around[v].iter().fold(Vec3::zeros(), |sum, &(o, _)| sum + mesh.positions[o]);
+
+                        p * (1.0 - n * beta) + sum * beta
+                    }
+                }
+            })
+            .collect();
+        let mut edge_index = HashMap::new();
+
+        for (&(a, b), faces) in sorted(&edges) {
+            let (pa, pb) = (mesh.positions[a], mesh.positions[b]);
+            // A degenerate face like (a, b, b) can show up twice on its own
+            // edge with no far corner, so that's treated as a boundary too
+            let opposite = |f: usize| mesh.faces[f].iter().copied().find(|&v| v != a && v != b);
+            let point = match faces.as_slice() {
+                [f, g] => match (opposite(*f), opposite(*g)) {
+                    (Some(c), Some(d)) => (pa + pb) * 0.375 + (mesh.positions[c] + mesh.positions[d]) * 0.125,
+                    _ => (pa + pb) * 0.5,
+                },
+                _ => (pa + pb) * 0.5,
+            };
+
+            edge_index.insert((a, b), positions.len());
+            positions.push(point);
+        }
+
+        let mid = |a: usize, b: usize| edge_index[&edge_key(a, b)];
+        let faces = mesh
+            .faces
+            .iter()
+            .flat_map(|f| {
+                let (a, b, c) = (f[0], f[1], f[2]);
+                let (ab, bc, ca) = (mid(a, b), mid(b, c), mid(c, a));
+                [vec![a, ab, ca], vec![b, bc, ab], vec![c, ca, bc], vec![ab, bc, ca]]
+            })
+            .collect();
+
+        Mesh::build(positions, faces)
+    }
+
+    // One round of Catmull-Clark subdivision: a point in the middle of every
+    // face and on every edge, old points moved, and each n-sided face split
+    // into n quads. Boundaries are smoothed as curves, like Loop's.
+    pub fn catmull_clark_step(&self) -> Mesh {
+        let edges = self.edges();
+        let around = self.neighbors(&edges);
+        let faces_around = self.vertex_faces();
+
+        let face_points: Vec<Vec3> = self
+            .faces
+            .iter()
+            .map(|f| f.iter().fold(Vec3::zeros(), |sum, &v| sum + self.positions[v]) / f.len() as f32)
+            .collect();
+
+        let mut positions: Vec<Vec3> = (0..self.positions.len())
+            .map(|v| {
+                if let Some(moved) = self.boundary_vertex(v, &around[v], &faces_around[v]) {
+                    return moved;
+                }
+
+                let valence = around[v].len() as f32;
+                let q = faces_around[v].iter().fold(Vec3::zeros(), |sum, &f| sum + face_points[f])
+                    / faces_around[v].len() as f32;
+                let r = around[v]
+                    .iter()
+                    .fold(Vec3::zeros(), |sum, &(o, _)| sum + (self.positions[v] + self.positions[o]) * 0.5)
+                    / valence;
+
+                (q + r * 2.0 + self.positions[v] * (valence - 3.0)) / valence
+            })
+            .collect();
+
+        let mut edge_index = HashMap::new();
+
+        for (&(a, b), faces) in sorted(&edges) {
+            let (pa, pb) = (self.positions[a], self.positions[b]);
+            let point = if faces.len() == 2 {
+                (pa + pb + face_points[faces[0]] + face_points[faces[1]]) * 0.25
+            }
+            else {
+                (pa + pb) * 0.5
+            };
+
+            edge_index.insert((a, b), positions.len());
+            positions.push(point);
+        }
+
+        let first_face_point = positions.len();
+        positions.extend(face_points);
+
+        let mid = |a: usize, b: usize| edge_index[&edge_key(a, b)];
+        let faces = self
+            .faces
+            .iter()
+            .enumerate()
+            .flat_map(|(i, f)| {
+                let k = f.len();
+                let mid = &mid;
+                (0..k).map(move |j| {
+                    let (prev, v, next) = (f[(j + k - 1) % k], f[j], f[(j + 1) % k]);
+                    vec![v, mid(v, next), first_face_point + i, mid(prev, v)]
+                })
+            })
+            .collect();
+
+        Mesh::build(positions, faces)
+    }
+
+    // Normal at each vertex, averaged from the faces around it weighted by
+    // their area. A polygon counts once however it's later cut up.
+    pub fn vertex_normals(&self) -> Vec<Vec3> {
+        let mut normals = vec![Vec3::zeros(); self.positions.len()];
+
+        for f in self.faces.iter() {
+            let a = self.positions[f[0]];
+            let n = (1..f.len() - 1).fold(Vec3::zeros(), |sum, i| {
+                sum + glm::cross(&(self.positions[f[i]] - a), &(self.positions[f[i + 1]] - a))
+            });
+
+            for &v in f.iter() {
+                normals[v] += n;
+            }
+        }
+
+        normals.iter().map(|n| if n.norm() > 0.0 { n.normalize() } else { *n }).collect()
+    }
+
+    // The mesh as triangles, smooth shaded from the vertex normals or flat.
+    // Triangles are wound the book's way round, clockwise from outside.
+    pub fn to_triangles(&self, smooth: bool) -> Vec<Triangle> {
+        let normals = self.vertex_normals();
+
+        self.triangulated()
+            .faces
+            .iter()
+            .map(|f| {
+                let (a, b, c) = (f[0], f[2], f[1]);
+                let p = |v: usize| &self.positions[v];
+
+                if smooth {
+                    Triangle::smooth(p(a), p(b), p(c), &normals[a], &normals[b], &normals[c])
+                }
+                else {
+                    Triangle::build(p(a), p(b), p(c))
+                }
+            })
+            .filter(|t| !t.is_degenerate())
+            .collect()
+    }
+
+    // The faces on each edge, keyed by the edge's vertices lowest first
+    fn edges(&self) -> HashMap<(usize, usize), Vec<usize>> {
+        let mut edges: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+
+        for (i, f) in self.faces.iter().enumerate() {
+            for j in 0..f.len() {
+                edges.entry(edge_key(f[j], f[(j + 1) % f.len()])).or_default().push(i);
+            }
+        }
+
+        edges
+    }
+
+    // The vertices joined to each vertex by an edge, and whether that edge is
+    // on the boundary, with a face on one side only
+    fn neighbors(&self, edges: &HashMap<(usize, usize), Vec<usize>>) -> Vec<Vec<(usize, bool)>> {
+        let mut around = vec![vec![]; self.positions.len()];
+
+        for (&(a, b), faces) in sorted(edges) {
+            around[a].push((b, faces.len() != 2));
+            around[b].push((a, faces.len() != 2));
+        }
+
+        around
+    }
+
+    // The faces each vertex is a corner of
+    fn vertex_faces(&self) -> Vec<Vec<usize>> {
+        let mut faces = vec![vec![]; self.positions.len()];
+
+        for (i, f) in self.faces.iter().enumerate() {
+            for &v in f.iter() {
+                faces[v].push(i);
+            }
+        }
+
+        faces
+    }
+
+    // Where an old vertex on the boundary moves to, smoothed along the
+    // boundary only so open edges don't shrink into the mesh. Corners of a
+    // single face, and vertices where the boundary meets itself, stay put.
+    // None for vertices inside, which each scheme moves its own way.
+    fn boundary_vertex(&self, v: usize, around: &[(usize, bool)], faces: &[usize]) -> Option<Vec3> {
+        let p = self.positions[v];
+        let boundary: Vec<usize> = around.iter().filter(|(_, b)| *b).map(|(o, _)| *o).collect();
+
+        match boundary.len() {
+            0 if !around.is_empty() => None,
+            2 if faces.len() > 1 => Some(p * 0.75 + (self.positions[boundary[0]] + self.positions[boundary[1]]) * 0.125),
+            _ => Some(p),
+        }
+    }
+}
+
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+// Edges in a fixed order, so new vertices are numbered the same every run
+fn sorted(edges: &HashMap<(usize, usize), Vec<usize>>) -> Vec<(&(usize, usize), &Vec<usize>)> {
+    let mut list: Vec<_> = edges.iter().collect();
+    list.sort_by_key(|(k, _)| **k);
+    list
+}
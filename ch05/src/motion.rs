@@ -0,0 +1,83 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Mat4;
+use glm::Quat;
+use glm::Vec3;
+
+// A transform that changes while the shutter is open. `open` applies at time 0
+// and `close` at time 1. In between, translation and scale are interpolated
+// linearly and rotation spherically, so a spinning object doesn't shrink on
+// the way round. Shear can't be represented and is lost.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Motion {
+    pub open: Mat4,
+    pub close: Mat4,
+    translation: [Vec3; 2],
+    rotation: [Quat; 2],
+    scale: [Vec3; 2],
+}
+
+#[allow(dead_code)]
+impl Motion {
+    pub fn build(open: &Mat4, close: &Mat4) -> Motion {
+        let (t0, r0, s0) = decompose(open);
+        let (t1, mut r1, s1) = decompose(close);
+
+        // q and -q are the same rotation. Pick the one that takes the short way.
+        if glm::quat_dot(&r0, &r1) < 0.0 {
+            r1 = -r1;
+        }
+
+        Motion {
+            open: *open,
+            close: *close,
+            translation: [t0, t1],
+            rotation: [r0, r1],
+            scale: [s0, s1],
+        }
+    }
+
+    pub fn at(&self, time: f32) -> Mat4 {
+        if time <= 0.0 {
+            return self.open;
+        }
+
+        if time >= 1.0 {
+            return self.close;
+        }
+
+        let t = glm::lerp(&self.translation[0], &self.translation[1], time);
+        let r = glm::quat_slerp(&self.rotation[0], &self.rotation[1], time);
+        let s = glm::lerp(&self.scale[0], &self.scale[1], time);
+
+        glm::translation(&t) * glm::quat_to_mat4(&r) * glm::scaling(&s)
+    }
+}
+
+// Splits an affine transform into translation, rotation and scale
+fn decompose(m: &Mat4) -> (Vec3, Quat, Vec3) {
+    let translation = glm::vec3(m[(0, 3)], m[(1, 3)], m[(2, 3)]);
+
+    let mut columns = [0, 1, 2].map(|c| glm::vec3(m[(0, c)], m[(1, c)], m[(2, c)]));
+    let mut scale = glm::vec3(columns[0].norm(), columns[1].norm(), columns[2].norm());
+
+    // A mirror shows up as a negative determinant. Put it in the scale.
+    if glm::dot(&columns[0], &glm::cross(&columns[1], &columns[2])) < 0.0 {
+        scale.x = -scale.x;
+    }
+
+    for c in 0..3 {
+        if scale[c] != 0.0 {
+            columns[c] /= scale[c];
+        }
+    }
+
+    let rotation = glm::mat3(
+        columns[0].x, columns[1].x, columns[2].x,
+        columns[0].y, columns[1].y, columns[2].y,
+        columns[0].z, columns[1].z, columns[2].z,
+    );
+
+    (translation, glm::mat3_to_quat(&rotation), scale)
+}
@@ -0,0 +1,68 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Mat4;
+
+use crate::camera::{self, Camera};
+use crate::ray::Ray;
+
+// Parallel projection for technical drawings. Every ray points down -z and
+// they start on a `width` units wide rectangle through the camera's origin, so
+// sizes don't shrink with distance.
+#[allow(dead_code)]
+pub struct OrthographicCamera {
+    hsize: usize,
+    vsize: usize,
+    width: f32,
+    transform: Mat4,
+    inverse: Mat4,
+    pixel_size: f32,
+}
+
+#[allow(dead_code)]
+impl OrthographicCamera {
+    pub fn new(hsize: usize, vsize: usize, width: f32) -> OrthographicCamera {
+        OrthographicCamera {
+            hsize,
+            vsize,
+            width,
+            transform: Mat4::identity(),
+            inverse: Mat4::identity(),
+            pixel_size: width / hsize as f32,
+        }
+    }
+
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    pub fn pixel_size(&self) -> f32 {
+        self.pixel_size
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn hsize(&self) -> usize {
+        self.hsize
+    }
+
+    fn vsize(&self) -> usize {
+        self.vsize
+    }
+
+    fn transform(&self) -> &Mat4 {
+        &self.transform
+    }
+
+    fn set_transform(&mut self, t: &Mat4) {
+        self.transform = *t;
+        self.inverse = glm::inverse(t);
+    }
+
+    fn ray_for_pixel(&self, px: f32, py: f32) -> Ray {
+        // Same orientation as the perspective camera: +x is to the left
+        let x = (self.hsize as f32 / 2.0 - px) * self.pixel_size;
+        let y = (self.vsize as f32 / 2.0 - py) * self.pixel_size;
+
+        camera::to_world(&self.inverse, &glm::vec3(x, y, 0.0), &glm::vec3(0.0, 0.0, -1.0))
+    }
+}
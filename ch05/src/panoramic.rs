@@ -0,0 +1,64 @@
+extern crate nalgebra_glm as glm;
+
+use std::f32::consts::{PI, TAU};
+
+use glm::Mat4;
+
+use crate::camera::{self, Camera};
+use crate::ray::Ray;
+
+// 360 x 180 degree equirectangular panorama, e.g. for environment probes.
+// Longitude runs across the image with the view direction in the middle and
+// latitude runs from straight up at the top row to straight down at the bottom.
+#[allow(dead_code)]
+pub struct PanoramicCamera {
+    hsize: usize,
+    vsize: usize,
+    transform: Mat4,
+    inverse: Mat4,
+}
+
+#[allow(dead_code)]
+impl PanoramicCamera {
+    pub fn new(hsize: usize, vsize: usize) -> PanoramicCamera {
+        PanoramicCamera {
+            hsize,
+            vsize,
+            transform: Mat4::identity(),
+            inverse: Mat4::identity(),
+        }
+    }
+}
+
+impl Camera for PanoramicCamera {
+    fn hsize(&self) -> usize {
+        self.hsize
+    }
+
+    fn vsize(&self) -> usize {
+        self.vsize
+    }
+
+    fn transform(&self) -> &Mat4 {
+        &self.transform
+    }
+
+    fn set_transform(&mut self, t: &Mat4) {
+        self.transform = *t;
+        self.inverse = glm::inverse(t);
+    }
+
+    fn ray_for_pixel(&self, px: f32, py: f32) -> Ray {
+        let longitude = (px / self.hsize as f32 - 0.5) * TAU;
+        let latitude = (0.5 - py / self.vsize as f32) * PI;
+
+        // Same orientation as the perspective camera: +x is to the left
+        let direction = glm::vec3(
+            -latitude.cos() * longitude.sin(),
+            latitude.sin(),
+            -latitude.cos() * longitude.cos(),
+        );
+
+        camera::to_world(&self.inverse, &glm::Vec3::zeros(), &direction)
+    }
+}
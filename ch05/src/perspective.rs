@@ -0,0 +1,182 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Mat4;
+
+use crate::camera::{self, Camera};
+use crate::ray::Ray;
+use crate::rng::Rng;
+use crate::sampling::concentric_disk;
+
+// Shape of the lens opening, which is also the shape of out-of-focus highlights
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aperture {
+    Disk,
+    // Regular polygon with `sides` blades, turned by `rotation` radians
+    Polygon { sides: usize, rotation: f32 },
+}
+
+#[allow(dead_code)]
+impl Aperture {
+    // Maps a point in the unit square to a point on the unit-radius aperture,
+    // uniformly by area
+    pub fn sample(&self, u: f32, v: f32) -> (f32, f32) {
+        match *self {
+            Aperture::Disk => concentric_disk(u, v),
+            Aperture::Polygon { sides, rotation } => {
+                let sides = sides.max(3);
+                let wedge = std::f32::consts::TAU / sides as f32;
+
+                // Pick a wedge with u, then a point in that triangle
+                let scaled = u * sides as f32;
+                let k = (scaled as usize).min(sides - 1);
+                let u = scaled - k as f32;
+
+                let a = rotation + wedge * k as f32;
+                let b = a + wedge;
+
+                let s = u.sqrt();
+                let (wa, wb) = (s * (1.0 - v), s * v);
+
+                (wa * a.cos() + wb * b.cos(), wa * a.sin() + wb * b.sin())
+            }
+        }
+    }
+}
+
+// The camera from chapter 7, extended with a thin lens. The camera sits at the
+// origin looking down -z, one unit away from the canvas, and `transform`
+// moves the world around it.
+//
+// With an aperture of 0 it's the book's pinhole camera. Otherwise rays start
+// on a lens of radius `aperture` and pass through the point on the focal plane,
+// `focal_distance` units away, that the pinhole ray would have hit.
+//
+// Each ray is cast at a time between `shutter_open` and `shutter_close`, so
+// objects that move while the shutter is open blur.
+#[allow(dead_code)]
+pub struct PerspectiveCamera {
+    pub aperture: f32,
+    pub focal_distance: f32,
+    pub aperture_shape: Aperture,
+    pub shutter_open: f32,
+    pub shutter_close: f32,
+    hsize: usize,
+    vsize: usize,
+    field_of_view: f32,
+    transform: Mat4,
+    inverse: Mat4,
+    half_width: f32,
+    half_height: f32,
+    pixel_size: f32,
+}
+
+#[allow(dead_code)]
+impl PerspectiveCamera {
+    pub fn new(hsize: usize, vsize: usize, field_of_view: f32) -> PerspectiveCamera {
+        let half_view = f32::tan(field_of_view / 2.0);
+        let aspect = hsize as f32 / vsize as f32;
+
+        let (half_width, half_height) = if aspect >= 1.0 {
+            (half_view, half_view / aspect)
+        }
+        else {
+            (half_view * aspect, half_view)
+        };
+
+        PerspectiveCamera {
+            aperture: 0.0,
+            focal_distance: 1.0,
+            aperture_shape: Aperture::Disk,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            hsize,
+            vsize,
+            field_of_view,
+            transform: Mat4::identity(),
+            inverse: Mat4::identity(),
+            half_width,
+            half_height,
+            pixel_size: half_width * 2.0 / hsize as f32,
+        }
+    }
+
+    pub fn field_of_view(&self) -> f32 {
+        self.field_of_view
+    }
+
+    pub fn pixel_size(&self) -> f32 {
+        self.pixel_size
+    }
+
+    // Ray through (px, py) starting from the point (u, v) in the unit square
+    // mapped onto the aperture
+    pub fn ray_for_pixel_lens(&self, px: f32, py: f32, u: f32, v: f32) -> Ray {
+        if self.aperture <= 0.0 {
+            return self.center_ray(px, py);
+        }
+
+        let world_x = self.half_width - px * self.pixel_size;
+        let world_y = self.half_height - py * self.pixel_size;
+
+        // The canvas is one unit away, so scaling it puts it on the focal plane
+        let focus = glm::vec3(world_x, world_y, -1.0) * self.focal_distance;
+        let (lx, ly) = self.aperture_shape.sample(u, v);
+
+        let origin = glm::vec3(lx * self.aperture, ly * self.aperture, 0.0);
+
+        let mut r = camera::to_world(&self.inverse, &origin, &(focus - origin));
+        r.time = self.shutter_open;
+        r
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn hsize(&self) -> usize {
+        self.hsize
+    }
+
+    fn vsize(&self) -> usize {
+        self.vsize
+    }
+
+    fn transform(&self) -> &Mat4 {
+        &self.transform
+    }
+
+    fn set_transform(&mut self, t: &Mat4) {
+        self.transform = *t;
+        self.inverse = glm::inverse(t);
+    }
+
+    // The lens point and the time are picked by an Rng seeded from the pixel
+    // coordinates, so the same position always gets the same ray.
+    fn ray_for_pixel(&self, px: f32, py: f32) -> Ray {
+        if self.aperture <= 0.0 && self.shutter_open == self.shutter_close {
+            return self.center_ray(px, py);
+        }
+
+        let mut rng = Rng::seeded(&[px.to_bits() as u64, py.to_bits() as u64]);
+        let (u, v) = (rng.next_f32(), rng.next_f32());
+        let time = glm::lerp_scalar(self.shutter_open, self.shutter_close, rng.next_f32());
+
+        let mut r = self.ray_for_pixel_lens(px, py, u, v);
+        r.time = time;
+        r
+    }
+
+    // The pinhole ray through (px, py) from the center of the lens
+    fn center_ray(&self, px: f32, py: f32) -> Ray {
+        let world_x = self.half_width - px * self.pixel_size;
+        let world_y = self.half_height - py * self.pixel_size;
+
+        let pixel = glm::vec4_to_vec3(&(self.inverse * glm::vec4(world_x, world_y, -1.0, 1.0)));
+        let origin = glm::vec4_to_vec3(&(self.inverse * glm::vec4(0.0, 0.0, 0.0, 1.0)));
+
+        Ray::build_at(&origin, &(pixel - origin).normalize(), self.shutter_open)
+    }
+
+    fn focus(&mut self, distance: f32) {
+        self.focal_distance = distance;
+    }
+}
@@ -0,0 +1,100 @@
+extern crate nalgebra_glm as glm;
+
+use std::fmt;
+
+use glm::Mat4;
+use glm::Vec3;
+
+use crate::bsdf::Bsdf;
+use crate::camera::Camera;
+use crate::material::Material;
+use crate::world::World;
+
+// Everything we know about the object under a pixel
+#[allow(dead_code)]
+pub struct Pick {
+    pub pixel: (usize, usize),
+    pub name: String,
+    pub t: f32,
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub material: Material,
+    pub transforms: Vec<Mat4>,
+}
+
+// Casts the ray through the center of pixel (x, y) and the center of the lens
+// and inspects the hit, if any
+pub fn pick(world: &World, camera: &dyn Camera, x: usize, y: usize) -> Option<Pick> {
+    let r = camera.center_ray(x as f32 + 0.5, y as f32 + 0.5);
+    let hit = world.hit(&r)?;
+
+    let obj = hit.obj();
+    let point = r.position(hit.t());
+
+    Some(Pick {
+        pixel: (x, y),
+        name: obj.name().to_string(),
+        t: hit.t(),
+        point,
+        normal: obj.normal_at_time(&point, r.time),
+        material: *obj.material(),
+        transforms: obj.transform_chain(),
+    })
+}
+
+fn fmt_vec3(v: &Vec3) -> String {
+    format!("({:.4}, {:.4}, {:.4})", v.x, v.y, v.z)
+}
+
+// What the path tracer scatters with; without a BSDF it falls back to the
+// Phong values
+fn fmt_bsdf(bsdf: &Option<Bsdf>) -> String {
+    match bsdf {
+        None => String::from("none (from Phong)"),
+        Some(Bsdf::Lambertian { albedo }) => format!("lambertian albedo {}", fmt_vec3(albedo)),
+        Some(Bsdf::Conductor { f0, roughness }) => format!("conductor f0 {} roughness {}", fmt_vec3(f0), roughness),
+        Some(Bsdf::Dielectric { ior, roughness }) => format!("dielectric ior {} roughness {}", ior, roughness),
+        Some(Bsdf::MetallicRoughness {
+            base_color,
+            metallic,
+            roughness,
+        }) => format!(
+            "metallic-roughness base color {} metallic {} roughness {}",
+            fmt_vec3(base_color),
+            metallic,
+            roughness
+        ),
+    }
+}
+
+impl fmt::Display for Pick {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let m = &self.material;
+
+        writeln!(f, "pixel ({}, {}): {}", self.pixel.0, self.pixel.1, self.name)?;
+        writeln!(f, "  t:        {:.4}", self.t)?;
+        writeln!(f, "  point:    {}", fmt_vec3(&self.point))?;
+        writeln!(f, "  normal:   {}", fmt_vec3(&self.normal))?;
+        writeln!(
+            f,
+            "  material: color {} ambient {} diffuse {} specular {} shininess {}",
+            fmt_vec3(&m.color), m.ambient, m.diffuse, m.specular, m.shininess,
+        )?;
+        writeln!(f, "            emissive {} bsdf {}", fmt_vec3(&m.emissive), fmt_bsdf(&m.bsdf))?;
+        writeln!(f, "  transform chain (outermost first):")?;
+
+        for (i, t) in self.transforms.iter().enumerate() {
+            for row in 0..4 {
+                let prefix = if row == 0 { format!("    [{}]", i) } else { String::new() };
+
+                writeln!(
+                    f,
+                    "{:8}{:10.4}{:10.4}{:10.4}{:10.4}",
+                    prefix, t[(row, 0)], t[(row, 1)], t[(row, 2)], t[(row, 3)],
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
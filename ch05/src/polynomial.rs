@@ -0,0 +1,179 @@
+// Real roots of low degree polynomials, in ascending order. Coefficients go
+// from the highest power down. Everything is done in f64, since ray-torus
+// coefficients span many orders of magnitude.
+
+use std::f64::consts::PI;
+
+// Below this a value counts as zero
+const EPSILON: f64 = 1e-12;
+
+fn is_zero(x: f64) -> bool {
+    x.abs() < EPSILON
+}
+
+// Roots of a x^2 + b x + c. A double root comes back twice, so a ray grazing
+// a sphere still meets it going in and coming out.
+//
+// The root the textbook formula would get by subtracting two nearly equal
+// numbers is found from the other one through their product, c / a.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return if b == 0.0 { vec![] } else { vec![-c / b] };
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+
+    if discriminant < 0.0 {
+        return vec![];
+    }
+
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+
+    if q == 0.0 {
+        // b and c are both zero
+        return vec![0.0, 0.0];
+    }
+
+    let (x1, x2) = (q / a, c / q);
+
+    if x1 < x2 { vec![x1, x2] } else { vec![x2, x1] }
+}
+
+// Roots of a x^3 + b x^2 + c x + d, by Cardano's formula or, with three real
+// roots, the trigonometric method
+pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return solve_quadratic(b, c, d);
+    }
+
+    // x^3 + A x^2 + B x + C, then x = y - A/3 leaves y^3 + 3p y + 2q
+    let (a2, b2, c2) = (b / a, c / a, d / a);
+    let p = (b2 - a2 * a2 / 3.0) / 3.0;
+    let q = (2.0 / 27.0 * a2 * a2 * a2 - a2 * b2 / 3.0 + c2) / 2.0;
+    let discriminant = q * q + p * p * p;
+
+    let mut roots = if is_zero(discriminant) {
+        if is_zero(q) {
+            vec![0.0]
+        }
+        else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    }
+    else if discriminant < 0.0 {
+        let phi = (-q / (-p * p * p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![t * phi.cos(), -t * (phi + PI / 3.0).cos(), -t * (phi - PI / 3.0).cos()]
+    }
+    else {
+        let s = discriminant.sqrt();
+        vec![(s - q).cbrt() - (s + q).cbrt()]
+    };
+
+    for r in roots.iter_mut() {
+        *r -= a2 / 3.0;
+    }
+
+    sorted(roots)
+}
+
+// Roots of a x^4 + b x^3 + c x^2 + d x + e, by Ferrari's method: one root of
+// a resolvent cubic splits the quartic into two quadratics. Each root is then
+// polished with a few Newton steps on the original polynomial, which wins
+// back the digits lost near double roots, as when a ray grazes a torus.
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return solve_cubic(b, c, d, e);
+    }
+
+    // x^4 + A x^3 + B x^2 + C x + D, then x = y - A/4 leaves
+    // y^4 + p y^2 + q y + r
+    let (a3, b3, c3, d3) = (b / a, c / a, d / a, e / a);
+    let sq = a3 * a3;
+    let p = b3 - 3.0 / 8.0 * sq;
+    let q = c3 - a3 * b3 / 2.0 + sq * a3 / 8.0;
+    let r = d3 - a3 * c3 / 4.0 + sq * b3 / 16.0 - 3.0 / 256.0 * sq * sq;
+
+    let mut roots = if is_zero(r) {
+        // y (y^3 + p y + q) = 0
+        let mut roots = solve_cubic(1.0, 0.0, p, q);
+        roots.push(0.0);
+        roots
+    }
+    else {
+        let resolvent = solve_cubic(1.0, -p / 2.0, -r, r * p / 2.0 - q * q / 8.0);
+        let z = resolvent.iter().fold(f64::MIN, |m, &z| m.max(z));
+
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+
+        let u = if is_zero(u) {
+            0.0
+        }
+        else if u > 0.0 {
+            u.sqrt()
+        }
+        else {
+            return vec![];
+        };
+
+        let v = if is_zero(v) {
+            0.0
+        }
+        else if v > 0.0 {
+            v.sqrt()
+        }
+        else {
+            return vec![];
+        };
+
+        let v = if q < 0.0 { -v } else { v };
+
+        let mut roots = solve_quadratic(1.0, v, z - u);
+        roots.extend(solve_quadratic(1.0, -v, z + u));
+        roots
+    };
+
+    for root in roots.iter_mut() {
+        *root = polish(&[a, b, c, d, e], *root - a3 / 4.0);
+    }
+
+    sorted(roots)
+}
+
+// Value and slope of the polynomial with these coefficients at x
+fn evaluate(coefficients: &[f64], x: f64) -> (f64, f64) {
+    coefficients.iter().fold((0.0, 0.0), |(f, df), &k| (f * x + k, df * x + f))
+}
+
+// Newton's method from x, keeping only steps that bring the polynomial
+// closer to zero
+fn polish(coefficients: &[f64], x: f64) -> f64 {
+    let mut best = x;
+    let (mut value, mut slope) = evaluate(coefficients, x);
+
+    for _ in 0..8 {
+        if value == 0.0 || slope == 0.0 {
+            break;
+        }
+
+        let next = best - value / slope;
+        let (next_value, next_slope) = evaluate(coefficients, next);
+
+        if next_value.abs() >= value.abs() {
+            break;
+        }
+
+        best = next;
+        value = next_value;
+        slope = next_slope;
+    }
+
+    best
+}
+
+fn sorted(mut roots: Vec<f64>) -> Vec<f64> {
+    roots.sort_by(|a, b| a.total_cmp(b));
+    roots
+}
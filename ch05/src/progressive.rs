@@ -0,0 +1,165 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Vec3;
+
+use crate::color;
+use crate::render;
+use crate::sampling::{self, Sampler};
+
+// Block sizes for the coarse preview passes, largest first
+const COARSE_BLOCKS: [usize; 3] = [16, 8, 4];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pass {
+    // One sample per block x block square, copied across the whole block
+    Coarse(usize),
+    // Sample i of the sampler's pattern in every pixel, filtered together
+    // with the samples before it
+    Sample(usize),
+}
+
+// Renders a frame in a series of increasingly expensive passes so the window
+// has something to show right away.
+//
+// The shade function takes continuous pixel coordinates: (x, y) is the top
+// left corner of pixel (x, y) and (x + 0.5, y + 0.5) is its center.
+pub struct Progressive {
+    width: usize,
+    height: usize,
+    threads: usize,
+    sampler: Sampler,
+    next: usize,
+    accum: Vec<Vec3>,
+    weights: Vec<f32>,
+    buffer: Vec<u32>,
+}
+
+#[allow(dead_code)]
+impl Progressive {
+    pub fn build(width: usize, height: usize, threads: usize, sampler: Sampler) -> Progressive {
+        Progressive {
+            width,
+            height,
+            threads,
+            sampler,
+            next: 0,
+            accum: vec![Vec3::zeros(); width * height],
+            weights: vec![0.0; width * height],
+            buffer: vec![0; width * height],
+        }
+    }
+
+    pub fn passes(&self) -> usize {
+        COARSE_BLOCKS.len() + self.sampler.count()
+    }
+
+    pub fn pass(&self, i: usize) -> Pass {
+        if i < COARSE_BLOCKS.len() {
+            Pass::Coarse(COARSE_BLOCKS[i])
+        }
+        else {
+            Pass::Sample(i - COARSE_BLOCKS.len())
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next >= self.passes()
+    }
+
+    // Throws away the accumulated samples, e.g. after the camera moved.
+    // The current buffer stays on screen until the next pass replaces it.
+    pub fn restart(&mut self) {
+        self.next = 0;
+        self.accum.iter_mut().for_each(|c| *c = Vec3::zeros());
+        self.weights.iter_mut().for_each(|w| *w = 0.0);
+    }
+
+    pub fn buffer(&self) -> &[u32] {
+        &self.buffer
+    }
+
+    pub fn progress(&self) -> String {
+        if self.is_done() {
+            return format!("done, {} spp", self.sampler.count());
+        }
+
+        match self.pass(self.next) {
+            Pass::Coarse(block) => format!("preview {}x{}", block, block),
+            Pass::Sample(i) => format!("sample {}/{}", i + 1, self.sampler.count()),
+        }
+    }
+
+    // Renders the next pass. Returns false once every pass has been rendered.
+    pub fn step<F>(&mut self, shade: F) -> bool
+    where
+        F: Fn(f32, f32) -> Vec3 + Sync,
+    {
+        if self.is_done() {
+            return false;
+        }
+
+        match self.pass(self.next) {
+            Pass::Coarse(block) => self.coarse(block, &shade),
+            Pass::Sample(i) => self.sample(i, &shade),
+        }
+
+        self.next += 1;
+
+        true
+    }
+
+    fn coarse<F>(&mut self, block: usize, shade: &F)
+    where
+        F: Fn(f32, f32) -> Vec3 + Sync,
+    {
+        let cols = self.width.div_ceil(block);
+        let rows = self.height.div_ceil(block);
+        let half = block as f32 / 2.0;
+
+        let blocks = render::render(cols, rows, self.threads, |bx, by| {
+            color::to_u32(&shade(
+                (bx * block) as f32 + half,
+                (by * block) as f32 + half,
+            ))
+        });
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                self.buffer[y * self.width + x] = blocks[(y / block) * cols + x / block];
+            }
+        }
+    }
+
+    fn sample<F>(&mut self, i: usize, shade: &F)
+    where
+        F: Fn(f32, f32) -> Vec3 + Sync,
+    {
+        let sampler = self.sampler;
+
+        let samples = render::render(self.width, self.height, self.threads, |x, y| {
+            let (px, py) = sampler.position(x, y, i);
+            let w = sampler.weight(x, y, px, py);
+
+            if w == 0.0 {
+                (Vec3::zeros(), 0.0)
+            }
+            else {
+                (shade(px, py) * w, w)
+            }
+        });
+
+        for (p, (s, w)) in samples.iter().enumerate() {
+            if *w == 0.0 {
+                continue;
+            }
+
+            self.accum[p] += s;
+            self.weights[p] += w;
+
+            // Keep the coarse preview until a pixel has some weight
+            if self.weights[p].abs() >= 1e-6 {
+                self.buffer[p] = color::to_u32(&sampling::resolve(&self.accum[p], self.weights[p]));
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Mat4;
+use glm::Vec3;
+
+use crate::hittable::Hittable;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::polynomial;
+use crate::ray::Ray;
+
+// Surface where
+//
+//   A x^2 + B y^2 + C z^2 + D xy + E xz + F yz + G x + H y + I z + J = 0
+//
+// with the coefficients given in that order. Paraboloids, hyperboloids,
+// cylinders and cones go on forever, so only the part inside `min` to `max`
+// in object space is kept; the rest of the surface is open there.
+#[allow(dead_code)]
+pub struct Quadric {
+    coefficients: [f32; 10],
+    // The same as a symmetric matrix Q, with f(p) = p^T Q p for p = (x, y, z, 1)
+    q: Mat4,
+    pub min: Vec3,
+    pub max: Vec3,
+    pub transform: Mat4,
+    pub material: Material,
+    pub name: String,
+}
+
+#[allow(dead_code)]
+impl Quadric {
+    pub fn build(coefficients: [f32; 10], t: &Mat4) -> Quadric {
+        let [a, b, c, d, e, f, g, h, i, j] = coefficients;
+        #[rustfmt::skip]
+        let q = Mat4::new(
+            a,       d / 2.0, e / 2.0, g / 2.0,
+            d / 2.0, b,       f / 2.0, h / 2.0,
+            e / 2.0, f / 2.0, c,       i / 2.0,
+            g / 2.0, h / 2.0, i / 2.0, j,
+        );
+
+        Quadric {
+            coefficients,
+            q,
+            min: glm::vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+            max: glm::vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            transform: *t,
+            material: Material::new(),
+            name: String::from("quadric"),
+        }
+    }
+
+    // x^2/a^2 + y^2/b^2 + z^2/c^2 = 1
+    pub fn ellipsoid(a: f32, b: f32, c: f32) -> Quadric {
+        Quadric::build(
+            [1.0 / (a * a), 1.0 / (b * b), 1.0 / (c * c), 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0],
+            &Mat4::identity(),
+        )
+    }
+
+    // y = x^2/a^2 + z^2/c^2, a bowl opening up from the origin
+    pub fn paraboloid(a: f32, c: f32) -> Quadric {
+        Quadric::build(
+            [1.0 / (a * a), 0.0, 1.0 / (c * c), 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0],
+            &Mat4::identity(),
+        )
+    }
+
+    // x^2/a^2 - y^2/b^2 + z^2/c^2 = 1, a cooling tower around the y axis
+    pub fn hyperboloid_one_sheet(a: f32, b: f32, c: f32) -> Quadric {
+        Quadric::build(
+            [1.0 / (a * a), -1.0 / (b * b), 1.0 / (c * c), 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0],
+            &Mat4::identity(),
+        )
+    }
+
+    // y^2/b^2 - x^2/a^2 - z^2/c^2 = 1, two bowls facing apart along y
+    pub fn hyperboloid_two_sheets(a: f32, b: f32, c: f32) -> Quadric {
+        Quadric::build(
+            [-1.0 / (a * a), 1.0 / (b * b), -1.0 / (c * c), 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0],
+            &Mat4::identity(),
+        )
+    }
+
+    // x^2 + z^2 = r^2 around the y axis
+    pub fn cylinder(r: f32) -> Quadric {
+        Quadric::build([1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -r * r], &Mat4::identity())
+    }
+
+    // x^2 + z^2 = (slope y)^2, with its tip at the origin
+    pub fn cone(slope: f32) -> Quadric {
+        Quadric::build([1.0, -slope * slope, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0], &Mat4::identity())
+    }
+
+    pub fn coefficients(&self) -> &[f32; 10] {
+        &self.coefficients
+    }
+
+    // Keeps only the part of the surface inside the box from `min` to `max`
+    pub fn clip(mut self, min: &Vec3, max: &Vec3) -> Quadric {
+        self.min = *min;
+        self.max = *max;
+        self
+    }
+
+    // Value of the quadric's function at a point in object space, zero on the
+    // surface
+    pub fn value(&self, p: &Vec3) -> f32 {
+        let h = glm::vec4(p.x, p.y, p.z, 1.0);
+        glm::dot(&h, &(self.q * h))
+    }
+
+    fn inside_bounds(&self, p: &Vec3) -> bool {
+        (0..3).all(|i| p[i] >= self.min[i] && p[i] <= self.max[i])
+    }
+}
+
+impl Hittable for Quadric {
+    // Putting o + t d into p^T Q p gives a quadratic in t
+    fn intersect(&self, r: &Ray) -> Vec<Intersection<'_>> {
+        let r2 = Ray::transform(r, &glm::inverse(&self.transform_at(r.time)));
+        let o = glm::vec4(r2.origin.x, r2.origin.y, r2.origin.z, 1.0);
+        let d = glm::vec4(r2.direction.x, r2.direction.y, r2.direction.z, 0.0);
+
+        let a = glm::dot(&d, &(self.q * d)) as f64;
+        let b = 2.0 * glm::dot(&d, &(self.q * o)) as f64;
+        let c = glm::dot(&o, &(self.q * o)) as f64;
+
+        polynomial::solve_quadratic(a, b, c)
+            .iter()
+            .map(|&t| t as f32)
+            .filter(|&t| self.inside_bounds(&r2.position(t)))
+            .map(|t| Intersection::build(t, self))
+            .collect()
+    }
+
+    // The gradient, 2 Q p
+    fn normal_at_time(&self, p: &Vec3, time: f32) -> Vec3 {
+        let inverse = glm::inverse(&self.transform_at(time));
+
+        let object_point = inverse * glm::vec4(p.x, p.y, p.z, 1.0);
+        let gradient = self.q * object_point;
+        let object_normal = glm::vec4(gradient.x, gradient.y, gradient.z, 0.0);
+        let world_normal = glm::transpose(&inverse) * object_normal;
+
+        glm::vec4_to_vec3(&world_normal).normalize()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn transform(&self) -> &Mat4 {
+        &self.transform
+    }
+}
@@ -5,6 +5,8 @@ use glm::Vec3;
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    // When the ray was cast, between shutter open (0) and close (1)
+    pub time: f32,
 }
 
 #[allow(dead_code)]
@@ -13,16 +15,40 @@ impl Ray {
         Ray {
             origin: glm::vec3(0.0, 0.0, 0.0),
             direction: glm::vec3(0.0, 0.0, 0.0),
+            time: 0.0,
         }
     }
 
     pub fn build(o: &Vec3, d: &Vec3) -> Ray {
         Ray {
-            origin: o.clone(),
-            direction: d.clone(),
+            origin: *o,
+            direction: *d,
+            time: 0.0,
         }
     }
 
+    pub fn build_at(o: &Vec3, d: &Vec3, time: f32) -> Ray {
+        Ray {
+            origin: *o,
+            direction: *d,
+            time,
+        }
+    }
+
+    // Bits of the ray to seed an Rng with, so anything random done for a ray
+    // is repeatable
+    pub fn seed(&self) -> [u64; 7] {
+        [
+            self.origin.x.to_bits() as u64,
+            self.origin.y.to_bits() as u64,
+            self.origin.z.to_bits() as u64,
+            self.direction.x.to_bits() as u64,
+            self.direction.y.to_bits() as u64,
+            self.direction.z.to_bits() as u64,
+            self.time.to_bits() as u64,
+        ]
+    }
+
     pub fn position(&self, t: f32) -> Vec3 {
         self.origin + self.direction * t
     }
@@ -35,6 +61,7 @@ impl Ray {
             direction: glm::vec4_to_vec3(
                 &(m * glm::vec4(r.direction.x, r.direction.y, r.direction.z, 0.0)),
             ),
+            time: r.time,
         }
     }
 }
@@ -0,0 +1,139 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+pub const TILE_SIZE: usize = 16;
+
+// A rectangular block of pixels. Tiles are the unit of work handed out to
+// the render threads.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+#[allow(dead_code)]
+impl Tile {
+    pub fn build(x: usize, y: usize, width: usize, height: usize) -> Tile {
+        Tile {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    // Splits a width x height image into tiles of at most size x size pixels,
+    // row by row. Tiles on the right and bottom edges may be smaller.
+    pub fn split(width: usize, height: usize, size: usize) -> Vec<Tile> {
+        let size = size.max(1);
+        let mut tiles = vec![];
+
+        for y in (0..height).step_by(size) {
+            for x in (0..width).step_by(size) {
+                tiles.push(Tile::build(
+                    x,
+                    y,
+                    size.min(width - x),
+                    size.min(height - y),
+                ));
+            }
+        }
+
+        tiles
+    }
+
+    pub fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// Renders every pixel in order on the calling thread.
+#[allow(dead_code)]
+pub fn render_serial<T, F>(width: usize, height: usize, shade: F) -> Vec<T>
+where
+    F: Fn(usize, usize) -> T,
+{
+    let mut buffer = Vec::with_capacity(width * height);
+
+    for y in 0..height {
+        for x in 0..width {
+            buffer.push(shade(x, y));
+        }
+    }
+
+    buffer
+}
+
+// Renders the image tile by tile across `threads` worker threads.
+//
+// Each worker pulls the next tile off a shared counter, so the order in which
+// tiles finish depends on scheduling. Every pixel is still computed by the same
+// call to `shade(x, y)` and written to the same slot, so the result is
+// identical to `render_serial` no matter how many threads are used.
+pub fn render<T, F>(width: usize, height: usize, threads: usize, shade: F) -> Vec<T>
+where
+    T: Clone + Default + Send,
+    F: Fn(usize, usize) -> T + Sync,
+{
+    let tiles = Tile::split(width, height, TILE_SIZE);
+    let next = AtomicUsize::new(0);
+    let threads = threads.clamp(1, tiles.len().max(1));
+
+    let finished: Vec<(Tile, Vec<T>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+
+                        if i >= tiles.len() {
+                            break;
+                        }
+
+                        let tile = tiles[i];
+                        let mut pixels = Vec::with_capacity(tile.len());
+
+                        for y in tile.y..tile.y + tile.height {
+                            for x in tile.x..tile.x + tile.width {
+                                pixels.push(shade(x, y));
+                            }
+                        }
+
+                        done.push((tile, pixels));
+                    }
+
+                    done
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("Render thread panicked.\n"))
+            .collect()
+    });
+
+    let mut buffer = vec![T::default(); width * height];
+
+    for (tile, pixels) in finished {
+        for (row, chunk) in pixels.chunks(tile.width).enumerate() {
+            let start = (tile.y + row) * width + tile.x;
+            buffer[start..start + tile.width].clone_from_slice(chunk);
+        }
+    }
+
+    buffer
+}
@@ -0,0 +1,205 @@
+extern crate nalgebra_glm as glm;
+
+use std::f32::consts::FRAC_PI_2;
+
+use glm::Vec3;
+
+use crate::camera::{self, Camera};
+use crate::image::Image;
+use crate::perspective::PerspectiveCamera;
+use crate::ray::Ray;
+use crate::render;
+use crate::sampling::Sampler;
+
+// Renders one camera's view. `trace` gives the color seen along a ray, e.g.
+// World::color_at.
+pub fn render_view<F>(camera: &dyn Camera, sampler: &Sampler, threads: usize, trace: &F) -> Image
+where
+    F: Fn(&Ray) -> Vec3 + Sync,
+{
+    let shade = |px: f32, py: f32| trace(&camera.ray_for_pixel(px, py));
+    let pixels = render::render(camera.hsize(), camera.vsize(), threads, |x, y| {
+        sampler.pixel(x, y, &shade)
+    });
+
+    Image::build(camera.hsize(), camera.vsize(), pixels)
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StereoOutput {
+    // Left eye on the left half, right eye on the right half
+    SideBySide,
+    // Red from the left eye, green and blue from the right, for red/cyan glasses
+    Anaglyph,
+}
+
+// Two perspective cameras with parallel view axes, `interocular` units apart
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StereoRig {
+    pub hsize: usize,
+    pub vsize: usize,
+    pub field_of_view: f32,
+    pub interocular: f32,
+    pub output: StereoOutput,
+}
+
+#[allow(dead_code)]
+impl StereoRig {
+    pub fn build(hsize: usize, vsize: usize, field_of_view: f32, interocular: f32, output: StereoOutput) -> StereoRig {
+        StereoRig {
+            hsize,
+            vsize,
+            field_of_view,
+            interocular,
+            output,
+        }
+    }
+
+    // Left and right eye cameras for a head at `from` looking at `to`
+    pub fn eyes(&self, from: &Vec3, to: &Vec3, up: &Vec3) -> [PerspectiveCamera; 2] {
+        // Same "left" as the book's view_transform: it ends up on the left of
+        // the image
+        let forward = (to - from).normalize();
+        let left = glm::cross(&forward, up).normalize() * (self.interocular / 2.0);
+
+        [left, -left].map(|offset| {
+            let mut c = PerspectiveCamera::new(self.hsize, self.vsize, self.field_of_view);
+            c.set_transform(&camera::view_transform(&(from + offset), &(to + offset), up));
+            c
+        })
+    }
+
+    pub fn render<F>(&self, from: &Vec3, to: &Vec3, up: &Vec3, sampler: &Sampler, threads: usize, trace: &F) -> Image
+    where
+        F: Fn(&Ray) -> Vec3 + Sync,
+    {
+        let [l, r] = self.eyes(from, to, up);
+        let left = render_view(&l, sampler, threads, trace);
+        let right = render_view(&r, sampler, threads, trace);
+
+        match self.output {
+            StereoOutput::SideBySide => {
+                let mut out = Image::new(self.hsize * 2, self.vsize);
+                out.blit(&left, 0, 0);
+                out.blit(&right, self.hsize, 0);
+                out
+            }
+            StereoOutput::Anaglyph => {
+                let pixels = left
+                    .pixels
+                    .iter()
+                    .zip(right.pixels.iter())
+                    .map(|(l, r)| glm::vec3(l.x, r.y, r.z))
+                    .collect();
+
+                Image::build(self.hsize, self.vsize, pixels)
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+#[allow(dead_code)]
+impl CubeFace {
+    pub fn all() -> [CubeFace; 6] {
+        [
+            CubeFace::PositiveX,
+            CubeFace::NegativeX,
+            CubeFace::PositiveY,
+            CubeFace::NegativeY,
+            CubeFace::PositiveZ,
+            CubeFace::NegativeZ,
+        ]
+    }
+
+    // View direction and up vector. Up and down are rolled so they line up
+    // with the +z face in the cross layout.
+    pub fn orientation(&self) -> (Vec3, Vec3) {
+        let y = glm::vec3(0.0, 1.0, 0.0);
+
+        match self {
+            CubeFace::PositiveX => (glm::vec3(1.0, 0.0, 0.0), y),
+            CubeFace::NegativeX => (glm::vec3(-1.0, 0.0, 0.0), y),
+            CubeFace::PositiveY => (y, glm::vec3(0.0, 0.0, -1.0)),
+            CubeFace::NegativeY => (-y, glm::vec3(0.0, 0.0, 1.0)),
+            CubeFace::PositiveZ => (glm::vec3(0.0, 0.0, 1.0), y),
+            CubeFace::NegativeZ => (glm::vec3(0.0, 0.0, -1.0), y),
+        }
+    }
+
+    // Tile (column, row) in the horizontal cross:
+    //
+    //        +y
+    //   -x   +z   +x   -z
+    //        -y
+    pub fn cross_tile(&self) -> (usize, usize) {
+        match self {
+            CubeFace::PositiveX => (2, 1),
+            CubeFace::NegativeX => (0, 1),
+            CubeFace::PositiveY => (1, 0),
+            CubeFace::NegativeY => (1, 2),
+            CubeFace::PositiveZ => (1, 1),
+            CubeFace::NegativeZ => (3, 1),
+        }
+    }
+}
+
+// Six square 90 degree cameras at the same point, one per cube face
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CubeMapRig {
+    pub size: usize,
+}
+
+#[allow(dead_code)]
+impl CubeMapRig {
+    pub fn build(size: usize) -> CubeMapRig {
+        CubeMapRig { size }
+    }
+
+    pub fn camera(&self, center: &Vec3, face: CubeFace) -> PerspectiveCamera {
+        let (direction, up) = face.orientation();
+        let mut c = PerspectiveCamera::new(self.size, self.size, FRAC_PI_2);
+
+        c.set_transform(&camera::view_transform(center, &(center + direction), &up));
+        c
+    }
+
+    // One image per face, in CubeFace::all() order
+    pub fn render_faces<F>(&self, center: &Vec3, sampler: &Sampler, threads: usize, trace: &F) -> Vec<Image>
+    where
+        F: Fn(&Ray) -> Vec3 + Sync,
+    {
+        CubeFace::all()
+            .iter()
+            .map(|&face| render_view(&self.camera(center, face), sampler, threads, trace))
+            .collect()
+    }
+
+    // All six faces in a 4 x 3 horizontal cross
+    pub fn render<F>(&self, center: &Vec3, sampler: &Sampler, threads: usize, trace: &F) -> Image
+    where
+        F: Fn(&Ray) -> Vec3 + Sync,
+    {
+        let mut out = Image::new(self.size * 4, self.size * 3);
+
+        for (face, image) in CubeFace::all().iter().zip(self.render_faces(center, sampler, threads, trace)) {
+            let (col, row) = face.cross_tile();
+            out.blit(&image, col * self.size, row * self.size);
+        }
+
+        out
+    }
+}
@@ -0,0 +1,59 @@
+// Small PCG32 generator.
+//
+// Every random decision in the renderer comes from an Rng seeded by the
+// pixel and sample it belongs to, never from shared state, so images come out
+// the same no matter which thread renders which tile.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+    inc: u64,
+}
+
+const MULTIPLIER: u64 = 6364136223846793005;
+
+#[allow(dead_code)]
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        let mut rng = Rng {
+            state: 0,
+            inc: (splitmix(seed ^ 0xDA3E39CB94B95BDB) << 1) | 1,
+        };
+
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(splitmix(seed));
+        rng.next_u32();
+
+        rng
+    }
+
+    // Seeds a generator from several values, e.g. (seed, x, y, sample)
+    pub fn seeded(values: &[u64]) -> Rng {
+        let seed = values
+            .iter()
+            .fold(0x9E3779B97F4A7C15, |h, v| splitmix(h ^ v));
+
+        Rng::new(seed)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.inc);
+
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+
+        xorshifted.rotate_right(rot)
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 * (1.0 / 16777216.0)
+    }
+}
+
+fn splitmix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
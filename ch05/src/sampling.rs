@@ -0,0 +1,431 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Vec3;
+
+use crate::rng::Rng;
+
+// Where the samples for a pixel go
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pattern {
+    // Centers of an n x n grid
+    Grid,
+    // One random point in each cell of an n x n grid
+    Stratified,
+    // Halton sequence in bases 2 and 3
+    Halton,
+    // First two dimensions of the Sobol sequence
+    Sobol,
+}
+
+// Reconstruction filter. Samples are spread over the filter's footprint
+// around the pixel center and weighted by it.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Box { radius: f32 },
+    Tent { radius: f32 },
+    Gaussian { radius: f32, alpha: f32 },
+    Mitchell { radius: f32, b: f32, c: f32 },
+}
+
+#[allow(dead_code)]
+impl Filter {
+    pub fn box_filter() -> Filter {
+        Filter::Box { radius: 0.5 }
+    }
+
+    pub fn tent() -> Filter {
+        Filter::Tent { radius: 1.0 }
+    }
+
+    pub fn gaussian() -> Filter {
+        Filter::Gaussian {
+            radius: 1.5,
+            alpha: 2.0,
+        }
+    }
+
+    pub fn mitchell() -> Filter {
+        Filter::Mitchell {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        }
+    }
+
+    pub fn radius(&self) -> f32 {
+        match *self {
+            Filter::Box { radius } => radius,
+            Filter::Tent { radius } => radius,
+            Filter::Gaussian { radius, .. } => radius,
+            Filter::Mitchell { radius, .. } => radius,
+        }
+    }
+
+    // Weight of a sample (dx, dy) pixels away from the pixel center
+    pub fn weight(&self, dx: f32, dy: f32) -> f32 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, x: f32) -> f32 {
+        let x = x.abs();
+
+        match *self {
+            Filter::Box { radius } => {
+                if x <= radius { 1.0 } else { 0.0 }
+            }
+            Filter::Tent { radius } => (radius - x).max(0.0),
+            Filter::Gaussian { radius, alpha } => {
+                (f32::exp(-alpha * x * x) - f32::exp(-alpha * radius * radius)).max(0.0)
+            }
+            Filter::Mitchell { radius, b, c } => {
+                let x = 2.0 * x / radius;
+
+                if x >= 2.0 {
+                    0.0
+                }
+                else if x >= 1.0 {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                }
+                else {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                }
+            }
+        }
+    }
+}
+
+// Supersampler: a sample pattern, a reconstruction filter and a sample count.
+//
+// Every random choice is seeded by (seed, x, y, sample), so the same seed
+// always gives the same image.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sampler {
+    pub pattern: Pattern,
+    pub filter: Filter,
+    pub samples: usize,
+    pub seed: u64,
+}
+
+#[allow(dead_code)]
+impl Sampler {
+    pub fn new() -> Sampler {
+        Sampler {
+            pattern: Pattern::Stratified,
+            filter: Filter::tent(),
+            samples: 16,
+            seed: 0,
+        }
+    }
+
+    pub fn build(pattern: Pattern, filter: Filter, samples: usize, seed: u64) -> Sampler {
+        Sampler {
+            pattern,
+            filter,
+            samples,
+            seed,
+        }
+    }
+
+    // Side of the grid used by the grid and stratified patterns
+    fn grid_side(&self) -> usize {
+        (self.samples.max(1) as f32).sqrt().ceil() as usize
+    }
+
+    // Number of samples actually taken per pixel. Grid patterns round the
+    // requested count up to a square.
+    pub fn count(&self) -> usize {
+        match self.pattern {
+            Pattern::Grid | Pattern::Stratified => self.grid_side() * self.grid_side(),
+            Pattern::Halton | Pattern::Sobol => self.samples.max(1),
+        }
+    }
+
+    // Sample i of pixel (x, y) as a point in the unit square
+    pub fn unit(&self, x: usize, y: usize, i: usize) -> (f32, f32) {
+        match self.pattern {
+            Pattern::Grid => {
+                let n = self.grid_side();
+
+                (((i % n) as f32 + 0.5) / n as f32, ((i / n) as f32 + 0.5) / n as f32)
+            }
+            Pattern::Stratified => {
+                let n = self.grid_side();
+                let mut rng = Rng::seeded(&[self.seed, x as u64, y as u64, i as u64]);
+
+                (
+                    ((i % n) as f32 + rng.next_f32()) / n as f32,
+                    ((i / n) as f32 + rng.next_f32()) / n as f32,
+                )
+            }
+            Pattern::Halton => {
+                // Cranley-Patterson rotation so neighbouring pixels don't share
+                // the exact same points
+                let mut rng = Rng::seeded(&[self.seed, x as u64, y as u64]);
+                let (sx, sy) = (rng.next_f32(), rng.next_f32());
+
+                (
+                    (radical_inverse(i as u64 + 1, 2) + sx).fract(),
+                    (radical_inverse(i as u64 + 1, 3) + sy).fract(),
+                )
+            }
+            Pattern::Sobol => {
+                // Random digital shift per pixel
+                let mut rng = Rng::seeded(&[self.seed, x as u64, y as u64]);
+                let (sx, sy) = (rng.next_u32(), rng.next_u32());
+
+                (
+                    to_unit(sobol(i as u32, 0) ^ sx),
+                    to_unit(sobol(i as u32, 1) ^ sy),
+                )
+            }
+        }
+    }
+
+    // Sample i of pixel (x, y) in continuous pixel coordinates, spread over the
+    // filter's footprint around the pixel center
+    pub fn position(&self, x: usize, y: usize, i: usize) -> (f32, f32) {
+        let (u, v) = self.unit(x, y, i);
+        let r = self.filter.radius();
+
+        (
+            x as f32 + 0.5 + (2.0 * u - 1.0) * r,
+            y as f32 + 0.5 + (2.0 * v - 1.0) * r,
+        )
+    }
+
+    pub fn weight(&self, x: usize, y: usize, px: f32, py: f32) -> f32 {
+        self.filter.weight(px - (x as f32 + 0.5), py - (y as f32 + 0.5))
+    }
+
+    // Filtered estimate of pixel (x, y)
+    pub fn pixel<F>(&self, x: usize, y: usize, shade: &F) -> Vec3
+    where
+        F: Fn(f32, f32) -> Vec3,
+    {
+        let mut sum = Vec3::zeros();
+        let mut weights = 0.0;
+
+        for i in 0..self.count() {
+            let (px, py) = self.position(x, y, i);
+            let w = self.weight(x, y, px, py);
+
+            if w != 0.0 {
+                sum += shade(px, py) * w;
+                weights += w;
+            }
+        }
+
+        resolve(&sum, weights)
+    }
+}
+
+// Divides a weighted sum by its weights, guarding against filters whose
+// weights cancel out
+pub fn resolve(sum: &Vec3, weights: f32) -> Vec3 {
+    if weights.abs() < 1e-6 {
+        Vec3::zeros()
+    }
+    else {
+        sum / weights
+    }
+}
+
+fn radical_inverse(mut i: u64, base: u64) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv = inv_base;
+    let mut result = 0.0;
+
+    while i > 0 {
+        result += (i % base) as f64 * inv;
+        i /= base;
+        inv *= inv_base;
+    }
+
+    result.min(1.0 - f64::EPSILON) as f32
+}
+
+// Dimension 0 is the van der Corput sequence, dimension 1 uses the direction
+// numbers for the primitive polynomial x + 1
+fn sobol(i: u32, dimension: usize) -> u32 {
+    let mut v: u32 = 1 << 31;
+    let mut result = 0;
+    let mut i = i;
+
+    while i > 0 {
+        if i & 1 == 1 {
+            result ^= v;
+        }
+
+        i >>= 1;
+        v = if dimension == 0 { v >> 1 } else { v ^ (v >> 1) };
+    }
+
+    result
+}
+
+fn to_unit(bits: u32) -> f32 {
+    (bits >> 8) as f32 * (1.0 / 16777216.0)
+}
+
+// Shirley and Chiu's concentric mapping from the square to the disk
+pub fn concentric_disk(u: f32, v: f32) -> (f32, f32) {
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, std::f32::consts::FRAC_PI_4 * (b / a))
+    }
+    else {
+        (b, std::f32::consts::FRAC_PI_2 - std::f32::consts::FRAC_PI_4 * (a / b))
+    };
+
+    (r * theta.cos(), r * theta.sin())
+}
+
+// Direction around `normal` with density cos(theta) / pi, from Malley's
+// method: a uniform point on the disk projected up onto the hemisphere
+pub fn cosine_hemisphere(normal: &Vec3, u: f32, v: f32) -> Vec3 {
+    let (x, y) = concentric_disk(u, v);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+    let (s, t) = orthonormal_basis(normal);
+
+    (s * x + t * y + normal * z).normalize()
+}
+
+// Two unit vectors perpendicular to `n` and to each other
+pub fn orthonormal_basis(n: &Vec3) -> (Vec3, Vec3) {
+    let a = if n.x.abs() > 0.9 { glm::vec3(0.0, 1.0, 0.0) } else { glm::vec3(1.0, 0.0, 0.0) };
+    let s = glm::cross(n, &a).normalize();
+    let t = glm::cross(n, &s);
+
+    (s, t)
+}
+
+// Veach's power heuristic: the MIS weight of a sample from strategy f, taken
+// `nf` times, against strategy g taken `ng` times. An infinite pdf means only
+// f could have picked the sample.
+pub fn power_heuristic(nf: f32, f_pdf: f32, ng: f32, g_pdf: f32) -> f32 {
+    if f_pdf.is_infinite() {
+        return 1.0;
+    }
+
+    let (f, g) = (nf * f_pdf, ng * g_pdf);
+
+    if f == 0.0 && g == 0.0 {
+        return 0.0;
+    }
+
+    (f * f) / (f * f + g * g)
+}
+
+// Piecewise-constant density over [0, 1) with one step per value of `func`,
+// for importance sampling tabulated functions. A function that's zero
+// everywhere is sampled uniformly.
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Distribution1D {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+#[allow(dead_code)]
+impl Distribution1D {
+    pub fn build(func: &[f32]) -> Distribution1D {
+        let n = func.len().max(1);
+        let mut cdf = vec![0.0; n + 1];
+
+        for i in 0..func.len() {
+            cdf[i + 1] = cdf[i] + func[i].max(0.0) / n as f32;
+        }
+
+        let integral = cdf[n];
+
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 { *c / integral } else { i as f32 / n as f32 };
+        }
+
+        Distribution1D {
+            func: func.iter().map(|f| f.max(0.0)).collect(),
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    // Point in [0, 1) for `u`, with its density and the step it's in
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let n = self.count().max(1);
+        let i = self.cdf.partition_point(|&c| c <= u).clamp(1, n) - 1;
+
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let du = if width > 0.0 { (u - self.cdf[i]) / width } else { 0.0 };
+
+        let x = ((i as f32 + du.clamp(0.0, 1.0)) / n as f32).min(1.0 - f32::EPSILON);
+
+        (x, self.pdf(i), i)
+    }
+
+    // Density of the step at `i`
+    pub fn pdf(&self, i: usize) -> f32 {
+        if self.integral > 0.0 { self.func[i] / self.integral } else { 1.0 }
+    }
+}
+
+// Piecewise-constant density over [0, 1)^2 for a `width` x `height` table in
+// row order: a row is picked from the rows' totals, then a column within it
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+#[allow(dead_code)]
+impl Distribution2D {
+    pub fn build(func: &[f32], width: usize, height: usize) -> Distribution2D {
+        let rows: Vec<Distribution1D> = func.chunks(width.max(1)).take(height).map(Distribution1D::build).collect();
+        let totals: Vec<f32> = rows.iter().map(|r| r.integral()).collect();
+
+        Distribution2D {
+            rows,
+            marginal: Distribution1D::build(&totals),
+        }
+    }
+
+    // Point (x, y) in the unit square and its density
+    pub fn sample(&self, u: f32, v: f32) -> ((f32, f32), f32) {
+        let (y, pdf_y, row) = self.marginal.sample(v);
+        let (x, pdf_x, _) = self.rows[row].sample(u);
+
+        ((x, y), pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, x: f32, y: f32) -> f32 {
+        let row = ((y * self.marginal.count() as f32) as usize).min(self.marginal.count() - 1);
+        let r = &self.rows[row];
+        let col = ((x * r.count() as f32) as usize).min(r.count() - 1);
+
+        self.marginal.pdf(row) * r.pdf(col)
+    }
+}
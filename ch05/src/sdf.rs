@@ -0,0 +1,283 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Mat4;
+use glm::Vec3;
+
+use crate::hittable::Hittable;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::ray::Ray;
+
+// Signed distance functions: how far a point is from the surface, negative
+// inside. The smooth operators blend within `k` of where the shapes meet and
+// only bound the true distance, which is all sphere tracing needs.
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Sdf {
+    Sphere { radius: f32 },
+    // Box centered on the origin with the given half sizes
+    Cuboid { half: Vec3 },
+    // Ring around the y axis
+    Torus { major: f32, minor: f32 },
+    Capsule { a: Vec3, b: Vec3, radius: f32 },
+    Translate { shape: Box<Sdf>, offset: Vec3 },
+    SmoothUnion { a: Box<Sdf>, b: Box<Sdf>, k: f32 },
+    // `a` with `b` carved out of it
+    SmoothSubtraction { a: Box<Sdf>, b: Box<Sdf>, k: f32 },
+    // Copies of the shape every `period` along each axis, or just one along
+    // axes where it's zero
+    Repeat { shape: Box<Sdf>, period: Vec3 },
+}
+
+#[allow(dead_code)]
+impl Sdf {
+    pub fn sphere(radius: f32) -> Sdf {
+        Sdf::Sphere { radius }
+    }
+
+    pub fn cuboid(half: &Vec3) -> Sdf {
+        Sdf::Cuboid { half: *half }
+    }
+
+    pub fn torus(major: f32, minor: f32) -> Sdf {
+        Sdf::Torus { major, minor }
+    }
+
+    pub fn capsule(a: &Vec3, b: &Vec3, radius: f32) -> Sdf {
+        Sdf::Capsule { a: *a, b: *b, radius }
+    }
+
+    pub fn translate(self, offset: &Vec3) -> Sdf {
+        Sdf::Translate {
+            shape: Box::new(self),
+            offset: *offset,
+        }
+    }
+
+    pub fn smooth_union(self, other: Sdf, k: f32) -> Sdf {
+        Sdf::SmoothUnion {
+            a: Box::new(self),
+            b: Box::new(other),
+            k,
+        }
+    }
+
+    pub fn smooth_subtraction(self, other: Sdf, k: f32) -> Sdf {
+        Sdf::SmoothSubtraction {
+            a: Box::new(self),
+            b: Box::new(other),
+            k,
+        }
+    }
+
+    pub fn repeat(self, period: &Vec3) -> Sdf {
+        Sdf::Repeat {
+            shape: Box::new(self),
+            period: *period,
+        }
+    }
+
+    pub fn distance(&self, p: &Vec3) -> f32 {
+        match self {
+            Sdf::Sphere { radius } => p.norm() - radius,
+            Sdf::Cuboid { half } => {
+                let q = p.abs() - half;
+                q.map(|c| c.max(0.0)).norm() + q.max().min(0.0)
+            }
+            Sdf::Torus { major, minor } => {
+                let ring = glm::vec2(p.x, p.z).norm() - major;
+                glm::vec2(ring, p.y).norm() - minor
+            }
+            Sdf::Capsule { a, b, radius } => {
+                let (pa, ba) = (p - a, b - a);
+                let h = (glm::dot(&pa, &ba) / glm::dot(&ba, &ba).max(f32::EPSILON)).clamp(0.0, 1.0);
+                (pa - ba * h).norm() - radius
+            }
+            Sdf::Translate { shape, offset } => shape.distance(&(p - offset)),
+            Sdf::SmoothUnion { a, b, k } => {
+                let (da, db) = (a.distance(p), b.distance(p));
+
+                if *k <= 0.0 {
+                    return da.min(db);
+                }
+
+                let h = (0.5 + 0.5 * (db - da) / k).clamp(0.0, 1.0);
+                db + (da - db) * h - k * h * (1.0 - h)
+            }
+            Sdf::SmoothSubtraction { a, b, k } => {
+                let (da, db) = (a.distance(p), b.distance(p));
+
+                if *k <= 0.0 {
+                    return da.max(-db);
+                }
+
+                let h = (0.5 - 0.5 * (da + db) / k).clamp(0.0, 1.0);
+                da + (-db - da) * h + k * h * (1.0 - h)
+            }
+            Sdf::Repeat { shape, period } => {
+                let local = Vec3::from_fn(|i, _| {
+                    let (c, n) = (p[i], period[i]);
+                    if n > 0.0 { c - n * (c / n).round() } else { c }
+                });
+
+                shape.distance(&local)
+            }
+        }
+    }
+
+    // Gradient by central differences, which points out of the surface
+    pub fn gradient(&self, p: &Vec3, h: f32) -> Vec3 {
+        let axis = |e: Vec3| self.distance(&(p + e * h)) - self.distance(&(p - e * h));
+
+        glm::vec3(axis(Vec3::x()), axis(Vec3::y()), axis(Vec3::z())) / (2.0 * h)
+    }
+}
+
+// Object whose surface is where an Sdf is zero, found by sphere tracing:
+// stepping along the ray by the distance to the nearest surface, which can
+// never overshoot it. The shape is marched in object space, so the transform
+// may stretch it like any other object's.
+#[allow(dead_code)]
+pub struct SdfObject {
+    pub sdf: Sdf,
+    pub transform: Mat4,
+    pub material: Material,
+    pub name: String,
+    // Close enough to count as on the surface, in object space
+    pub epsilon: f32,
+    pub max_steps: usize,
+    // How far along the ray to look before giving up, in object space
+    pub max_distance: f32,
+}
+
+#[allow(dead_code)]
+impl SdfObject {
+    pub fn new(sdf: Sdf) -> SdfObject {
+        SdfObject::build(sdf, &Mat4::identity())
+    }
+
+    pub fn build(sdf: Sdf, t: &Mat4) -> SdfObject {
+        SdfObject {
+            sdf,
+            transform: *t,
+            material: Material::new(),
+            name: String::from("sdf"),
+            epsilon: 0.0001,
+            max_steps: 512,
+            max_distance: 100.0,
+        }
+    }
+
+    // Distance along a unit ray to where it next crosses the surface, from
+    // either side, starting at `start`
+    fn march(&self, origin: &Vec3, direction: &Vec3, start: f32) -> Option<f32> {
+        let mut t = start;
+
+        for _ in 0..self.max_steps {
+            let d = self.sdf.distance(&(origin + direction * t)).abs();
+
+            if d < self.epsilon {
+                return Some(t);
+            }
+
+            t += d;
+
+            if t > self.max_distance {
+                return None;
+            }
+        }
+
+        None
+    }
+
+    // Where along the ray it first really passes from `inside` to the other
+    // side, and a distance just past there to march on from. Marching only
+    // finds where the ray comes within epsilon of the surface, which a ray
+    // grazing it does without going through, so from each such point it edges
+    // along until it's clear of the surface and checks which side that is.
+    fn crossing(&self, origin: &Vec3, direction: &Vec3, start: f32, inside: bool) -> Option<(f32, f32)> {
+        let mut t = start;
+
+        loop {
+            let hit = self.march(origin, direction, t)?;
+            t = hit;
+
+            let mut d = self.sdf.distance(&(origin + direction * t));
+
+            for _ in 0..self.max_steps {
+                if d.abs() >= self.epsilon || t > self.max_distance {
+                    break;
+                }
+
+                t += self.epsilon;
+                d = self.sdf.distance(&(origin + direction * t));
+            }
+
+            if (d < 0.0) != inside {
+                return Some((hit, t));
+            }
+
+            if t > self.max_distance {
+                return None;
+            }
+        }
+    }
+}
+
+impl Hittable for SdfObject {
+    // Every crossing ahead of the ray up to max_distance, entering and leaving
+    // in turn; grazes that don't go through aren't crossings. A ray starting
+    // inside also gets where it came in, behind it, so crossings still pair up
+    // for refraction and volumes.
+    fn intersect(&self, r: &Ray) -> Vec<Intersection<'_>> {
+        let r2 = Ray::transform(r, &glm::inverse(&self.transform_at(r.time)));
+        let length = r2.direction.norm();
+
+        if length == 0.0 {
+            return vec![];
+        }
+
+        let direction = r2.direction / length;
+        let mut xs = vec![];
+
+        let mut inside = self.sdf.distance(&r2.origin) < 0.0;
+
+        if inside {
+            if let Some((t, _)) = self.crossing(&r2.origin, &-direction, 0.0, true) {
+                xs.push(Intersection::build(-t / length, self));
+            }
+        }
+
+        let mut t = 0.0;
+
+        while let Some((hit, after)) = self.crossing(&r2.origin, &direction, t, inside) {
+            xs.push(Intersection::build(hit / length, self));
+            inside = !inside;
+            t = after;
+        }
+
+        xs
+    }
+
+    fn normal_at_time(&self, p: &Vec3, time: f32) -> Vec3 {
+        let inverse = glm::inverse(&self.transform_at(time));
+
+        let object_point = glm::vec4_to_vec3(&(inverse * glm::vec4(p.x, p.y, p.z, 1.0)));
+        let object_normal = self.sdf.gradient(&object_point, self.epsilon * 10.0);
+        let world_normal = glm::transpose(&inverse) * glm::vec4(object_normal.x, object_normal.y, object_normal.z, 0.0);
+
+        glm::vec4_to_vec3(&world_normal).normalize()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn transform(&self) -> &Mat4 {
+        &self.transform
+    }
+}
@@ -0,0 +1,210 @@
+extern crate nalgebra_glm as glm;
+
+use std::f32::consts::FRAC_PI_2;
+
+use glm::Vec3;
+
+use crate::environment::Environment;
+use crate::image::Image;
+use crate::light::{DirectionalLight, Light, LightSample};
+use crate::rng::Rng;
+
+// Size of the table the sky is baked into for importance sampling
+const TABLE_WIDTH: usize = 128;
+const TABLE_HEIGHT: usize = 64;
+
+// Preetham, Shirley and Smits' analytic daylight model ("A Practical Analytic
+// Model for Daylight", 1999). The sky's luminance and chromaticity follow
+// Perez's formula, fitted to the sun's position and the air's turbidity (2 is
+// very clear, 10 hazy). Below the horizon it's black.
+//
+// The sun is at `elevation` above the horizon and `azimuth` from -z toward +x,
+// both in radians. The model's luminance is in kcd/m^2, so `scale` brings it
+// down to the renderer's range.
+#[allow(dead_code)]
+pub struct Sky {
+    pub sun: Vec3,
+    pub turbidity: f32,
+    pub scale: f32,
+    // Light samples per shading point
+    pub samples: usize,
+    theta_sun: f32,
+    // Zenith luminance and chromaticity, then Perez coefficients, for Y, x and y
+    zenith: [f32; 3],
+    perez: [[f32; 5]; 3],
+    table: Environment,
+}
+
+#[allow(dead_code)]
+impl Sky {
+    pub fn build(elevation: f32, azimuth: f32, turbidity: f32) -> Sky {
+        let t = turbidity;
+        let theta_sun = (FRAC_PI_2 - elevation).clamp(0.0, FRAC_PI_2);
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * theta_sun);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let (t2, th, th2, th3) = (t * t, theta_sun, theta_sun * theta_sun, theta_sun.powi(3));
+        let zenith_x = t2 * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let zenith_chroma_y = t2 * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let mut sky = Sky {
+            sun: Sky::sun_direction(elevation, azimuth),
+            turbidity,
+            scale: 0.05,
+            samples: 4,
+            theta_sun,
+            zenith: [zenith_y, zenith_x, zenith_chroma_y],
+            perez,
+            table: Environment::build(Image::new(1, 1)),
+        };
+
+        // Unscaled, so changing `scale` later doesn't need a new table
+        let pixels = (0..TABLE_WIDTH * TABLE_HEIGHT)
+            .map(|i| {
+                let u = ((i % TABLE_WIDTH) as f32 + 0.5) / TABLE_WIDTH as f32;
+                let v = ((i / TABLE_WIDTH) as f32 + 0.5) / TABLE_HEIGHT as f32;
+                sky.unscaled(&Environment::direction(u, v))
+            })
+            .collect();
+
+        sky.table = Environment::build(Image::build(TABLE_WIDTH, TABLE_HEIGHT, pixels));
+        sky
+    }
+
+    pub fn sun_direction(elevation: f32, azimuth: f32) -> Vec3 {
+        glm::vec3(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        )
+    }
+
+    // Zenith luminance in kcd/m^2
+    pub fn zenith_luminance(&self) -> f32 {
+        self.zenith[0]
+    }
+
+    pub fn radiance(&self, direction: &Vec3) -> Vec3 {
+        self.unscaled(direction) * self.scale
+    }
+
+    // Fraction of sunlight that makes it through the atmosphere in red, green
+    // and blue: Rayleigh scattering plus Preetham's turbidity-dependent haze,
+    // over the air mass of Kasten and Young. Close to white at noon, orange
+    // near the horizon.
+    pub fn sun_transmittance(&self) -> Vec3 {
+        let degrees = self.theta_sun.to_degrees();
+        let air_mass = 1.0 / (self.theta_sun.cos() + 0.50572 * (96.07995 - degrees).powf(-1.6364));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+
+        // Wavelengths in micrometres
+        glm::vec3(0.68_f32, 0.55, 0.44).map(|l| {
+            let rayleigh = 0.008735 * l.powf(-4.08);
+            let haze = beta * l.powf(-1.3);
+
+            (-air_mass * (rayleigh + haze)).exp()
+        })
+    }
+
+    // A directional light from the sun, with `intensity` its strength above
+    // the atmosphere
+    pub fn sun_light(&self, intensity: f32) -> DirectionalLight {
+        DirectionalLight::build(&-self.sun, &(self.sun_transmittance() * intensity))
+    }
+
+    fn unscaled(&self, direction: &Vec3) -> Vec3 {
+        let d = direction.normalize();
+
+        if d.y <= 0.0 {
+            return Vec3::zeros();
+        }
+
+        let theta = d.y.acos();
+        let gamma = glm::dot(&d, &self.sun).clamp(-1.0, 1.0).acos();
+
+        let [big_y, x, y] = [0, 1, 2].map(|i| {
+            self.zenith[i] * self.perez_f(i, theta, gamma) / self.perez_f(i, 0.0, self.theta_sun)
+        });
+
+        xyy_to_rgb(x, y, big_y)
+    }
+
+    fn perez_f(&self, i: usize, theta: f32, gamma: f32) -> f32 {
+        let [a, b, c, d, e] = self.perez[i];
+        let cos_gamma = gamma.cos();
+
+        (1.0 + a * (b / theta.cos().max(0.01)).exp()) * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+    }
+}
+
+impl Light for Sky {
+    // Average radiance, for the ambient term
+    fn intensity(&self) -> Vec3 {
+        self.table.intensity() * self.scale
+    }
+
+    // Directions come from the baked table, radiance from the model itself
+    fn samples(&self, _point: &Vec3, rng: &mut Rng) -> Vec<LightSample> {
+        (0..self.samples)
+            .filter_map(|_| {
+                let (direction, pdf) = self.table.sample(rng.next_f32(), rng.next_f32());
+                (pdf > 0.0).then(|| LightSample::from_radiance(&direction, &self.radiance(&direction), pdf))
+            })
+            .collect()
+    }
+
+    fn hit(&self, _point: &Vec3, direction: &Vec3) -> Option<LightSample> {
+        let pdf = self.table.pdf(direction);
+        (pdf > 0.0).then(|| LightSample::from_radiance(direction, &self.radiance(direction), pdf))
+    }
+
+    fn background(&self, direction: &Vec3) -> Option<Vec3> {
+        Some(self.radiance(direction))
+    }
+}
+
+// CIE xyY to linear sRGB
+fn xyy_to_rgb(x: f32, y: f32, big_y: f32) -> Vec3 {
+    if y <= 0.0 {
+        return Vec3::zeros();
+    }
+
+    let big_x = x / y * big_y;
+    let big_z = (1.0 - x - y) / y * big_y;
+
+    glm::vec3(
+        3.2406 * big_x - 1.5372 * big_y - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * big_y + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * big_y + 1.0570 * big_z,
+    )
+    .map(|c| c.max(0.0))
+}
@@ -5,6 +5,9 @@ use glm::Mat4;
 
 use crate::hittable::Hittable;
 use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::motion::Motion;
+use crate::polynomial;
 use crate::ray::Ray;
 
 #[allow(dead_code)]
@@ -12,6 +15,10 @@ pub struct Sphere {
     origin: Vec3,
     radius: f32,
     pub transform: Mat4,
+    // Replaces `transform` while the shutter is open, if set
+    pub motion: Option<Motion>,
+    pub material: Material,
+    pub name: String,
 }
 
 #[allow(dead_code)]
@@ -21,14 +28,20 @@ impl Sphere {
             origin: glm::vec3(0.0, 0.0, 0.0),
             radius: 1.0,
             transform: Mat4::identity(),
+            motion: None,
+            material: Material::new(),
+            name: String::from("sphere"),
         }
     }
 
     pub fn build(o: &Vec3, r: f32, t: &Mat4) -> Sphere {
         Sphere {
-            origin: o.clone(),
+            origin: *o,
             radius: r,
-            transform: t.clone(),
+            transform: *t,
+            motion: None,
+            material: Material::new(),
+            name: String::from("sphere"),
         }
     }
 
@@ -42,27 +55,47 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-    fn intersect(&self, r: &Ray) -> Vec<Intersection> {
-        let r2 = Ray::transform(&r, &glm::inverse(&self.transform));
+    fn intersect(&self, r: &Ray) -> Vec<Intersection<'_>> {
+        let r2 = Ray::transform(r, &glm::inverse(&self.transform_at(r.time)));
 
         let sphere_to_ray: Vec3 = r2.origin - glm::Vec3::zeros();
 
-        let a: f32 = glm::dot(&r2.direction, &r2.direction);
-        let b: f32 = 2.0 * glm::dot(&r2.direction, &sphere_to_ray);
-        let c: f32 = glm::dot(&sphere_to_ray, &sphere_to_ray) - 1.0;
+        let a = glm::dot(&r2.direction, &r2.direction) as f64;
+        let b = 2.0 * glm::dot(&r2.direction, &sphere_to_ray) as f64;
+        let c = (glm::dot(&sphere_to_ray, &sphere_to_ray) - 1.0) as f64;
 
-        let discriminant: f32 = b * b - 4.0 * a * c;
+        polynomial::solve_quadratic(a, b, c)
+            .iter()
+            .map(|&t| Intersection::build(t as f32, self))
+            .collect()
+    }
 
-        if discriminant < 0.0 {
-            return vec![];
-        }
+    fn normal_at_time(&self, p: &Vec3, time: f32) -> Vec3 {
+        let inverse = glm::inverse(&self.transform_at(time));
+
+        let object_point = inverse * glm::vec4(p.x, p.y, p.z, 1.0);
+        let object_normal = glm::vec4(object_point.x, object_point.y, object_point.z, 0.0);
+        let world_normal = glm::transpose(&inverse) * object_normal;
+
+        glm::vec4_to_vec3(&world_normal).normalize()
+    }
 
-        let t1 = (-b - f32::sqrt(discriminant)) / (2.0 * a);
-        let t2 = (-b + f32::sqrt(discriminant)) / (2.0 * a);
+    fn name(&self) -> &str {
+        &self.name
+    }
 
-        let i1 = Intersection::build(t1, self);
-        let i2 = Intersection::build(t2, self);
+    fn material(&self) -> &Material {
+        &self.material
+    }
 
-        vec![i1, i2]
+    fn transform(&self) -> &Mat4 {
+        &self.transform
+    }
+
+    fn transform_at(&self, time: f32) -> Mat4 {
+        match &self.motion {
+            Some(m) => m.at(time),
+            None => self.transform,
+        }
     }
 }
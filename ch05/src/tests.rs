@@ -506,3 +506,3759 @@ mod sphere_transform_test {
         assert_eq!(xs.len(), 0);
     }
 }
+
+#[cfg(test)]
+mod render_test {
+    extern crate nalgebra_glm as glm;
+
+    use crate::hittable::Hittable;
+    use crate::intersection::Intersection;
+    use crate::ray::Ray;
+    use crate::render::{self, Tile};
+    use crate::sphere::Sphere;
+
+    // Shades a pixel by casting a ray at a translated, scaled sphere
+    // Returns the hit distance bits so any difference shows up
+    fn sphere_pixel(s: &Sphere, x: usize, y: usize) -> u32 {
+        let origin = glm::vec3(0.0, 0.0, -5.0);
+        let target = glm::vec3(-3.5 + 0.07 * x as f32, 3.5 - 0.07 * y as f32, 10.0);
+        let r = Ray::build(&origin, &(target - origin).normalize());
+
+        match Intersection::hit(&s.intersect(&r)) {
+            Some(i) => i.t().to_bits(),
+            None => 0,
+        }
+    }
+
+    // Splits a 40x35 image into 16x16 tiles
+    // Checks that the tiles cover every pixel exactly once
+    #[test]
+    fn tiles_cover_image() {
+        let tiles = Tile::split(40, 35, 16);
+        let mut covered = vec![0; 40 * 35];
+
+        for t in tiles.iter() {
+            for y in t.y..t.y + t.height {
+                for x in t.x..t.x + t.width {
+                    covered[y * 40 + x] += 1;
+                }
+            }
+        }
+
+        assert_eq!(tiles.len(), 9);
+        assert_eq!(tiles[8], Tile::build(32, 32, 8, 3));
+        assert!(covered.iter().all(|&c| c == 1));
+    }
+
+    // Renders the same scene with 1 thread and with several
+    // Checks that the buffers are bit-identical
+    #[test]
+    fn parallel_matches_serial() {
+        let s = Sphere::build(
+            &glm::Vec3::zeros(),
+            1.0,
+            &(glm::translation(&glm::vec3(0.5, -0.25, 0.0)) * glm::scaling(&glm::vec3(1.5, 1.0, 1.0))),
+        );
+        let shade = |x: usize, y: usize| sphere_pixel(&s, x, y);
+
+        let serial = render::render_serial(100, 100, shade);
+
+        for threads in [1, 2, 3, 8, 64] {
+            assert_eq!(render::render(100, 100, threads, shade), serial);
+        }
+    }
+
+    // Renders an image smaller than a single tile
+    // Checks that the odd size is handled the same way
+    #[test]
+    fn parallel_small_image() {
+        let shade = |x: usize, y: usize| (y * 1000 + x) as u32;
+
+        assert_eq!(
+            render::render(7, 3, 4, shade),
+            render::render_serial(7, 3, shade)
+        );
+    }
+}
+
+#[cfg(test)]
+mod progressive_test {
+    extern crate nalgebra_glm as glm;
+
+    use crate::color;
+    use crate::progressive::{Pass, Progressive};
+    use crate::sampling::{Filter, Pattern, Sampler};
+
+    // Converts a few colors to packed pixels
+    // Checks channel order and clamping
+    #[test]
+    fn color_to_u32() {
+        assert_eq!(color::to_u32(&glm::vec3(1.0, 0.0, 0.0)), 0x00FF0000);
+        assert_eq!(color::to_u32(&glm::vec3(0.0, 1.0, 0.5)), 0x0000FF80);
+        assert_eq!(color::to_u32(&glm::vec3(-1.0, 2.0, 0.0)), 0x0000FF00);
+    }
+
+    // Runs the first pass over a gradient
+    // Checks that every pixel in a block has the block's color
+    #[test]
+    fn coarse_pass_is_blocky() {
+        let mut p = Progressive::build(40, 20, 2, Sampler::new());
+
+        assert_eq!(p.pass(0), Pass::Coarse(16));
+        assert!(p.step(|x: f32, _y: f32| glm::vec3(x / 40.0, 0.0, 0.0)));
+
+        let b = p.buffer();
+        assert_eq!(b[0], b[15]);
+        assert_eq!(b[0], b[19 * 40 + 15]);
+        assert_ne!(b[0], b[16]);
+    }
+
+    // Runs every pass with a constant shade
+    // Checks that the passes stop and the average is the shade color
+    #[test]
+    fn passes_finish() {
+        let mut p = Progressive::build(10, 10, 3, Sampler::build(Pattern::Halton, Filter::gaussian(), 5, 0));
+        let mut steps = 0;
+
+        while p.step(|_x: f32, _y: f32| glm::vec3(0.0, 0.5, 1.0)) {
+            steps += 1;
+        }
+
+        assert_eq!(steps, p.passes());
+        assert!(p.is_done());
+        assert!(p.buffer().iter().all(|&c| c == 0x000080FF));
+    }
+
+    // Renders progressively with different thread counts
+    // Checks that the accumulated jittered samples are identical
+    #[test]
+    fn passes_deterministic() {
+        let shade = |x: f32, y: f32| glm::vec3(x.fract(), y.fract(), 0.0);
+        let sampler = Sampler::build(Pattern::Stratified, Filter::tent(), 9, 7);
+        let mut a = Progressive::build(33, 17, 1, sampler);
+        let mut b = Progressive::build(33, 17, 5, sampler);
+
+        while a.step(shade) {}
+        while b.step(shade) {}
+
+        assert_eq!(a.buffer(), b.buffer());
+    }
+
+    // Restarts after finishing
+    // Checks that the schedule starts over at the coarse pass
+    #[test]
+    fn restart_resets_passes() {
+        let mut p = Progressive::build(8, 8, 1, Sampler::build(Pattern::Grid, Filter::box_filter(), 4, 0));
+
+        while p.step(|_x: f32, _y: f32| glm::vec3(1.0, 1.0, 1.0)) {}
+        p.restart();
+
+        assert!(!p.is_done());
+        assert_eq!(p.progress(), "preview 16x16");
+    }
+}
+
+#[cfg(test)]
+mod camera_test {
+    extern crate nalgebra_glm as glm;
+
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    use crate::camera::{self, Camera};
+    use crate::controls::OrbitControls;
+    use crate::perspective::PerspectiveCamera;
+
+    fn assert_vec_eq(a: &glm::Vec3, b: &glm::Vec3) {
+        float_cmp::assert_approx_eq!(f32, a.x, b.x, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.y, b.y, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.z, b.z, epsilon = 0.0001);
+    }
+
+    // Looks from the origin down -z
+    // Checks that the view transform is the identity
+    #[test]
+    fn view_transform_default() {
+        let t = camera::view_transform(
+            &glm::vec3(0.0, 0.0, 0.0),
+            &glm::vec3(0.0, 0.0, -1.0),
+            &glm::vec3(0.0, 1.0, 0.0),
+        );
+
+        assert!(glm::Mat4::eq(&t, &glm::Mat4::identity()));
+    }
+
+    // Looks from (0, 0, 8) at the origin
+    // Checks that the view transform moves the world instead of the eye
+    #[test]
+    fn view_transform_moves_world() {
+        let t = camera::view_transform(
+            &glm::vec3(0.0, 0.0, 8.0),
+            &glm::vec3(0.0, 0.0, 0.0),
+            &glm::vec3(0.0, 1.0, 0.0),
+        );
+
+        assert!(glm::Mat4::eq(&t, &glm::translation(&glm::vec3(0.0, 0.0, -8.0))));
+    }
+
+    // Pixel size of horizontal and vertical canvases
+    #[test]
+    fn camera_pixel_size() {
+        float_cmp::assert_approx_eq!(f32, PerspectiveCamera::new(200, 125, FRAC_PI_2).pixel_size(), 0.01);
+        float_cmp::assert_approx_eq!(f32, PerspectiveCamera::new(125, 200, FRAC_PI_2).pixel_size(), 0.01);
+    }
+
+    // Casts rays through the center and the corner of the canvas
+    #[test]
+    fn camera_ray_untransformed() {
+        let c = PerspectiveCamera::new(201, 101, FRAC_PI_2);
+
+        let r = c.ray_for_pixel(100.5, 50.5);
+        assert_vec_eq(&r.origin, &glm::vec3(0.0, 0.0, 0.0));
+        assert_vec_eq(&r.direction, &glm::vec3(0.0, 0.0, -1.0));
+
+        let r = c.ray_for_pixel(0.5, 0.5);
+        assert_vec_eq(&r.origin, &glm::vec3(0.0, 0.0, 0.0));
+        assert_vec_eq(&r.direction, &glm::vec3(0.66519, 0.33259, -0.66851));
+    }
+
+    // Casts a ray through the center when the camera is transformed
+    #[test]
+    fn camera_ray_transformed() {
+        let mut c = PerspectiveCamera::new(201, 101, FRAC_PI_2);
+        c.set_transform(
+            &(glm::rotation(FRAC_PI_4, &glm::vec3(0.0, 1.0, 0.0))
+                * glm::translation(&glm::vec3(0.0, -2.0, 5.0))),
+        );
+
+        let r = c.ray_for_pixel(100.5, 50.5);
+        let h = 2.0_f32.sqrt() / 2.0;
+
+        assert_vec_eq(&r.origin, &glm::vec3(0.0, 2.0, -5.0));
+        assert_vec_eq(&r.direction, &glm::vec3(h, 0.0, -h));
+    }
+
+    // Builds orbit controls from a view and reads it back
+    // Checks that the eye position survives the round trip
+    #[test]
+    fn orbit_round_trip() {
+        let from = glm::vec3(1.0, 2.0, -5.0);
+        let to = glm::vec3(0.5, 0.0, 0.0);
+        let up = glm::vec3(0.0, 1.0, 0.0);
+        let c = OrbitControls::build(&from, &to, &up);
+
+        assert_vec_eq(&c.from(), &from);
+
+        let t = c.view_transform();
+        let expected = camera::view_transform(&from, &to, &up);
+
+        for i in 0..16 {
+            float_cmp::assert_approx_eq!(f32, t[i], expected[i], epsilon = 0.0001);
+        }
+    }
+
+    // Orbits, pans and dollies the camera
+    // Checks the distance to the target is kept while orbiting and panning
+    #[test]
+    fn orbit_pan_dolly() {
+        let mut c = OrbitControls::build(
+            &glm::vec3(0.0, 0.0, -5.0),
+            &glm::vec3(0.0, 0.0, 0.0),
+            &glm::vec3(0.0, 1.0, 0.0),
+        );
+
+        c.orbit(FRAC_PI_2, 0.0);
+        float_cmp::assert_approx_eq!(f32, (c.from() - c.target).norm(), 5.0, epsilon = 0.0001);
+
+        c.pan(0.0, 0.2);
+        assert_vec_eq(&c.target, &glm::vec3(0.0, 1.0, 0.0));
+
+        c.orbit(0.0, 10.0);
+        assert!(c.pitch < FRAC_PI_2);
+
+        c.dolly(2.0_f32.ln());
+        float_cmp::assert_approx_eq!(f32, c.distance, 2.5, epsilon = 0.0001);
+    }
+}
+
+#[cfg(test)]
+mod pick_test {
+    extern crate nalgebra_glm as glm;
+
+    use std::f32::consts::FRAC_PI_2;
+
+    use crate::camera::{self, Camera};
+    use crate::hittable::Hittable;
+    use crate::perspective::PerspectiveCamera;
+    use crate::pick;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::world::World;
+
+    fn assert_vec_eq(a: &glm::Vec3, b: &glm::Vec3) {
+        float_cmp::assert_approx_eq!(f32, a.x, b.x, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.y, b.y, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.z, b.z, epsilon = 0.0001);
+    }
+
+    // Normal on a unit sphere at a non-axial point
+    #[test]
+    fn sphere_normal() {
+        let s = Sphere::new();
+        let k = 3.0_f32.sqrt() / 3.0;
+
+        assert_vec_eq(&s.normal_at(&glm::vec3(k, k, k)), &glm::vec3(k, k, k));
+    }
+
+    // Normal on a translated sphere
+    #[test]
+    fn sphere_normal_translated() {
+        let s = Sphere::build(&glm::Vec3::zeros(), 1.0, &glm::translation(&glm::vec3(0.0, 1.0, 0.0)));
+
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+
+        assert_vec_eq(
+            &s.normal_at(&glm::vec3(0.0, 1.0 + h, -h)),
+            &glm::vec3(0.0, h, -h),
+        );
+    }
+
+    // Two concentric spheres
+    // Checks that the world's intersections are sorted by t
+    #[test]
+    fn world_intersect_sorted() {
+        let mut w = World::new();
+        w.add(Sphere::build(&glm::Vec3::zeros(), 1.0, &glm::scaling(&glm::vec3(0.5, 0.5, 0.5))));
+        w.add(Sphere::new());
+
+        let r = Ray::build(&glm::vec3(0.0, 0.0, -5.0), &glm::vec3(0.0, 0.0, 1.0));
+        let ts: Vec<f32> = w.intersect(&r).iter().map(|i| i.t()).collect();
+
+        assert_eq!(ts.len(), 4);
+        float_cmp::assert_approx_eq!(f32, ts[0], 4.0);
+        float_cmp::assert_approx_eq!(f32, ts[1], 4.5);
+        float_cmp::assert_approx_eq!(f32, ts[2], 5.5);
+        float_cmp::assert_approx_eq!(f32, ts[3], 6.0);
+    }
+
+    // Picks the center pixel in front of two spheres
+    // Checks that the nearer sphere is reported with its hit data
+    #[test]
+    fn pick_center() {
+        let mut near = Sphere::new();
+        near.name = String::from("near");
+        near.material.color = glm::vec3(0.2, 0.4, 0.6);
+
+        let mut far = Sphere::new();
+        far.name = String::from("far");
+        far.transform = glm::translation(&glm::vec3(0.0, 0.0, 5.0));
+
+        let mut w = World::new();
+        w.add(far);
+        w.add(near);
+
+        let mut c = PerspectiveCamera::new(11, 11, FRAC_PI_2);
+        c.set_transform(&camera::view_transform(
+            &glm::vec3(0.0, 0.0, -5.0),
+            &glm::vec3(0.0, 0.0, 0.0),
+            &glm::vec3(0.0, 1.0, 0.0),
+        ));
+
+        let p = pick::pick(&w, &c, 5, 5).unwrap();
+
+        assert_eq!(p.name, "near");
+        float_cmp::assert_approx_eq!(f32, p.t, 4.0, epsilon = 0.0001);
+        assert_vec_eq(&p.point, &glm::vec3(0.0, 0.0, -1.0));
+        assert_vec_eq(&p.normal, &glm::vec3(0.0, 0.0, -1.0));
+        assert_eq!(p.material.color, glm::vec3(0.2, 0.4, 0.6));
+        assert_eq!(p.transforms, vec![glm::Mat4::identity()]);
+        assert!(p.to_string().starts_with("pixel (5, 5): near"));
+        assert!(p.to_string().contains("emissive (0.0000, 0.0000, 0.0000) bsdf none"));
+    }
+
+    // Picks a corner pixel that sees nothing
+    #[test]
+    fn pick_miss() {
+        let mut w = World::new();
+        w.add(Sphere::new());
+
+        let mut c = PerspectiveCamera::new(11, 11, FRAC_PI_2);
+        c.set_transform(&camera::view_transform(
+            &glm::vec3(0.0, 0.0, -5.0),
+            &glm::vec3(0.0, 0.0, 0.0),
+            &glm::vec3(0.0, 1.0, 0.0),
+        ));
+
+        assert!(pick::pick(&w, &c, 0, 0).is_none());
+    }
+}
+
+#[cfg(test)]
+mod sampling_test {
+    extern crate nalgebra_glm as glm;
+
+    use crate::progressive::Progressive;
+    use crate::render;
+    use crate::sampling::{Filter, Pattern, Sampler};
+
+    // Counts how many of the first n samples land in each cell of a side x side grid
+    fn cell_counts(s: &Sampler, side: usize) -> Vec<usize> {
+        let mut counts = vec![0; side * side];
+
+        for i in 0..s.count() {
+            let (u, v) = s.unit(3, 4, i);
+
+            assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+            counts[(v * side as f32) as usize * side + (u * side as f32) as usize] += 1;
+        }
+
+        counts
+    }
+
+    // Requests 10 grid samples
+    // Checks that the count is rounded up to a square
+    #[test]
+    fn grid_rounds_up() {
+        let s = Sampler::build(Pattern::Grid, Filter::box_filter(), 10, 0);
+
+        assert_eq!(s.count(), 16);
+        assert_eq!(s.unit(0, 0, 5), (0.375, 0.375));
+    }
+
+    // Checks that stratified, Halton and Sobol samples are spread over the pixel
+    // with one sample in each cell of a 4x4 grid
+    #[test]
+    fn patterns_stratify() {
+        for pattern in [Pattern::Grid, Pattern::Stratified, Pattern::Sobol] {
+            let s = Sampler::build(pattern, Filter::box_filter(), 16, 42);
+
+            assert!(cell_counts(&s, 4).iter().all(|&c| c == 1), "{:?}", pattern);
+        }
+
+        let s = Sampler::build(Pattern::Halton, Filter::box_filter(), 16, 42);
+        assert!(cell_counts(&s, 2).iter().all(|&c| c == 4));
+    }
+
+    // Checks that the same seed reproduces the samples and another seed doesn't
+    #[test]
+    fn seed_reproducible() {
+        for pattern in [Pattern::Stratified, Pattern::Halton, Pattern::Sobol] {
+            let a = Sampler::build(pattern, Filter::tent(), 8, 1);
+            let b = Sampler::build(pattern, Filter::tent(), 8, 1);
+            let c = Sampler::build(pattern, Filter::tent(), 8, 2);
+
+            assert_eq!(a.unit(5, 6, 3), b.unit(5, 6, 3));
+            assert_ne!(a.unit(5, 6, 3), c.unit(5, 6, 3));
+        }
+    }
+
+    // Filter weights at the center and at the edge of their footprint
+    #[test]
+    fn filter_weights() {
+        float_cmp::assert_approx_eq!(f32, Filter::box_filter().weight(0.4, -0.4), 1.0);
+        float_cmp::assert_approx_eq!(f32, Filter::box_filter().weight(0.6, 0.0), 0.0);
+        float_cmp::assert_approx_eq!(f32, Filter::tent().weight(0.5, 0.0), 0.5);
+        float_cmp::assert_approx_eq!(f32, Filter::gaussian().weight(1.5, 0.0), 0.0);
+        float_cmp::assert_approx_eq!(f32, Filter::mitchell().weight(0.0, 0.0), 64.0 / 81.0);
+        float_cmp::assert_approx_eq!(f32, Filter::mitchell().weight(2.0, 0.0), 0.0);
+        assert!(Filter::mitchell().weight(1.5, 0.0) < 0.0);
+    }
+
+    // Shades a vertical edge through the middle of pixel 2
+    // Checks that the edge pixel is blended and its neighbours aren't
+    #[test]
+    fn edge_is_antialiased() {
+        let shade = |x: f32, _y: f32| if x < 2.5 { glm::vec3(1.0, 1.0, 1.0) } else { glm::Vec3::zeros() };
+        let s = Sampler::build(Pattern::Stratified, Filter::box_filter(), 64, 0);
+
+        let edge = s.pixel(2, 0, &shade).x;
+
+        assert!(edge > 0.35 && edge < 0.65);
+        float_cmp::assert_approx_eq!(f32, s.pixel(0, 0, &shade).x, 1.0);
+        float_cmp::assert_approx_eq!(f32, s.pixel(4, 0, &shade).x, 0.0);
+    }
+
+    // Renders progressively and in one go with every filter
+    // Checks that the accumulated passes match the direct estimate
+    #[test]
+    fn progressive_matches_sampler() {
+        let shade = |x: f32, y: f32| glm::vec3((x * 0.37).sin().abs(), (y * 0.21).cos().abs(), 0.5);
+
+        for filter in [Filter::box_filter(), Filter::tent(), Filter::gaussian(), Filter::mitchell()] {
+            let s = Sampler::build(Pattern::Sobol, filter, 8, 3);
+            let mut p = Progressive::build(20, 10, 4, s);
+
+            while p.step(shade) {}
+
+            let direct = render::render(20, 10, 2, |x, y| crate::color::to_u32(&s.pixel(x, y, &shade)));
+
+            assert_eq!(p.buffer(), &direct[..]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod adaptive_test {
+    extern crate nalgebra_glm as glm;
+
+    use crate::adaptive::{Adaptive, AdaptiveStats, Sample};
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::world::World;
+
+    fn flat(_x: f32, _y: f32) -> Sample {
+        Sample {
+            color: glm::vec3(0.5, 0.5, 0.5),
+            obj: Some(1),
+        }
+    }
+
+    // Vertical edge at x = 2.3
+    fn edge(x: f32, _y: f32) -> Sample {
+        if x < 2.3 {
+            Sample { color: glm::vec3(1.0, 1.0, 1.0), obj: Some(1) }
+        }
+        else {
+            Sample { color: glm::Vec3::zeros(), obj: None }
+        }
+    }
+
+    // Samples a flat pixel
+    // Checks that only the four corners are cast
+    #[test]
+    fn flat_pixel_four_rays() {
+        let a = Adaptive::build(0.1, 3);
+        let stats = AdaptiveStats::new();
+
+        let c = a.pixel(0, 0, &flat, &stats);
+
+        assert_eq!(c, glm::vec3(0.5, 0.5, 0.5));
+        assert_eq!(stats.rays(), 4);
+        assert_eq!(stats.budget(), 81);
+        assert_eq!(stats.saved(), 77);
+    }
+
+    // Samples a pixel with an edge through it
+    // Checks that it subdivides and lands near the covered fraction
+    #[test]
+    fn edge_pixel_subdivides() {
+        let a = Adaptive::build(0.1, 4);
+        let stats = AdaptiveStats::new();
+
+        let c = a.pixel(2, 0, &edge, &stats);
+
+        assert!(stats.rays() > 4);
+        assert!(stats.rays() < stats.budget());
+        float_cmp::assert_approx_eq!(f32, c.x, 0.3, epsilon = 0.05);
+    }
+
+    // Same color on both sides of an edge but different objects
+    // Checks that the object change alone triggers subdivision
+    #[test]
+    fn object_change_subdivides() {
+        let two_objects = |x: f32, _y: f32| Sample {
+            color: glm::vec3(1.0, 0.0, 0.0),
+            obj: Some(if x < 0.5 { 1 } else { 2 }),
+        };
+        let a = Adaptive::build(0.1, 2);
+        let stats = AdaptiveStats::new();
+
+        a.pixel(0, 0, &two_objects, &stats);
+
+        assert!(stats.rays() > 4);
+    }
+
+    // Checks that a hit in the world reports which object was hit
+    #[test]
+    fn world_sample_object() {
+        let mut w = World::new();
+        w.add(Sphere::new());
+
+        let hit = w.sample_at(&Ray::build(&glm::vec3(0.0, 0.0, -5.0), &glm::vec3(0.0, 0.0, 1.0)));
+        let miss = w.sample_at(&Ray::build(&glm::vec3(0.0, 5.0, -5.0), &glm::vec3(0.0, 0.0, 1.0)));
+
+        assert!(hit.obj.is_some());
+        assert_eq!(miss.obj, None);
+        assert_eq!(miss.color, glm::Vec3::zeros());
+    }
+}
+
+#[cfg(test)]
+mod lens_test {
+    extern crate nalgebra_glm as glm;
+
+    use std::f32::consts::FRAC_PI_2;
+
+    use crate::camera::Camera;
+    use crate::perspective::{Aperture, PerspectiveCamera};
+
+    // Checks that a closed aperture gives the pinhole ray wherever on the lens we ask
+    #[test]
+    fn closed_aperture_is_pinhole() {
+        let c = PerspectiveCamera::new(201, 101, FRAC_PI_2);
+        let pinhole = c.center_ray(10.5, 20.5);
+        let r = c.ray_for_pixel_lens(10.5, 20.5, 0.9, 0.1);
+
+        assert_eq!(r.origin, pinhole.origin);
+        assert_eq!(r.direction, pinhole.direction);
+    }
+
+    // Opens the aperture and focuses 4 units away
+    // Checks that rays from all over the lens meet on the focal plane
+    #[test]
+    fn lens_rays_converge() {
+        let mut c = PerspectiveCamera::new(201, 101, FRAC_PI_2);
+        c.aperture = 0.5;
+        c.focal_distance = 4.0;
+
+        let center = c.center_ray(30.5, 70.5);
+        let focus = center.position(-4.0 / center.direction.z);
+
+        for (u, v) in [(0.1, 0.2), (0.9, 0.5), (0.5, 0.99), (0.3, 0.7)] {
+            let r = c.ray_for_pixel_lens(30.5, 70.5, u, v);
+            let p = r.position((-4.0 - r.origin.z) / r.direction.z);
+
+            assert!(r.origin.xy().norm() > 0.0);
+            assert!(r.origin.xy().norm() <= 0.5 + 1e-5);
+            float_cmp::assert_approx_eq!(f32, p.x, focus.x, epsilon = 0.0001);
+            float_cmp::assert_approx_eq!(f32, p.y, focus.y, epsilon = 0.0001);
+        }
+    }
+
+    // Samples both aperture shapes on a grid
+    // Checks that disk samples stay in the unit disk and square-blade samples in the diamond
+    #[test]
+    fn aperture_shapes() {
+        let disk = Aperture::Disk;
+        let diamond = Aperture::Polygon { sides: 4, rotation: 0.0 };
+
+        for i in 0..16 {
+            for j in 0..16 {
+                let (u, v) = ((i as f32 + 0.5) / 16.0, (j as f32 + 0.5) / 16.0);
+
+                let (x, y) = disk.sample(u, v);
+                assert!(x * x + y * y <= 1.0 + 1e-5);
+
+                let (x, y) = diamond.sample(u, v);
+                assert!(x.abs() + y.abs() <= 1.0 + 1e-5);
+            }
+        }
+    }
+
+    // Checks that an open aperture picks the same lens point for the same position
+    #[test]
+    fn lens_sample_reproducible() {
+        let mut c = PerspectiveCamera::new(64, 64, FRAC_PI_2);
+        c.aperture = 0.2;
+        c.focal_distance = 3.0;
+
+        let a = c.ray_for_pixel(12.25, 40.75);
+        let b = c.ray_for_pixel(12.25, 40.75);
+        let d = c.ray_for_pixel(12.5, 40.75);
+
+        assert_eq!(a.origin, b.origin);
+        assert_ne!(a.origin, d.origin);
+    }
+}
+
+#[cfg(test)]
+mod projection_test {
+    extern crate nalgebra_glm as glm;
+
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    use crate::camera::{self, Camera};
+    use crate::fisheye::FisheyeCamera;
+    use crate::orthographic::OrthographicCamera;
+    use crate::panoramic::PanoramicCamera;
+    use crate::perspective::PerspectiveCamera;
+
+    fn assert_vec_eq(a: &glm::Vec3, b: &glm::Vec3) {
+        float_cmp::assert_approx_eq!(f32, a.x, b.x, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.y, b.y, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.z, b.z, epsilon = 0.0001);
+    }
+
+    // Checks that every camera looks down -z through the image center
+    #[test]
+    fn center_looks_forward() {
+        let cameras: Vec<Box<dyn Camera>> = vec![
+            Box::new(PerspectiveCamera::new(100, 50, FRAC_PI_2)),
+            Box::new(OrthographicCamera::new(100, 50, 4.0)),
+            Box::new(FisheyeCamera::new(100, 50, PI)),
+            Box::new(PanoramicCamera::new(100, 50)),
+        ];
+
+        for c in cameras.iter() {
+            let r = c.ray_for_pixel(50.0, 25.0);
+
+            assert_vec_eq(&r.direction, &glm::vec3(0.0, 0.0, -1.0));
+        }
+    }
+
+    // Orthographic rays are parallel and start on a 4 unit wide rectangle
+    #[test]
+    fn orthographic_rays_parallel() {
+        let c = OrthographicCamera::new(100, 50, 4.0);
+
+        let r = c.ray_for_pixel(0.0, 0.0);
+        assert_vec_eq(&r.origin, &glm::vec3(2.0, 1.0, 0.0));
+        assert_vec_eq(&r.direction, &glm::vec3(0.0, 0.0, -1.0));
+
+        let r = c.ray_for_pixel(100.0, 50.0);
+        assert_vec_eq(&r.origin, &glm::vec3(-2.0, -1.0, 0.0));
+        assert_vec_eq(&r.direction, &glm::vec3(0.0, 0.0, -1.0));
+    }
+
+    // Moves an orthographic camera back and to the side
+    // Checks that the view transform moves the ray origins too
+    #[test]
+    fn orthographic_transformed() {
+        let mut c = OrthographicCamera::new(10, 10, 2.0);
+        c.set_transform(&camera::view_transform(
+            &glm::vec3(3.0, 0.0, -5.0),
+            &glm::vec3(3.0, 0.0, 0.0),
+            &glm::vec3(0.0, 1.0, 0.0),
+        ));
+
+        let r = c.ray_for_pixel(5.0, 5.0);
+
+        assert_vec_eq(&r.origin, &glm::vec3(3.0, 0.0, -5.0));
+        assert_vec_eq(&r.direction, &glm::vec3(0.0, 0.0, 1.0));
+    }
+
+    // A 180 degree fisheye sees sideways at the edge of the image circle
+    // and the angle grows linearly on the way there
+    #[test]
+    fn fisheye_equidistant() {
+        let c = FisheyeCamera::new(100, 100, PI);
+
+        assert_vec_eq(&c.ray_for_pixel(100.0, 50.0).direction, &glm::vec3(-1.0, 0.0, 0.0));
+        assert_vec_eq(&c.ray_for_pixel(50.0, 0.0).direction, &glm::vec3(0.0, 1.0, 0.0));
+
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        assert_vec_eq(&c.ray_for_pixel(75.0, 50.0).direction, &glm::vec3(-h, 0.0, -h));
+    }
+
+    // Checks the panorama's seams, quarter turns and poles
+    #[test]
+    fn panorama_covers_sphere() {
+        let c = PanoramicCamera::new(200, 100);
+
+        assert_vec_eq(&c.ray_for_pixel(0.0, 50.0).direction, &glm::vec3(0.0, 0.0, 1.0));
+        assert_vec_eq(&c.ray_for_pixel(150.0, 50.0).direction, &glm::vec3(-1.0, 0.0, 0.0));
+        assert_vec_eq(&c.ray_for_pixel(50.0, 50.0).direction, &glm::vec3(1.0, 0.0, 0.0));
+        assert_vec_eq(&c.ray_for_pixel(100.0, 0.0).direction, &glm::vec3(0.0, 1.0, 0.0));
+        assert_vec_eq(&c.ray_for_pixel(100.0, 100.0).direction, &glm::vec3(0.0, -1.0, 0.0));
+    }
+}
+
+#[cfg(test)]
+mod rig_test {
+    extern crate nalgebra_glm as glm;
+
+    use std::f32::consts::FRAC_PI_2;
+
+    use crate::camera::Camera;
+    use crate::image::Image;
+    use crate::ray::Ray;
+    use crate::rig::{CubeFace, CubeMapRig, StereoOutput, StereoRig};
+    use crate::sampling::{Filter, Pattern, Sampler};
+
+    fn center_sampler() -> Sampler {
+        Sampler::build(Pattern::Grid, Filter::box_filter(), 1, 0)
+    }
+
+    // Colors each pixel by its ray direction so images can be compared
+    fn direction(r: &Ray) -> glm::Vec3 {
+        r.direction
+    }
+
+    // Checks that the eyes are interocular apart along the camera's sideways axis
+    // and look in the same direction
+    #[test]
+    fn stereo_eyes_offset() {
+        let rig = StereoRig::build(10, 10, FRAC_PI_2, 0.2, StereoOutput::SideBySide);
+        let [l, r] = rig.eyes(
+            &glm::vec3(0.0, 0.0, -5.0),
+            &glm::vec3(0.0, 0.0, 0.0),
+            &glm::vec3(0.0, 1.0, 0.0),
+        );
+
+        let lr = l.center_ray(5.0, 5.0);
+        let rr = r.center_ray(5.0, 5.0);
+
+        float_cmp::assert_approx_eq!(f32, (lr.origin - rr.origin).norm(), 0.2, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, lr.origin.y, rr.origin.y, epsilon = 0.0001);
+        assert!(glm::dot(&lr.direction, &rr.direction) > 0.9999);
+
+        // The left eye is on the side that shows up on the left of the image
+        let image_left = l.center_ray(0.5, 5.0).direction - lr.direction;
+        assert!(glm::dot(&(lr.origin - rr.origin), &image_left) > 0.0);
+    }
+
+    // Checks the output layouts
+    #[test]
+    fn stereo_outputs() {
+        let from = glm::vec3(0.0, 0.0, -5.0);
+        let to = glm::vec3(0.0, 0.0, 0.0);
+        let up = glm::vec3(0.0, 1.0, 0.0);
+        let left_only = |r: &Ray| if r.origin.x < 0.0 { glm::vec3(1.0, 0.0, 0.0) } else { glm::vec3(0.0, 1.0, 1.0) };
+
+        let sbs = StereoRig::build(8, 4, FRAC_PI_2, 0.5, StereoOutput::SideBySide)
+            .render(&from, &to, &up, &center_sampler(), 2, &left_only);
+        assert_eq!((sbs.width, sbs.height), (16, 4));
+        assert_ne!(sbs.get(0, 0), sbs.get(8, 0));
+
+        let ana = StereoRig::build(8, 4, FRAC_PI_2, 0.5, StereoOutput::Anaglyph)
+            .render(&from, &to, &up, &center_sampler(), 2, &left_only);
+        assert_eq!((ana.width, ana.height), (8, 4));
+        assert_eq!(ana.get(3, 2), glm::vec3(1.0, 1.0, 1.0));
+    }
+
+    // Checks that each face looks along its axis
+    #[test]
+    fn cube_faces_look_along_axes() {
+        let rig = CubeMapRig::build(16);
+        let center = glm::vec3(1.0, 2.0, 3.0);
+
+        for face in CubeFace::all() {
+            let r = rig.camera(&center, face).center_ray(8.0, 8.0);
+
+            assert!((r.origin - center).norm() < 0.0001);
+            assert!(glm::dot(&r.direction, &face.orientation().0) > 0.9999);
+        }
+    }
+
+    // Renders the cross with pixels colored by ray direction
+    // Checks that the faces meet along their shared edges
+    #[test]
+    fn cube_cross_seams_match() {
+        let n = 16;
+        let cross: Image = CubeMapRig::build(n).render(&glm::Vec3::zeros(), &center_sampler(), 4, &direction);
+
+        assert_eq!((cross.width, cross.height), (4 * n, 3 * n));
+
+        // (left of the seam, right of the seam) along the middle row,
+        // and (above, below) down the middle column
+        let seams = [
+            ((n - 1, n + n / 2), (n, n + n / 2)),
+            ((2 * n - 1, n + n / 2), (2 * n, n + n / 2)),
+            ((3 * n - 1, n + n / 2), (3 * n, n + n / 2)),
+            ((n + n / 2, n - 1), (n + n / 2, n)),
+            ((n + n / 2, 2 * n - 1), (n + n / 2, 2 * n)),
+        ];
+
+        for (a, b) in seams {
+            let d = glm::dot(&cross.get(a.0, a.1), &cross.get(b.0, b.1));
+
+            assert!(d > 0.99, "seam between {:?} and {:?}", a, b);
+        }
+    }
+
+    // Checks the PPM header and that long rows are wrapped
+    #[test]
+    fn image_to_ppm() {
+        let mut image = Image::new(10, 2);
+        image.pixels.iter_mut().for_each(|c| *c = glm::vec3(1.0, 0.8, 0.6));
+
+        let ppm = image.to_ppm();
+        let lines: Vec<&str> = ppm.lines().collect();
+
+        assert_eq!(&lines[..3], &["P3", "10 2", "255"]);
+        assert_eq!(lines[3], "255 204 153 255 204 153 255 204 153 255 204 153 255 204 153 255 204");
+        assert_eq!(lines[4], "153 255 204 153 255 204 153 255 204 153 255 204 153");
+        assert!(ppm.ends_with('\n'));
+    }
+}
+
+#[cfg(test)]
+mod motion_test {
+    extern crate nalgebra_glm as glm;
+
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    use crate::camera::Camera;
+    use crate::hittable::Hittable;
+    use crate::motion::Motion;
+    use crate::perspective::PerspectiveCamera;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+
+    fn assert_mat_eq(a: &glm::Mat4, b: &glm::Mat4) {
+        for i in 0..16 {
+            float_cmp::assert_approx_eq!(f32, a[i], b[i], epsilon = 0.0001);
+        }
+    }
+
+    // Checks that rays start at time 0 and transforming keeps the time
+    #[test]
+    fn ray_time() {
+        let r = Ray::build(&glm::vec3(1.0, 2.0, 3.0), &glm::vec3(0.0, 1.0, 0.0));
+        assert_eq!(r.time, 0.0);
+
+        let r = Ray::build_at(&glm::vec3(1.0, 2.0, 3.0), &glm::vec3(0.0, 1.0, 0.0), 0.25);
+        let r2 = Ray::transform(&r, &glm::translation(&glm::vec3(3.0, 4.0, 5.0)));
+        assert_eq!(r2.time, 0.25);
+    }
+
+    // Checks the endpoints and the midpoint of a translation and scale
+    #[test]
+    fn motion_lerps_translation_and_scale() {
+        let m = Motion::build(
+            &glm::translation(&glm::vec3(0.0, 0.0, 0.0)),
+            &(glm::translation(&glm::vec3(4.0, 2.0, 0.0)) * glm::scaling(&glm::vec3(3.0, 3.0, 3.0))),
+        );
+
+        assert_eq!(m.at(0.0), glm::Mat4::identity());
+        assert_eq!(m.at(1.0), m.close);
+        assert_mat_eq(
+            &m.at(0.5),
+            &(glm::translation(&glm::vec3(2.0, 1.0, 0.0)) * glm::scaling(&glm::vec3(2.0, 2.0, 2.0))),
+        );
+    }
+
+    // Rotates half a turn around y
+    // Checks that the midpoint is a quarter turn with the scale kept
+    #[test]
+    fn motion_slerps_rotation() {
+        let y = glm::vec3(0.0, 1.0, 0.0);
+        let m = Motion::build(
+            &glm::scaling(&glm::vec3(2.0, 2.0, 2.0)),
+            &(glm::rotation(PI * 0.9, &y) * glm::scaling(&glm::vec3(2.0, 2.0, 2.0))),
+        );
+
+        assert_mat_eq(
+            &m.at(0.5),
+            &(glm::rotation(PI * 0.45, &y) * glm::scaling(&glm::vec3(2.0, 2.0, 2.0))),
+        );
+    }
+
+    // A sphere moving from x = 0 to x = 4
+    // Checks that where a ray hits it depends on the ray's time
+    #[test]
+    fn moving_sphere_intersect() {
+        let mut s = Sphere::new();
+        s.motion = Some(Motion::build(
+            &glm::Mat4::identity(),
+            &glm::translation(&glm::vec3(4.0, 0.0, 0.0)),
+        ));
+
+        let d = glm::vec3(0.0, 0.0, 1.0);
+
+        assert_eq!(s.intersect(&Ray::build_at(&glm::vec3(0.0, 0.0, -5.0), &d, 0.0)).len(), 2);
+        assert_eq!(s.intersect(&Ray::build_at(&glm::vec3(0.0, 0.0, -5.0), &d, 1.0)).len(), 0);
+        assert_eq!(s.intersect(&Ray::build_at(&glm::vec3(2.0, 0.0, -5.0), &d, 0.5)).len(), 2);
+        assert_eq!(s.intersect(&Ray::build_at(&glm::vec3(4.0, 0.0, -5.0), &d, 1.0)).len(), 2);
+
+        let n = s.normal_at_time(&glm::vec3(2.0, 0.0, -1.0), 0.5);
+        float_cmp::assert_approx_eq!(f32, n.z, -1.0, epsilon = 0.0001);
+    }
+
+    // Opens the shutter from 0.25 to 0.75
+    // Checks that ray times spread over the interval and repeat for the same position
+    #[test]
+    fn camera_samples_shutter() {
+        let mut c = PerspectiveCamera::new(20, 20, FRAC_PI_2);
+        c.shutter_open = 0.25;
+        c.shutter_close = 0.75;
+
+        let times: Vec<f32> = (0..64).map(|i| c.ray_for_pixel(i as f32 * 0.3, 7.5).time).collect();
+
+        assert!(times.iter().all(|t| (0.25..=0.75).contains(t)));
+        assert!(times.iter().any(|&t| t < 0.4));
+        assert!(times.iter().any(|&t| t > 0.6));
+        assert_eq!(c.ray_for_pixel(3.3, 7.5).time, c.ray_for_pixel(3.3, 7.5).time);
+        assert_eq!(c.center_ray(3.3, 7.5).time, 0.25);
+    }
+}
+
+#[cfg(test)]
+mod light_test {
+    extern crate nalgebra_glm as glm;
+
+    use std::f32::consts::FRAC_PI_4;
+
+    use crate::light::{self, AreaLight, Attenuation, DirectionalLight, Light, LightSample, PointLight, SphereLight, SpotLight};
+    use crate::material::Material;
+    use crate::rng::Rng;
+    use crate::sphere::Sphere;
+    use crate::world::World;
+
+    fn assert_vec_eq(a: &glm::Vec3, b: &glm::Vec3) {
+        float_cmp::assert_approx_eq!(f32, a.x, b.x, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.y, b.y, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.z, b.z, epsilon = 0.0001);
+    }
+
+    fn white() -> glm::Vec3 {
+        glm::vec3(1.0, 1.0, 1.0)
+    }
+
+    // Eye and light straight in front of the surface
+    // Checks full ambient + diffuse + specular, and only ambient in shadow
+    #[test]
+    fn lighting_point_light() {
+        let m = Material::new();
+        let l = PointLight::build(&glm::vec3(0.0, 0.0, -10.0), &white());
+        let samples = l.samples(&glm::Vec3::zeros(), &mut Rng::new(0));
+        let eyev = glm::vec3(0.0, 0.0, -1.0);
+        let normalv = glm::vec3(0.0, 0.0, -1.0);
+
+        assert_vec_eq(
+            &light::lighting(&m, &l, &samples, &[true], &eyev, &normalv),
+            &glm::vec3(1.9, 1.9, 1.9),
+        );
+        assert_vec_eq(
+            &light::lighting(&m, &l, &samples, &[false], &eyev, &normalv),
+            &glm::vec3(0.1, 0.1, 0.1),
+        );
+    }
+
+    // Half the samples of a light blocked
+    // Checks that diffuse + specular is halved but ambient isn't
+    #[test]
+    fn lighting_fractional() {
+        let m = Material::new();
+        let l = PointLight::build(&glm::vec3(0.0, 0.0, -10.0), &white());
+        let samples = [LightSample::build(&glm::vec3(0.0, 0.0, -1.0), 10.0, &white()); 2];
+        let n = glm::vec3(0.0, 0.0, -1.0);
+
+        assert_vec_eq(
+            &light::lighting(&m, &l, &samples, &[true, false], &n, &n),
+            &glm::vec3(1.0, 1.0, 1.0),
+        );
+    }
+
+    // Point light 2 units away
+    // Checks that no attenuation keeps the intensity and inverse square quarters it
+    #[test]
+    fn point_light_attenuation() {
+        let mut l = PointLight::build(&glm::vec3(0.0, 2.0, 0.0), &white());
+        let mut rng = Rng::new(0);
+
+        let s = l.samples(&glm::Vec3::zeros(), &mut rng)[0];
+        assert_vec_eq(&s.direction, &glm::vec3(0.0, 1.0, 0.0));
+        float_cmp::assert_approx_eq!(f32, s.distance, 2.0);
+        assert_vec_eq(&s.intensity, &white());
+
+        l.attenuation = Attenuation::inverse_square();
+        assert_vec_eq(&l.samples(&glm::Vec3::zeros(), &mut rng)[0].intensity, &glm::vec3(0.25, 0.25, 0.25));
+
+        l.attenuation = Attenuation::build(1.0, 0.5, 0.0);
+        float_cmp::assert_approx_eq!(f32, l.attenuation.factor(2.0), 0.5);
+    }
+
+    // Spot light pointing down with a 30 degree inner and 45 degree outer cone
+    // Checks full light inside, none outside and a smooth blend between
+    #[test]
+    fn spot_light_cone() {
+        let l = SpotLight::build(
+            &glm::vec3(0.0, 1.0, 0.0),
+            &glm::vec3(0.0, -1.0, 0.0),
+            std::f32::consts::FRAC_PI_6,
+            FRAC_PI_4,
+            &white(),
+        );
+        let mut rng = Rng::new(0);
+
+        float_cmp::assert_approx_eq!(f32, l.falloff(&glm::Vec3::zeros()), 1.0);
+        float_cmp::assert_approx_eq!(f32, l.falloff(&glm::vec3(0.5, 0.0, 0.0)), 1.0);
+        float_cmp::assert_approx_eq!(f32, l.falloff(&glm::vec3(1.5, 0.0, 0.0)), 0.0);
+        float_cmp::assert_approx_eq!(f32, l.falloff(&glm::vec3(0.0, 2.0, 0.0)), 0.0);
+
+        let between = (37.5_f32).to_radians().tan();
+        let f = l.falloff(&glm::vec3(between, 0.0, 0.0));
+        assert!(f > 0.0 && f < 1.0);
+        assert!(l.falloff(&glm::vec3(between * 0.95, 0.0, 0.0)) > f);
+
+        assert_vec_eq(&l.samples(&glm::vec3(1.5, 0.0, 0.0), &mut rng)[0].intensity, &glm::Vec3::zeros());
+    }
+
+    // Sun shining straight down
+    // Checks that its sample points up, infinitely far, and anything above
+    // shadows the point
+    #[test]
+    fn directional_light_shadow() {
+        let l = DirectionalLight::build(&glm::vec3(0.0, -2.0, 0.0), &white());
+        let point = glm::vec3(0.0, -5.0, 0.0);
+        let s = l.samples(&point, &mut Rng::new(0))[0];
+
+        assert_vec_eq(&s.direction, &glm::vec3(0.0, 1.0, 0.0));
+        assert!(s.distance.is_infinite());
+
+        let mut w = World::new();
+        w.add(Sphere::build(&glm::Vec3::zeros(), 1.0, &glm::translation(&glm::vec3(0.0, 100.0, 0.0))));
+
+        assert!(w.is_occluded(&point, &s, 0.0));
+        assert!(!w.is_occluded(&glm::vec3(5.0, -5.0, 0.0), &s, 0.0));
+    }
+
+    // Spot light behind a sphere
+    // Checks that spot lights cast shadows like any other light
+    #[test]
+    fn spot_light_shadow() {
+        let mut w = World::new();
+        w.add(Sphere::new());
+
+        let l = SpotLight::build(&glm::vec3(0.0, 0.0, -10.0), &glm::vec3(0.0, 0.0, 1.0), 0.2, 0.3, &white());
+
+        float_cmp::assert_approx_eq!(f32, w.intensity_at(&l, &glm::vec3(0.0, 0.0, 5.0), 0.0, &mut Rng::new(0)), 0.0);
+        float_cmp::assert_approx_eq!(f32, w.intensity_at(&l, &glm::vec3(0.0, 0.0, -5.0), 0.0, &mut Rng::new(0)), 1.0);
+    }
+
+    // Area light from the book's bonus chapter, without jitter
+    // Checks that cell samples sit in the middle of their cells
+    #[test]
+    fn area_light_cells() {
+        let mut l = AreaLight::build(
+            &glm::Vec3::zeros(),
+            &glm::vec3(2.0, 0.0, 0.0),
+            4,
+            &glm::vec3(0.0, 0.0, 1.0),
+            2,
+            &white(),
+        );
+        l.jitter = false;
+
+        let mut rng = Rng::new(0);
+
+        assert_eq!(l.samples(&glm::vec3(0.0, 1.0, 0.0), &mut rng).len(), 8);
+        assert_vec_eq(&l.uvec, &glm::vec3(0.5, 0.0, 0.0));
+        assert_vec_eq(&l.center(), &glm::vec3(1.0, 0.0, 0.5));
+        assert_vec_eq(&l.point_on_light(0, 0, &mut rng), &glm::vec3(0.25, 0.0, 0.25));
+        assert_vec_eq(&l.point_on_light(3, 1, &mut rng), &glm::vec3(1.75, 0.0, 0.75));
+    }
+
+    // Jittered area light
+    // Checks that every sample stays inside its own cell
+    #[test]
+    fn area_light_jitter() {
+        let l = AreaLight::build(
+            &glm::Vec3::zeros(),
+            &glm::vec3(2.0, 0.0, 0.0),
+            4,
+            &glm::vec3(0.0, 0.0, 1.0),
+            2,
+            &white(),
+        );
+        let mut rng = Rng::new(7);
+        let point = glm::vec3(0.0, 5.0, 0.0);
+
+        let samples = l.samples(&point, &mut rng);
+
+        assert_eq!(samples.len(), 8);
+
+        for (i, s) in samples.iter().enumerate() {
+            let p = point + s.direction * s.distance;
+            let (u, v) = ((i % 4) as f32, (i / 4) as f32);
+
+            assert!(p.x >= u * 0.5 - 0.0001 && p.x <= (u + 1.0) * 0.5 + 0.0001);
+            assert!(p.z >= v * 0.5 - 0.0001 && p.z <= (v + 1.0) * 0.5 + 0.0001);
+            float_cmp::assert_approx_eq!(f32, p.y, 0.0, epsilon = 0.0001);
+        }
+    }
+
+    // Spherical light seen from along +z
+    // Checks that samples lie on the disk facing the point
+    #[test]
+    fn sphere_light_samples() {
+        let l = SphereLight::build(&glm::vec3(1.0, 2.0, 3.0), 0.5, 3, &white());
+        let mut rng = Rng::new(3);
+        let point = glm::vec3(1.0, 2.0, 10.0);
+
+        let samples = l.samples(&point, &mut rng);
+
+        assert_eq!(samples.len(), 9);
+
+        for s in samples {
+            let p = point + s.direction * s.distance;
+
+            float_cmp::assert_approx_eq!(f32, p.z, 3.0, epsilon = 0.0001);
+            assert!(glm::distance(&p, &l.center) <= 0.5 + 0.0001);
+        }
+    }
+
+    // A wide light behind a unit sphere, seen from the other side
+    // Checks that half of the shadow rays are blocked
+    #[test]
+    fn area_light_penumbra() {
+        let mut w = World::new();
+        w.add(Sphere::new());
+
+        let mut l = AreaLight::build(
+            &glm::vec3(-4.0, -0.5, -5.0),
+            &glm::vec3(8.0, 0.0, 0.0),
+            4,
+            &glm::vec3(0.0, 1.0, 0.0),
+            1,
+            &white(),
+        );
+        l.jitter = false;
+
+        let mut rng = Rng::new(0);
+
+        float_cmp::assert_approx_eq!(f32, w.intensity_at(&l, &glm::vec3(0.0, 0.0, 5.0), 0.0, &mut rng), 0.5);
+        float_cmp::assert_approx_eq!(f32, w.intensity_at(&l, &glm::vec3(0.0, 5.0, -5.0), 0.0, &mut rng), 1.0);
+    }
+
+    // Unit sphere lit from the front
+    // Checks that the lit side is brighter than ambient and the world without
+    // lights is just black
+    #[test]
+    fn world_shade_with_lights() {
+        let mut w = World::new();
+        w.add(Sphere::new());
+
+        let r = crate::ray::Ray::build(&glm::vec3(0.0, 0.0, -5.0), &glm::vec3(0.0, 0.0, 1.0));
+        assert_eq!(w.color_at(&r), glm::Vec3::zeros());
+
+        w.add_light(PointLight::build(&glm::vec3(0.0, 0.0, -10.0), &white()));
+        assert_vec_eq(&w.color_at(&r), &glm::vec3(1.9, 1.9, 1.9));
+    }
+}
+
+#[cfg(test)]
+mod integrator_test {
+    extern crate nalgebra_glm as glm;
+
+    use crate::integrator::{Integrator, PathTracer, Whitted};
+    use crate::light::PointLight;
+    use crate::ray::Ray;
+    use crate::rng::Rng;
+    use crate::sampling;
+    use crate::sphere::Sphere;
+    use crate::world::World;
+
+    // Cosine-weighted directions around a tilted normal
+    // Checks they're unit length, in the hemisphere and average cos(theta) = 2/3
+    #[test]
+    fn cosine_hemisphere() {
+        let n = glm::vec3(1.0, 2.0, -1.0).normalize();
+        let mut rng = Rng::new(5);
+        let count = 20000;
+
+        let mean = (0..count)
+            .map(|_| {
+                let d = sampling::cosine_hemisphere(&n, rng.next_f32(), rng.next_f32());
+
+                float_cmp::assert_approx_eq!(f32, glm::length(&d), 1.0, epsilon = 0.0001);
+                assert!(glm::dot(&d, &n) >= 0.0);
+
+                glm::dot(&d, &n)
+            })
+            .sum::<f32>()
+            / count as f32;
+
+        float_cmp::assert_approx_eq!(f32, mean, 2.0 / 3.0, epsilon = 0.01);
+    }
+
+    // Checks that the Whitted integrator is the world's own shading
+    #[test]
+    fn whitted_is_color_at() {
+        let mut w = World::new();
+        w.add(Sphere::new());
+        w.add_light(PointLight::build(&glm::vec3(-10.0, 10.0, -10.0), &glm::vec3(1.0, 1.0, 1.0)));
+
+        let r = Ray::build(&glm::vec3(0.0, 0.0, -5.0), &glm::vec3(0.1, 0.2, 1.0).normalize());
+
+        assert_eq!(Whitted.li(&w, &r), w.color_at(&r));
+        assert_eq!(Whitted.sample(&w, &r).obj, w.hit(&r).map(|i| i.obj_id()));
+    }
+
+    // Black sphere that glows, with nothing else around
+    // Checks that the path tracer sees exactly its emission
+    #[test]
+    fn path_emission() {
+        let mut s = Sphere::new();
+        s.material.color = glm::Vec3::zeros();
+        s.material.emissive = glm::vec3(2.0, 1.0, 0.5);
+
+        let mut w = World::new();
+        w.add(s);
+
+        let r = Ray::build(&glm::vec3(0.0, 0.0, -5.0), &glm::vec3(0.0, 0.0, 1.0));
+
+        assert_eq!(PathTracer::new().li(&w, &r), glm::vec3(2.0, 1.0, 0.5));
+        assert_eq!(PathTracer::new().li(&w, &Ray::build(&glm::vec3(0.0, 5.0, -5.0), &glm::vec3(0.0, 0.0, 1.0))), glm::Vec3::zeros());
+    }
+
+    // One bounce off a white sphere facing a point light
+    // Checks that next-event estimation gives the diffuse color
+    #[test]
+    fn path_direct_light() {
+        let mut w = World::new();
+        w.add(Sphere::new());
+        w.add_light(PointLight::build(&glm::vec3(0.0, 0.0, -10.0), &glm::vec3(1.0, 1.0, 1.0)));
+
+        let r = Ray::build(&glm::vec3(0.0, 0.0, -5.0), &glm::vec3(0.0, 0.0, 1.0));
+        let c = PathTracer::build(1, 1).li(&w, &r);
+
+        float_cmp::assert_approx_eq!(f32, c.x, 0.9, epsilon = 0.0001);
+    }
+
+    // Inside a glowing sphere with emission 1 and albedo 0.5
+    // Checks that paths average 1 / (1 - 0.5) = 2, so bounces and Russian
+    // roulette add up to the right amount of indirect light
+    #[test]
+    fn path_indirect_converges() {
+        let mut s = Sphere::new();
+        s.material.diffuse = 0.5;
+        s.material.emissive = glm::vec3(1.0, 1.0, 1.0);
+
+        let mut w = World::new();
+        w.add(s);
+
+        let p = PathTracer::build(64, 2);
+        let mut rng = Rng::new(11);
+        let count = 20000;
+
+        let mean = (0..count)
+            .map(|_| {
+                let d = glm::vec3(rng.next_f32() - 0.5, rng.next_f32() - 0.5, rng.next_f32() - 0.5);
+                p.li(&w, &Ray::build(&glm::Vec3::zeros(), &d.normalize())).x
+            })
+            .sum::<f32>()
+            / count as f32;
+
+        float_cmp::assert_approx_eq!(f32, mean, 2.0, epsilon = 0.05);
+    }
+}
+
+#[cfg(test)]
+mod bsdf_test {
+    extern crate nalgebra_glm as glm;
+
+    use std::f32::consts::PI;
+
+    use crate::bsdf::{self, Bsdf};
+    use crate::material::Material;
+    use crate::rng::Rng;
+
+    const COUNT: usize = 20000;
+
+    // Uniform directions rarely land in a glossy lobe, so they need more
+    const UNIFORM_COUNT: usize = 100000;
+
+    fn n() -> glm::Vec3 {
+        glm::vec3(0.0, 1.0, 0.0)
+    }
+
+    fn direction(theta: f32) -> glm::Vec3 {
+        glm::vec3(theta.sin(), theta.cos(), 0.0)
+    }
+
+    fn uniform_sphere(rng: &mut Rng) -> glm::Vec3 {
+        let z = 1.0 - 2.0 * rng.next_f32();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.next_f32();
+
+        glm::vec3(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    // Fraction of light from `wo` that leaves the surface, estimated with the
+    // BSDF's own sampling
+    fn albedo(b: &Bsdf, wo: &glm::Vec3) -> f32 {
+        let mut rng = Rng::new(1);
+
+        (0..COUNT)
+            .filter_map(|_| b.sample(wo, &n(), &mut rng))
+            .map(|s| s.f.x * glm::dot(&s.wi, &n()).abs() / s.pdf)
+            .sum::<f32>()
+            / COUNT as f32
+    }
+
+    // The same fraction estimated with uniform directions, plus the integral of the pdf
+    fn albedo_uniform(b: &Bsdf, wo: &glm::Vec3) -> (f32, f32) {
+        let mut rng = Rng::new(2);
+        let (mut albedo, mut pdf) = (0.0, 0.0);
+
+        for _ in 0..UNIFORM_COUNT {
+            let wi = uniform_sphere(&mut rng);
+            albedo += b.evaluate(wo, &wi, &n()).x * glm::dot(&wi, &n()).abs() * 4.0 * PI;
+            pdf += b.pdf(wo, &wi, &n()) * 4.0 * PI;
+        }
+
+        (albedo / UNIFORM_COUNT as f32, pdf / UNIFORM_COUNT as f32)
+    }
+
+    fn rough_bsdfs() -> Vec<Bsdf> {
+        vec![
+            Bsdf::lambertian(&glm::vec3(0.8, 0.8, 0.8)),
+            Bsdf::conductor(&glm::vec3(1.0, 1.0, 1.0), 0.5),
+            Bsdf::dielectric(1.5, 0.5),
+            Bsdf::metallic_roughness(&glm::vec3(1.0, 1.0, 1.0), 0.0, 0.5),
+            Bsdf::metallic_roughness(&glm::vec3(1.0, 1.0, 1.0), 0.5, 0.6),
+        ]
+    }
+
+    // Checks the exact Fresnel equations at normal incidence and past the
+    // critical angle
+    #[test]
+    fn fresnel() {
+        float_cmp::assert_approx_eq!(f32, bsdf::fresnel_dielectric(1.0, 1.0 / 1.5), 0.04, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, bsdf::fresnel_dielectric(1.0, 1.5), 0.04, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, bsdf::fresnel_dielectric(0.5, 1.5), 1.0);
+        float_cmp::assert_approx_eq!(f32, bsdf::fresnel_dielectric(0.0, 1.0 / 1.5), 1.0, epsilon = 0.0001);
+    }
+
+    // Checks that a Lambertian surface reflects exactly its albedo and nothing
+    // below the surface
+    #[test]
+    fn lambertian() {
+        let b = Bsdf::lambertian(&glm::vec3(0.5, 0.5, 0.5));
+        let wo = direction(0.3);
+
+        float_cmp::assert_approx_eq!(f32, b.evaluate(&wo, &direction(-0.7), &n()).x, 0.5 / PI);
+        assert_eq!(b.evaluate(&wo, &-direction(0.2), &n()), glm::Vec3::zeros());
+        float_cmp::assert_approx_eq!(f32, albedo(&b, &wo), 0.5, epsilon = 0.0001);
+    }
+
+    // Checks that no BSDF gives off more light than it receives, from several angles
+    #[test]
+    fn energy_conserving() {
+        let mut bsdfs = rough_bsdfs();
+        bsdfs.push(Bsdf::conductor(&glm::vec3(1.0, 1.0, 1.0), 0.05));
+        bsdfs.push(Bsdf::dielectric(1.5, 0.05));
+
+        for b in bsdfs {
+            for theta in [0.0, 0.8, 1.4] {
+                let a = albedo(&b, &direction(theta));
+                assert!(a <= 1.01, "{:?} at {} reflects {}", b, theta, a);
+
+                // Seen from inside the dielectric too
+                let a = albedo(&b, &-direction(theta));
+                assert!(a <= 1.01, "{:?} at {} from below reflects {}", b, theta, a);
+            }
+        }
+    }
+
+    // Checks that sample, evaluate and pdf agree: the albedo estimated through
+    // sample matches one from uniform directions, and the pdf integrates to
+    // at most 1
+    #[test]
+    fn sample_matches_pdf() {
+        for b in rough_bsdfs() {
+            for wo in [direction(0.4), -direction(0.4)] {
+                let sampled = albedo(&b, &wo);
+                let (uniform, pdf) = albedo_uniform(&b, &wo);
+
+                float_cmp::assert_approx_eq!(f32, sampled, uniform, epsilon = 0.03);
+                assert!(pdf <= 1.02 && pdf > 0.5, "{:?} pdf integrates to {}", b, pdf);
+            }
+        }
+    }
+
+    // Checks that the metal's value is the same with the directions swapped
+    #[test]
+    fn conductor_reciprocal() {
+        let b = Bsdf::conductor(&glm::vec3(0.9, 0.6, 0.3), 0.4);
+        let (a, c) = (direction(0.3), direction(-0.9));
+
+        let ab = b.evaluate(&a, &c, &n());
+        let ba = b.evaluate(&c, &a, &n());
+
+        float_cmp::assert_approx_eq!(f32, ab.x, ba.x, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, ab.z, ba.z, epsilon = 0.0001);
+    }
+
+    // Nearly smooth glass hit head on
+    // Checks that about 4% of samples reflect and the rest refract straight through
+    #[test]
+    fn dielectric_splits() {
+        let b = Bsdf::dielectric(1.5, 0.0);
+        let mut rng = Rng::new(4);
+
+        let samples: Vec<_> = (0..COUNT).filter_map(|_| b.sample(&n(), &n(), &mut rng)).collect();
+        let reflected = samples.iter().filter(|s| s.wi.y > 0.0).count() as f32 / samples.len() as f32;
+
+        float_cmp::assert_approx_eq!(f32, reflected, 0.04, epsilon = 0.01);
+        assert!(samples.iter().filter(|s| s.wi.y < 0.0).all(|s| s.wi.y < -0.99));
+    }
+
+    // Checks that the glTF material keeps its base color for the Whitted
+    // renderer and other materials fall back to Lambertian
+    #[test]
+    fn material_bsdf() {
+        let m = Material::metallic_roughness(&glm::vec3(0.2, 0.4, 0.6), 1.0, 0.3);
+
+        assert_eq!(m.color, glm::vec3(0.2, 0.4, 0.6));
+        assert_eq!(m.effective_bsdf(), Bsdf::metallic_roughness(&glm::vec3(0.2, 0.4, 0.6), 1.0, 0.3));
+        assert_eq!(Material::new().effective_bsdf(), Bsdf::lambertian(&glm::vec3(0.9, 0.9, 0.9)));
+    }
+}
+
+#[cfg(test)]
+mod mis_test {
+    extern crate nalgebra_glm as glm;
+
+    use std::f32::consts::PI;
+
+    use crate::bsdf::Bsdf;
+    use crate::environment::Environment;
+    use crate::image::Image;
+    use crate::integrator::{Integrator, PathTracer};
+    use crate::light::{AreaLight, Light, SphereLight};
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::rng::Rng;
+    use crate::sampling;
+    use crate::sphere::Sphere;
+    use crate::world::World;
+
+    // Floor with its top at y = 0 under a small bright light
+    fn glossy_world(roughness: f32) -> World {
+        let mut floor = Sphere::build(
+            &glm::Vec3::zeros(),
+            1.0,
+            &(glm::translation(&glm::vec3(0.0, -1000.0, 0.0)) * glm::scaling(&glm::vec3(1000.0, 1000.0, 1000.0))),
+        );
+        floor.material.bsdf = Some(Bsdf::conductor(&glm::vec3(0.9, 0.9, 0.9), roughness));
+
+        let mut w = World::new();
+        w.add(floor);
+        w.add_light(AreaLight::build(
+            &glm::vec3(0.8, 2.0, -0.2),
+            &glm::vec3(0.4, 0.0, 0.0),
+            2,
+            &glm::vec3(0.0, 0.0, 0.4),
+            2,
+            &glm::vec3(5.0, 5.0, 5.0),
+        ));
+        w
+    }
+
+    // Mean and variance of the MIS estimate and of light sampling alone, at
+    // the point where a mirror-ish view sees the light
+    fn estimates(w: &World, count: usize) -> ((f32, f32), (f32, f32)) {
+        let r = Ray::build(&glm::vec3(-1.0, 2.0, 0.0), &glm::vec3(1.0, -2.0, 0.0).normalize());
+        let comps = w.hit(&r).unwrap().prepare(&r);
+        let bsdf = comps.obj.material().effective_bsdf();
+        let n = comps.outward_normal();
+        let p = PathTracer::new();
+
+        let mut mis = vec![];
+        let mut light = vec![];
+
+        for i in 0..count {
+            let mut rng = Rng::new(i as u64);
+            let b = bsdf.sample(&comps.eyev, &n, &mut rng);
+            mis.push(p.direct(w, &comps, &bsdf, b.as_ref(), 0.0, &mut rng).x);
+
+            let samples = w.lights[0].samples(&comps.over_point, &mut rng);
+            let total: f32 = samples
+                .iter()
+                .map(|s| bsdf.evaluate(&comps.eyev, &s.direction, &n).x * s.intensity.x * glm::dot(&s.direction, &n) * PI)
+                .sum();
+            light.push(total / samples.len() as f32);
+        }
+
+        let stats = |v: &[f32]| {
+            let mean = v.iter().sum::<f32>() / v.len() as f32;
+            let var = v.iter().map(|x| (x - mean) * (x - mean)).sum::<f32>() / v.len() as f32;
+            (mean, var)
+        };
+
+        (stats(&mis), stats(&light))
+    }
+
+    // Checks the power heuristic's weights and that they sum to one
+    #[test]
+    fn power_heuristic() {
+        float_cmp::assert_approx_eq!(f32, sampling::power_heuristic(1.0, 2.0, 1.0, 2.0), 0.5);
+        float_cmp::assert_approx_eq!(f32, sampling::power_heuristic(1.0, 3.0, 1.0, 1.0), 0.9);
+        float_cmp::assert_approx_eq!(f32, sampling::power_heuristic(4.0, 0.5, 1.0, 1.0), 0.8);
+        float_cmp::assert_approx_eq!(
+            f32,
+            sampling::power_heuristic(4.0, 0.3, 1.0, 0.7) + sampling::power_heuristic(1.0, 0.7, 4.0, 0.3),
+            1.0
+        );
+        assert_eq!(sampling::power_heuristic(1.0, f32::INFINITY, 1.0, 5.0), 1.0);
+        assert_eq!(sampling::power_heuristic(1.0, 0.0, 1.0, 0.0), 0.0);
+    }
+
+    // Checks that aiming at a light sample hits the light and gives the same
+    // distance and pdf, for both rectangular and spherical lights
+    #[test]
+    fn light_hit_matches_samples() {
+        let lights: Vec<Box<dyn Light>> = vec![
+            Box::new(AreaLight::build(
+                &glm::vec3(-1.0, 3.0, -1.0),
+                &glm::vec3(2.0, 0.0, 0.5),
+                3,
+                &glm::vec3(0.0, 0.5, 2.0),
+                3,
+                &glm::vec3(1.0, 1.0, 1.0),
+            )),
+            Box::new(SphereLight::build(&glm::vec3(1.0, 4.0, 0.0), 0.7, 3, &glm::vec3(1.0, 1.0, 1.0))),
+        ];
+        let point = glm::vec3(0.3, 0.0, 0.2);
+
+        for l in lights {
+            for s in l.samples(&point, &mut Rng::new(9)) {
+                let h = l.hit(&point, &s.direction).unwrap();
+
+                float_cmp::assert_approx_eq!(f32, h.distance, s.distance, epsilon = 0.001);
+                float_cmp::assert_approx_eq!(f32, h.pdf, s.pdf, epsilon = 0.001 * s.pdf);
+            }
+
+            assert!(l.hit(&point, &glm::vec3(0.0, -1.0, 0.0)).is_none());
+        }
+    }
+
+    // Checks that MIS gives the same answer as light sampling alone, so no
+    // light is counted twice or lost
+    #[test]
+    fn mis_unbiased() {
+        let ((mis, _), (light, _)) = estimates(&glossy_world(0.4), 20000);
+
+        float_cmp::assert_approx_eq!(f32, mis, light, epsilon = 0.03 * light);
+    }
+
+    // Glossy floor reflecting a small bright light
+    // Checks that MIS is much less noisy than light sampling alone
+    #[test]
+    fn mis_glossy_less_noise() {
+        let ((_, mis), (_, light)) = estimates(&glossy_world(0.1), 4000);
+
+        assert!(mis * 4.0 < light, "MIS variance {} vs light sampling {}", mis, light);
+    }
+
+    // White furnace: a diffuse and a glossy sphere, both white, touching
+    // under a uniform white sky. All light comes from the sky and none is
+    // absorbed, so every pixel should come out 1 however many times its path
+    // bounces between the two.
+    // Checks that the light-sampled and BSDF-sampled halves of MIS add up to
+    // exactly the sky, so neither counts energy twice or drops it
+    #[test]
+    fn white_furnace() {
+        for roughness in [0.05, 0.2] {
+            let mut diffuse = Sphere::build(&glm::Vec3::zeros(), 1.0, &glm::translation(&glm::vec3(-1.0, 0.0, 0.0)));
+            diffuse.material = Material::new();
+            diffuse.material.bsdf = Some(Bsdf::lambertian(&glm::vec3(1.0, 1.0, 1.0)));
+
+            let mut glossy = Sphere::build(&glm::Vec3::zeros(), 1.0, &glm::translation(&glm::vec3(1.0, 0.0, 0.0)));
+            glossy.material = Material::new();
+            glossy.material.bsdf = Some(Bsdf::conductor(&glm::vec3(1.0, 1.0, 1.0), roughness));
+
+            let mut w = World::new();
+            w.add(diffuse);
+            w.add(glossy);
+            w.add_light(Environment::build(Image::build(8, 4, vec![glm::vec3(1.0, 1.0, 1.0); 32])));
+
+            let p = PathTracer::build(64, 3);
+            let mut rng = Rng::new(13);
+            let count = 20000;
+
+            // Rays from all round aimed near where the spheres touch
+            let mean = (0..count)
+                .map(|_| {
+                    let d = glm::vec3(rng.next_f32() - 0.5, rng.next_f32() - 0.5, rng.next_f32() - 0.5).normalize();
+                    let target = glm::vec3(rng.next_f32() - 0.5, rng.next_f32() - 0.5, rng.next_f32() - 0.5);
+                    p.li(&w, &Ray::build(&(target - d * 5.0), &d)).x
+                })
+                .sum::<f32>()
+                / count as f32;
+
+            float_cmp::assert_approx_eq!(f32, mean, 1.0, epsilon = 0.02);
+        }
+    }
+}
+
+#[cfg(test)]
+mod environment_test {
+    extern crate nalgebra_glm as glm;
+
+    use std::f32::consts::PI;
+
+    use crate::camera::Camera;
+    use crate::environment::Environment;
+    use crate::image::Image;
+    use crate::integrator::{Integrator, PathTracer};
+    use crate::light::Light;
+    use crate::panoramic::PanoramicCamera;
+    use crate::ray::Ray;
+    use crate::rng::Rng;
+    use crate::sampling::Distribution1D;
+    use crate::sphere::Sphere;
+    use crate::world::World;
+
+    fn assert_vec_eq(a: &glm::Vec3, b: &glm::Vec3) {
+        float_cmp::assert_approx_eq!(f32, a.x, b.x, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.y, b.y, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.z, b.z, epsilon = 0.0001);
+    }
+
+    fn hdr_header(width: usize, height: usize) -> Vec<u8> {
+        format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\nEXPOSURE=1.0\n\n-Y {} +X {}\n", height, width).into_bytes()
+    }
+
+    // Checks that a PFM written out reads back the same
+    #[test]
+    fn pfm_round_trip() {
+        let mut image = Image::new(3, 2);
+        image.set(0, 0, &glm::vec3(1.5, 2.0, 100.0));
+        image.set(2, 1, &glm::vec3(0.001, 0.0, 7.25));
+
+        assert_eq!(Image::from_pfm(&image.to_pfm()).unwrap(), image);
+    }
+
+    // Big-endian greyscale PFM, bottom row first
+    // Checks the byte order and that rows are flipped
+    #[test]
+    fn pfm_grey_big_endian() {
+        let mut bytes = b"Pf\n2 2\n1.0\n".to_vec();
+
+        for v in [1.0_f32, 2.0, 3.0, 4.0] {
+            bytes.extend_from_slice(&v.to_be_bytes());
+        }
+
+        let image = Image::from_pfm(&bytes).unwrap();
+
+        assert_eq!(image.get(0, 0), glm::vec3(3.0, 3.0, 3.0));
+        assert_eq!(image.get(1, 1), glm::vec3(2.0, 2.0, 2.0));
+        assert!(Image::from_pfm(b"P6\n2 2\n1.0\n").is_err());
+        assert!(Image::from_pfm(b"PF\n2 2\n-1.0\n1234").is_err());
+    }
+
+    // Flat RGBE pixels
+    // Checks the shared exponent and that a zero exponent is black
+    #[test]
+    fn hdr_flat() {
+        let mut bytes = hdr_header(2, 1);
+        bytes.extend_from_slice(&[128, 64, 32, 129, 200, 200, 200, 0]);
+
+        let image = Image::from_hdr(&bytes).unwrap();
+
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_vec_eq(&image.get(0, 0), &glm::vec3(1.0, 0.5, 0.25));
+        assert_eq!(image.get(1, 0), glm::Vec3::zeros());
+    }
+
+    // Run-length encoded scanlines, each channel stored as runs and literals
+    // Checks they decode to the same pixels as flat ones
+    #[test]
+    fn hdr_rle() {
+        let width = 8;
+        let mut bytes = hdr_header(width, 2);
+
+        for _ in 0..2 {
+            bytes.extend_from_slice(&[2, 2, 0, width as u8]);
+            // Red: a run of 8
+            bytes.extend_from_slice(&[128 + 8, 128]);
+            // Green: 4 literals then a run of 4
+            bytes.extend_from_slice(&[4, 0, 64, 128, 192, 128 + 4, 32]);
+            // Blue: a run of 8 zeros
+            bytes.extend_from_slice(&[128 + 8, 0]);
+            // Exponent: a run of 8
+            bytes.extend_from_slice(&[128 + 8, 129]);
+        }
+
+        let image = Image::from_hdr(&bytes).unwrap();
+        let f = 1.0 / 128.0;
+
+        assert_eq!((image.width, image.height), (8, 2));
+        assert_vec_eq(&image.get(0, 1), &glm::vec3(1.0, 0.0, 0.0));
+        assert_vec_eq(&image.get(2, 0), &glm::vec3(1.0, 128.0 * f, 0.0));
+        assert_vec_eq(&image.get(7, 1), &glm::vec3(1.0, 32.0 * f, 0.0));
+
+        assert!(Image::from_hdr(b"P3\n").is_err());
+        assert!(Image::from_hdr(&[hdr_header(8, 2), vec![2, 2, 0, 8, 128 + 9, 1]].concat()).is_err());
+    }
+
+    // Checks that files load by extension
+    #[test]
+    fn load_by_extension() {
+        let mut image = Image::new(2, 2);
+        image.set(1, 0, &glm::vec3(3.0, 2.0, 1.0));
+
+        let path = std::env::temp_dir().join(format!("environment_test_{}.pfm", std::process::id()));
+        image.save_pfm(&path).unwrap();
+        let loaded = Image::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap(), image);
+        assert!(Image::load(std::path::Path::new("missing.png")).is_err());
+    }
+
+    // Headers claiming sizes whose byte counts overflow, and a huge HDR
+    // with almost no data
+    // Checks that each is an error rather than a panic or a giant allocation
+    #[test]
+    fn oversized_headers() {
+        let huge = usize::MAX / 2;
+
+        assert!(Image::from_pfm(format!("PF\n{} 3\n-1.0\n", huge).as_bytes()).is_err());
+        assert!(Image::from_pfm(format!("Pf\n{} {}\n-1.0\n", huge, huge).as_bytes()).is_err());
+
+        let mut bytes = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n".to_vec();
+        bytes.extend_from_slice(format!("-Y {} +X {}\n", huge, huge).as_bytes());
+        assert!(Image::from_hdr(&bytes).is_err());
+
+        let mut bytes = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n".to_vec();
+        bytes.extend_from_slice(b"-Y 4000000000 +X 100000\n");
+        bytes.extend_from_slice(&[0; 16]);
+        assert!(Image::from_hdr(&bytes).is_err());
+    }
+
+    // Empty PFM saved to disk
+    // Checks that it can't be loaded as an environment, whose lookups need at
+    // least one pixel
+    #[test]
+    fn empty_environment() {
+        let path = std::env::temp_dir().join(format!("environment_empty_{}.pfm", std::process::id()));
+        std::fs::write(&path, b"PF\n0 0\n-1.0\n").unwrap();
+        let loaded = Environment::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(loaded.is_err());
+    }
+
+    // Checks that directions and image coordinates convert both ways and
+    // follow the panoramic camera's layout
+    #[test]
+    fn equirectangular_mapping() {
+        let c = PanoramicCamera::new(64, 32);
+
+        for (px, py) in [(0.5, 0.5), (10.25, 3.5), (32.0, 16.0), (63.5, 31.5)] {
+            let d = c.ray_for_pixel(px, py).direction;
+            let (u, v) = Environment::uv(&d);
+
+            float_cmp::assert_approx_eq!(f32, u, px / 64.0, epsilon = 0.0001);
+            float_cmp::assert_approx_eq!(f32, v, py / 32.0, epsilon = 0.0001);
+            assert_vec_eq(&Environment::direction(u, v), &d);
+        }
+    }
+
+    // Checks that steps are picked in proportion to their values
+    #[test]
+    fn distribution_1d() {
+        let d = Distribution1D::build(&[1.0, 3.0, 0.0]);
+
+        let (x, pdf, i) = d.sample(0.1);
+        assert_eq!(i, 0);
+        float_cmp::assert_approx_eq!(f32, x, 0.4 / 3.0, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, pdf, 0.75);
+
+        let (_, pdf, i) = d.sample(0.9);
+        assert_eq!(i, 1);
+        float_cmp::assert_approx_eq!(f32, pdf, 2.25);
+        assert_eq!(d.pdf(2), 0.0);
+
+        let flat = Distribution1D::build(&[0.0, 0.0]);
+        assert_eq!(flat.sample(0.75).2, 1);
+        assert_eq!(flat.pdf(0), 1.0);
+    }
+
+    // Map with one bright pixel
+    // Checks that most samples go toward it, their pdfs match pdf() and the
+    // pdf integrates to one over the sphere
+    #[test]
+    fn importance_sampling() {
+        let mut image = Image::build(16, 8, vec![glm::vec3(0.1, 0.1, 0.1); 128]);
+        image.set(4, 2, &glm::vec3(100.0, 100.0, 100.0));
+        let env = Environment::build(image);
+        let mut rng = Rng::new(3);
+
+        let bright = Environment::direction(4.5 / 16.0, 2.5 / 8.0);
+        let mut toward = 0;
+
+        for _ in 0..1000 {
+            let (d, pdf) = env.sample(rng.next_f32(), rng.next_f32());
+
+            float_cmp::assert_approx_eq!(f32, pdf, env.pdf(&d), epsilon = 0.001 * pdf);
+
+            if glm::dot(&d, &bright) > 0.9 {
+                toward += 1;
+            }
+        }
+
+        assert!(toward > 900);
+
+        let count = 100000;
+        let total: f32 = (0..count)
+            .map(|_| {
+                let z = 1.0 - 2.0 * rng.next_f32();
+                let r = (1.0 - z * z).sqrt();
+                let phi = 2.0 * PI * rng.next_f32();
+
+                env.pdf(&glm::vec3(r * phi.cos(), z, r * phi.sin())) * 4.0 * PI
+            })
+            .sum();
+
+        float_cmp::assert_approx_eq!(f32, total / count as f32, 1.0, epsilon = 0.05);
+    }
+
+    // Checks that rays missing everything see the environment
+    #[test]
+    fn background() {
+        let mut image = Image::build(4, 2, vec![glm::vec3(0.0, 0.0, 1.0); 8]);
+        image.set(0, 0, &glm::vec3(1.0, 0.0, 0.0));
+
+        let mut env = Environment::build(image);
+        env.scale = 2.0;
+
+        let mut w = World::new();
+        assert_eq!(w.background(&glm::vec3(0.0, 1.0, 0.0)), glm::Vec3::zeros());
+
+        w.add_light(env);
+
+        let r = Ray::build(&glm::Vec3::zeros(), &glm::vec3(0.0, -1.0, 0.0));
+        assert_eq!(w.color_at(&r), glm::vec3(0.0, 0.0, 2.0));
+        assert_eq!(w.background(&Environment::direction(0.1, 0.1)), glm::vec3(2.0, 0.0, 0.0));
+        assert_eq!(PathTracer::new().li(&w, &r), glm::vec3(0.0, 0.0, 2.0));
+    }
+
+    // Convex sphere with albedo 0.5 under a uniform white sky
+    // Checks that the path tracer reflects exactly half of it, so environment
+    // light is neither lost nor counted twice
+    #[test]
+    fn environment_furnace() {
+        let env = Environment::build(Image::build(8, 4, vec![glm::vec3(1.0, 1.0, 1.0); 32]));
+        assert_vec_eq(&env.intensity(), &glm::vec3(1.0, 1.0, 1.0));
+
+        let mut s = Sphere::new();
+        s.material.color = glm::vec3(1.0, 1.0, 1.0);
+        s.material.diffuse = 0.5;
+
+        let mut w = World::new();
+        w.add(s);
+        w.add_light(env);
+
+        let p = PathTracer::new();
+        let mut rng = Rng::new(8);
+        let count = 4000;
+
+        let mean = (0..count)
+            .map(|_| {
+                let x = rng.next_f32() * 1.6 - 0.8;
+                let y = rng.next_f32() * 1.6 - 0.8;
+                p.li(&w, &Ray::build(&glm::vec3(x * 0.7, y * 0.7, -5.0), &glm::vec3(0.0, 0.0, 1.0))).x
+            })
+            .sum::<f32>()
+            / count as f32;
+
+        float_cmp::assert_approx_eq!(f32, mean, 0.5, epsilon = 0.01);
+    }
+}
+
+#[cfg(test)]
+mod sky_test {
+    extern crate nalgebra_glm as glm;
+
+    use std::f32::consts::PI;
+
+    use crate::light::Light;
+    use crate::ray::Ray;
+    use crate::rng::Rng;
+    use crate::sky::Sky;
+    use crate::world::World;
+
+    // Checks that the zenith luminance follows Preetham's fit
+    #[test]
+    fn zenith_luminance() {
+        let sky = Sky::build(PI / 2.0, 0.0, 2.0);
+        let chi: f32 = (4.0 / 9.0 - 2.0 / 120.0) * PI;
+        let expected = (4.0453 * 2.0 - 4.9710) * chi.tan() - 0.2155 * 2.0 + 2.4192;
+
+        float_cmp::assert_approx_eq!(f32, sky.zenith_luminance(), expected, epsilon = 0.0001);
+    }
+
+    // Checks the sun direction for a few elevations and azimuths
+    #[test]
+    fn sun_direction() {
+        let d = Sky::sun_direction(0.0, 0.0);
+        float_cmp::assert_approx_eq!(f32, d.z, -1.0, epsilon = 0.0001);
+
+        let d = Sky::sun_direction(0.0, PI / 2.0);
+        float_cmp::assert_approx_eq!(f32, d.x, 1.0, epsilon = 0.0001);
+
+        let d = Sky::sun_direction(PI / 2.0, 1.0);
+        float_cmp::assert_approx_eq!(f32, d.y, 1.0, epsilon = 0.0001);
+    }
+
+    // Sun well above the horizon
+    // Checks that the zenith is blue, the ground black, and the sky brighter
+    // near the sun than opposite it
+    #[test]
+    fn sky_colors() {
+        let sky = Sky::build(45_f32.to_radians(), 0.0, 3.0);
+
+        let zenith = sky.radiance(&glm::vec3(0.0, 1.0, 0.0));
+        assert!(zenith.z > zenith.x);
+
+        assert_eq!(sky.radiance(&glm::vec3(0.3, -0.5, 0.2)), glm::Vec3::zeros());
+
+        let near = sky.radiance(&(sky.sun + glm::vec3(0.0, 0.0, 0.1)));
+        let away = sky.radiance(&glm::vec3(0.0, 0.7, 0.7));
+        assert!(near.y > 2.0 * away.y);
+    }
+
+    // Checks that the sky is mirror symmetric about the sun's vertical plane
+    #[test]
+    fn symmetric_about_sun() {
+        let sky = Sky::build(0.4, 0.0, 4.0);
+
+        for d in [glm::vec3(0.3, 0.5, -0.8), glm::vec3(0.9, 0.1, 0.2), glm::vec3(0.5, 0.8, 0.4)] {
+            let a = sky.radiance(&d);
+            let b = sky.radiance(&glm::vec3(-d.x, d.y, d.z));
+
+            float_cmp::assert_approx_eq!(f32, a.x, b.x, epsilon = 0.0001 * a.x.max(1.0));
+            float_cmp::assert_approx_eq!(f32, a.z, b.z, epsilon = 0.0001 * a.z.max(1.0));
+        }
+    }
+
+    // Checks that sunlight loses more blue than red, and more still at sunset
+    #[test]
+    fn sun_color() {
+        let noon = Sky::build(80_f32.to_radians(), 0.0, 3.0).sun_light(1.0).intensity;
+        let sunset = Sky::build(3_f32.to_radians(), 0.0, 3.0).sun_light(1.0).intensity;
+
+        assert!(noon.x > noon.z && noon.x < 1.0);
+        assert!(noon.z > 0.5);
+        assert!(sunset.x / sunset.z > 2.0 * noon.x / noon.z);
+        assert!(sunset.y < noon.y);
+    }
+
+    // Checks that light samples stay above the horizon, carry the sky's
+    // radiance and have the pdf hit() reports for the same direction
+    #[test]
+    fn light_samples() {
+        let sky = Sky::build(0.5, 1.0, 3.0);
+        let mut rng = Rng::new(11);
+        let p = glm::Vec3::zeros();
+
+        for _ in 0..50 {
+            for s in sky.samples(&p, &mut rng) {
+                assert!(s.direction.y > 0.0);
+                assert!(s.pdf > 0.0);
+
+                let radiance = s.intensity * (PI * s.pdf);
+                let expected = sky.radiance(&s.direction);
+                float_cmp::assert_approx_eq!(f32, radiance.y, expected.y, epsilon = 0.0001 * expected.y.max(1.0));
+
+                let hit = sky.hit(&p, &s.direction).unwrap();
+                float_cmp::assert_approx_eq!(f32, hit.pdf, s.pdf, epsilon = 0.001 * s.pdf);
+            }
+        }
+    }
+
+    // Checks that rays missing everything see the sky
+    #[test]
+    fn background() {
+        let sky = Sky::build(0.5, 0.0, 3.0);
+        let up = sky.radiance(&glm::vec3(0.0, 1.0, 0.0));
+
+        let mut w = World::new();
+        w.add_light(sky);
+
+        let r = Ray::build(&glm::Vec3::zeros(), &glm::vec3(0.0, 1.0, 0.0));
+        assert_eq!(w.color_at(&r), up);
+    }
+}
+
+#[cfg(test)]
+mod medium_test {
+    extern crate nalgebra_glm as glm;
+
+    use std::f32::consts::PI;
+
+    use crate::environment::Environment;
+    use crate::image::Image;
+    use crate::integrator::{Integrator, PathTracer};
+    use crate::light::{LightSample, PointLight};
+    use crate::medium::{self, Fog, HenyeyGreenstein, Interaction, Medium, Volume};
+    use crate::ray::Ray;
+    use crate::rng::Rng;
+    use crate::sphere::Sphere;
+    use crate::world::World;
+
+    fn random_direction(rng: &mut Rng) -> glm::Vec3 {
+        let z = 1.0 - 2.0 * rng.next_f32();
+        let r = (1.0 - z * z).sqrt();
+        let phi = 2.0 * PI * rng.next_f32();
+
+        glm::vec3(r * phi.cos(), z, r * phi.sin())
+    }
+
+    // Checks that the phase function is uniform for g = 0 and integrates to
+    // one over the sphere for any g
+    #[test]
+    fn phase_normalized() {
+        let wo = glm::vec3(0.0, 0.0, 1.0);
+        let iso = HenyeyGreenstein::build(0.0);
+        float_cmp::assert_approx_eq!(f32, iso.evaluate(&wo, &glm::vec3(1.0, 0.0, 0.0)), 1.0 / (4.0 * PI), epsilon = 0.00001);
+
+        let mut rng = Rng::new(5);
+
+        for g in [-0.5, 0.3, 0.8] {
+            let hg = HenyeyGreenstein::build(g);
+            let count = 200000;
+            let total: f32 = (0..count).map(|_| hg.evaluate(&wo, &random_direction(&mut rng)) * 4.0 * PI).sum();
+
+            float_cmp::assert_approx_eq!(f32, total / count as f32, 1.0, epsilon = 0.03);
+        }
+    }
+
+    // Light coming along +z, scattered on toward the viewer
+    // Checks that g > 0 favors light that keeps going the same way
+    #[test]
+    fn phase_forward() {
+        let hg = HenyeyGreenstein::build(0.7);
+        let wo = glm::vec3(0.0, 0.0, 1.0);
+
+        assert!(hg.evaluate(&wo, &glm::vec3(0.0, 0.0, -1.0)) > 10.0 * hg.evaluate(&wo, &wo));
+    }
+
+    // Checks that sampled directions have the mean cosine g
+    #[test]
+    fn phase_sampling() {
+        let mut rng = Rng::new(9);
+        let wo = glm::vec3(0.0, 1.0, 0.0);
+
+        for g in [-0.6, 0.0, 0.4, 0.9] {
+            let hg = HenyeyGreenstein::build(g);
+            let count = 20000;
+            let mean: f32 = (0..count)
+                .map(|_| {
+                    let wi = hg.sample(&wo, rng.next_f32(), rng.next_f32());
+                    float_cmp::assert_approx_eq!(f32, wi.norm(), 1.0, epsilon = 0.0001);
+                    -glm::dot(&wo, &wi)
+                })
+                .sum::<f32>()
+                / count as f32;
+
+            float_cmp::assert_approx_eq!(f32, mean, g, epsilon = 0.02);
+        }
+    }
+
+    // Checks Beer's law, and that a clear channel stays clear forever
+    #[test]
+    fn transmittance() {
+        let m = Medium::build(&glm::vec3(0.5, 0.0, 0.1), &glm::vec3(0.5, 0.0, 0.1), 0.0);
+        let tr = m.transmittance(2.0);
+
+        float_cmp::assert_approx_eq!(f32, tr.x, (-2.0_f32).exp(), epsilon = 0.00001);
+        float_cmp::assert_approx_eq!(f32, tr.y, 1.0, epsilon = 0.00001);
+        float_cmp::assert_approx_eq!(f32, tr.z, (-0.4_f32).exp(), epsilon = 0.00001);
+
+        assert_eq!(m.transmittance(f32::INFINITY), glm::vec3(0.0, 1.0, 0.0));
+
+        let fog = Medium::from_albedo(2.0, &glm::vec3(0.25, 0.5, 1.0), 0.0);
+        assert_eq!(fog.scattering, glm::vec3(0.5, 1.0, 2.0));
+        assert_eq!(fog.extinction(), glm::vec3(2.0, 2.0, 2.0));
+    }
+
+    // Unit sphere of smoke
+    // Checks the parts of rays inside it, from outside, from inside and when
+    // something gets in the way
+    #[test]
+    fn volume_spans() {
+        let v = Volume::build(Sphere::new(), Medium::from_albedo(1.0, &glm::vec3(1.0, 1.0, 1.0), 0.0));
+
+        let r = Ray::build(&glm::vec3(0.0, 0.0, -5.0), &glm::vec3(0.0, 0.0, 1.0));
+        let spans = v.spans(&r, f32::INFINITY);
+        assert_eq!(spans.len(), 1);
+        assert_eq!((spans[0].start, spans[0].end), (4.0, 6.0));
+
+        assert_eq!(v.spans(&r, 5.0)[0].end, 5.0);
+        assert!(v.spans(&r, 3.0).is_empty());
+
+        let inside = Ray::build(&glm::vec3(0.0, 0.0, 0.0), &glm::vec3(0.0, 1.0, 0.0));
+        let spans = v.spans(&inside, f32::INFINITY);
+        assert_eq!((spans[0].start, spans[0].end), (0.0, 1.0));
+
+        let miss = Ray::build(&glm::vec3(0.0, 2.0, -5.0), &glm::vec3(0.0, 0.0, 1.0));
+        assert!(v.spans(&miss, f32::INFINITY).is_empty());
+    }
+
+    // Fog below y = 1
+    // Checks the parts of rays in it going up, down and level
+    #[test]
+    fn fog_spans() {
+        let fog = Fog::build(Medium::from_albedo(1.0, &glm::vec3(1.0, 1.0, 1.0), 0.0), 1.0);
+
+        let up = Ray::build(&glm::vec3(0.0, 0.0, 0.0), &glm::vec3(0.0, 1.0, 0.0));
+        let spans = fog.spans(&up, f32::INFINITY);
+        assert_eq!((spans[0].start, spans[0].end), (0.0, 1.0));
+
+        let down = Ray::build(&glm::vec3(0.0, 3.0, 0.0), &glm::vec3(0.0, -1.0, 0.0));
+        let spans = fog.spans(&down, 10.0);
+        assert_eq!((spans[0].start, spans[0].end), (2.0, 10.0));
+
+        let level = Ray::build(&glm::vec3(0.0, 2.0, 0.0), &glm::vec3(1.0, 0.0, 0.0));
+        assert!(fog.spans(&level, f32::INFINITY).is_empty());
+
+        let everywhere = Fog::new(fog.medium);
+        assert_eq!(everywhere.spans(&level, 4.0)[0].end, 4.0);
+    }
+
+    // Two overlapping stretches of gray medium
+    // Checks that the chance of getting through matches the transmittance
+    // and scattering happens only where there is a medium
+    #[test]
+    fn distance_sampling() {
+        let m = Medium::from_albedo(0.5, &glm::vec3(1.0, 1.0, 1.0), 0.0);
+        let spans = [
+            medium::Span { start: 1.0, end: 3.0, medium: m },
+            medium::Span { start: 2.0, end: 4.0, medium: m },
+        ];
+        let expected = medium::transmittance(&spans);
+        float_cmp::assert_approx_eq!(f32, expected.x, (-2.0_f32).exp(), epsilon = 0.00001);
+
+        let mut rng = Rng::new(4);
+        let count = 20000;
+        let mut passed = 0;
+
+        for _ in 0..count {
+            match medium::sample_distance(&spans, &mut rng) {
+                Interaction::Pass { weight } => {
+                    float_cmp::assert_approx_eq!(f32, weight.x, 1.0, epsilon = 0.0001);
+                    passed += 1;
+                }
+                Interaction::Scatter { t, weight, .. } => {
+                    assert!((1.0..=4.0).contains(&t));
+                    assert!(weight.x > 0.0);
+                }
+            }
+        }
+
+        float_cmp::assert_approx_eq!(f32, passed as f32 / count as f32, expected.x, epsilon = 0.01);
+    }
+
+    // Sphere of smoke between a point and a light
+    // Checks that shadow rays get the sphere's transmittance, and nothing
+    // past an opaque object
+    #[test]
+    fn world_transmittance() {
+        let mut w = World::new();
+        w.add_volume(Volume::build(Sphere::new(), Medium::build(&glm::vec3(0.25, 0.5, 1.0), &glm::Vec3::zeros(), 0.0)));
+
+        let p = glm::vec3(0.0, 0.0, -5.0);
+        let s = LightSample::toward(&p, &glm::vec3(0.0, 0.0, 5.0), &glm::vec3(1.0, 1.0, 1.0));
+        let tr = w.transmittance(&p, &s, 0.0);
+
+        float_cmp::assert_approx_eq!(f32, tr.x, (-0.5_f32).exp(), epsilon = 0.00001);
+        float_cmp::assert_approx_eq!(f32, tr.z, (-2.0_f32).exp(), epsilon = 0.00001);
+
+        let mut wall = Sphere::new();
+        wall.transform = glm::translation(&glm::vec3(0.0, 0.0, 3.0)) * glm::scaling(&glm::vec3(0.5, 0.5, 0.5));
+        w.add(wall);
+
+        assert_eq!(w.transmittance(&p, &s, 0.0), glm::Vec3::zeros());
+    }
+
+    // Absorbing ball in front of a white sky
+    // Checks that on average the path tracer dims what's behind it by Beer's
+    // law
+    #[test]
+    fn absorbing_volume() {
+        let mut w = World::new();
+        w.add_light(Environment::build(Image::build(4, 2, vec![glm::vec3(1.0, 1.0, 1.0); 8])));
+        w.add_volume(Volume::build(Sphere::new(), Medium::build(&glm::vec3(0.5, 0.5, 0.5), &glm::Vec3::zeros(), 0.0)));
+
+        let p = PathTracer::new();
+        let mut rng = Rng::new(6);
+        let count = 4000;
+
+        // Rays through the middle, nudged so each gets its own random numbers
+        let mean = (0..count)
+            .map(|_| {
+                let origin = glm::vec3(0.0001 * rng.next_f32(), 0.0001 * rng.next_f32(), -5.0);
+                p.li(&w, &Ray::build(&origin, &glm::vec3(0.0, 0.0, 1.0))).x
+            })
+            .sum::<f32>()
+            / count as f32;
+
+        float_cmp::assert_approx_eq!(f32, mean, (-1.0_f32).exp(), epsilon = 0.02);
+    }
+
+    // Ball of white, purely scattering smoke under a uniform white sky
+    // Checks that it looks exactly as bright as the sky, so light is neither
+    // lost nor counted twice between light sampling and the phase function
+    #[test]
+    fn volume_furnace() {
+        let mut w = World::new();
+        w.add_light(Environment::build(Image::build(8, 4, vec![glm::vec3(1.0, 1.0, 1.0); 32])));
+        w.add_volume(Volume::build(Sphere::new(), Medium::from_albedo(1.0, &glm::vec3(1.0, 1.0, 1.0), 0.5)));
+
+        let p = PathTracer::new();
+        let mut rng = Rng::new(12);
+        let count = 4000;
+
+        let mean = (0..count)
+            .map(|_| {
+                let x = rng.next_f32() * 1.2 - 0.6;
+                let y = rng.next_f32() * 1.2 - 0.6;
+                p.li(&w, &Ray::build(&glm::vec3(x, y, -5.0), &glm::vec3(0.0, 0.0, 1.0))).x
+            })
+            .sum::<f32>()
+            / count as f32;
+
+        float_cmp::assert_approx_eq!(f32, mean, 1.0, epsilon = 0.02);
+    }
+
+    // Point light inside thin fog, seen from the side
+    // Checks that the fog glows where a clear world stays black
+    #[test]
+    fn fog_in_scattering() {
+        let mut w = World::new();
+        w.add_light(PointLight::build(&glm::vec3(0.0, 0.0, 0.0), &glm::vec3(1.0, 1.0, 1.0)));
+
+        let r = Ray::build(&glm::vec3(-5.0, 0.5, 0.0), &glm::vec3(1.0, 0.0, 0.0));
+        let p = PathTracer::new();
+        assert_eq!(p.li(&w, &r), glm::Vec3::zeros());
+
+        w.fog = Some(Fog::new(Medium::from_albedo(0.1, &glm::vec3(1.0, 1.0, 1.0), 0.0)));
+        let mut rng = Rng::new(2);
+        let mean = (0..200)
+            .map(|_| p.li(&w, &Ray::build(&glm::vec3(-5.0, 0.5 + 0.001 * rng.next_f32(), 0.0), &glm::vec3(1.0, 0.0, 0.0))).x)
+            .sum::<f32>()
+            / 200.0;
+
+        assert!(mean > 0.0);
+    }
+}
+
+#[cfg(test)]
+mod grid_test {
+    extern crate nalgebra_glm as glm;
+
+    use crate::environment::Environment;
+    use crate::grid::{DensityGrid, GridVolume};
+    use crate::image::Image;
+    use crate::integrator::{Integrator, PathTracer};
+    use crate::light::LightSample;
+    use crate::ray::Ray;
+    use crate::rng::Rng;
+    use crate::world::World;
+
+    // Two voxels along x, 0 and 2: flat at the ends and ramping in between,
+    // so a ray straight along x crosses one unit of density in total
+    fn ramp() -> DensityGrid {
+        DensityGrid::build(2, 1, 1, vec![0.0, 2.0])
+    }
+
+    fn along_x() -> Ray {
+        Ray::build(&glm::vec3(-1.0, 0.5, 0.5), &glm::vec3(1.0, 0.0, 0.0))
+    }
+
+    // Checks that densities are blended between voxel centers, flat past the
+    // outer ones and zero outside the cube
+    #[test]
+    fn trilinear_density() {
+        let g = ramp();
+
+        float_cmp::assert_approx_eq!(f32, g.density(&glm::vec3(0.25, 0.5, 0.5)), 0.0, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, g.density(&glm::vec3(0.5, 0.1, 0.9)), 1.0, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, g.density(&glm::vec3(0.625, 0.5, 0.5)), 1.5, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, g.density(&glm::vec3(0.9, 0.5, 0.5)), 2.0, epsilon = 0.0001);
+        assert_eq!(g.density(&glm::vec3(1.1, 0.5, 0.5)), 0.0);
+        assert_eq!(g.max(), 2.0);
+
+        let mut g = DensityGrid::new(2, 2, 2);
+        g.set(1, 1, 1, 8.0);
+        float_cmp::assert_approx_eq!(f32, g.density(&glm::vec3(0.5, 0.5, 0.5)), 1.0, epsilon = 0.0001);
+    }
+
+    // Checks that a grid written out reads back the same, and that other
+    // files are refused
+    #[test]
+    fn raw_round_trip() {
+        let mut g = DensityGrid::new(3, 2, 4);
+        g.set(2, 1, 3, 0.75);
+        g.set(0, 1, 0, 2.5);
+
+        let bytes = g.to_raw();
+        assert_eq!(DensityGrid::from_raw(&bytes).unwrap(), g);
+
+        assert!(DensityGrid::from_raw(b"PF\n1 1\n-1\n").is_err());
+        assert!(DensityGrid::from_raw(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    // Voxel files holding an infinite, a NaN and a negative density
+    // Checks that the first two are refused, so tracking can't get stuck
+    // behind an infinite majorant, and the negative one reads as empty
+    #[test]
+    fn raw_bad_densities() {
+        let raw = |d: f32| {
+            let mut g = DensityGrid::new(2, 1, 1);
+            g.set(1, 0, 0, d);
+            g.to_raw()
+        };
+
+        assert!(DensityGrid::from_raw(&raw(f32::INFINITY)).is_err());
+        assert!(DensityGrid::from_raw(&raw(f32::NAN)).is_err());
+        assert_eq!(DensityGrid::from_raw(&raw(-3.0)).unwrap().get(1, 0, 0), 0.0);
+    }
+
+    // Header whose voxel count overflows, and a finite density that becomes
+    // infinite once multiplied by the extinction
+    // Checks that both are errors rather than panics
+    #[test]
+    fn oversized_grids() {
+        let huge = usize::MAX / 2;
+        assert!(DensityGrid::from_raw(format!("VOL\n{} {} 1\n", huge, huge).as_bytes()).is_err());
+        assert!(DensityGrid::from_raw(format!("VOL\n{} 1 1\n", huge).as_bytes()).is_err());
+
+        let mut g = DensityGrid::new(2, 2, 2);
+        g.set(1, 1, 1, 1e38);
+        let g = DensityGrid::from_raw(&g.to_raw()).unwrap();
+
+        assert!(GridVolume::build(g.clone(), &glm::Mat4::identity(), 1.0, &glm::vec3(1.0, 1.0, 1.0), 0.0).is_ok());
+        assert!(GridVolume::build(g, &glm::Mat4::identity(), 4.0, &glm::vec3(1.0, 1.0, 1.0), 0.0).is_err());
+    }
+
+    // Checks where rays enter and leave a stretched, moved box
+    #[test]
+    fn box_span() {
+        let transform = glm::translation(&glm::vec3(1.0, 0.0, 0.0)) * glm::scaling(&glm::vec3(2.0, 1.0, 1.0));
+        let v = GridVolume::build(ramp(), &transform, 1.0, &glm::vec3(1.0, 1.0, 1.0), 0.0).unwrap();
+
+        let r = Ray::build(&glm::vec3(0.0, 0.5, 0.5), &glm::vec3(1.0, 0.0, 0.0));
+        assert_eq!(v.span(&r, f32::INFINITY), Some((1.0, 3.0)));
+        assert_eq!(v.span(&r, 2.0), Some((1.0, 2.0)));
+
+        let inside = Ray::build(&glm::vec3(2.0, 0.5, 0.5), &glm::vec3(0.0, -1.0, 0.0));
+        assert_eq!(v.span(&inside, f32::INFINITY), Some((0.0, 0.5)));
+
+        let miss = Ray::build(&glm::vec3(0.0, 1.5, 0.5), &glm::vec3(1.0, 0.0, 0.0));
+        assert_eq!(v.span(&miss, f32::INFINITY), None);
+    }
+
+    // Ray along the ramp, one unit of density in all
+    // Checks that delta tracking gets through and ratio tracking averages to
+    // the transmittance
+    #[test]
+    fn tracking() {
+        let v = GridVolume::build(ramp(), &glm::Mat4::identity(), 1.0, &glm::vec3(1.0, 1.0, 1.0), 0.0).unwrap();
+        let expected = (-1.0_f32).exp();
+        let mut rng = Rng::new(7);
+        let count = 20000;
+
+        let passed = (0..count).filter(|_| v.sample_distance(&along_x(), f32::INFINITY, &mut rng).is_none()).count();
+        float_cmp::assert_approx_eq!(f32, passed as f32 / count as f32, expected, epsilon = 0.01);
+
+        let mean = (0..count).map(|_| v.transmittance(&along_x(), f32::INFINITY, &mut rng)).sum::<f32>() / count as f32;
+        float_cmp::assert_approx_eq!(f32, mean, expected, epsilon = 0.01);
+
+        // Nothing but empty voxels before x = 0.25
+        for _ in 0..100 {
+            if let Some(t) = v.sample_distance(&along_x(), f32::INFINITY, &mut rng) {
+                assert!(t > 1.25 && t < 2.0);
+            }
+        }
+
+        assert_eq!(v.transmittance(&along_x(), 1.25, &mut rng), 1.0);
+    }
+
+    // Checks that shadow rays through a grid in the world are dimmed on
+    // average by its transmittance
+    #[test]
+    fn world_transmittance() {
+        let mut w = World::new();
+        w.add_grid(GridVolume::build(ramp(), &glm::Mat4::identity(), 1.0, &glm::vec3(1.0, 1.0, 1.0), 0.0).unwrap());
+
+        let mut rng = Rng::new(3);
+        let count = 4000;
+        let mean = (0..count)
+            .map(|_| {
+                let p = glm::vec3(-1.0, 0.5 + 0.001 * rng.next_f32(), 0.5);
+                let s = LightSample::toward(&p, &glm::vec3(3.0, p.y, 0.5), &glm::vec3(1.0, 1.0, 1.0));
+                w.transmittance(&p, &s, 0.0).x
+            })
+            .sum::<f32>()
+            / count as f32;
+
+        float_cmp::assert_approx_eq!(f32, mean, (-1.0_f32).exp(), epsilon = 0.02);
+    }
+
+    // Lumpy white cloud that only scatters, under a uniform white sky
+    // Checks that it looks exactly as bright as the sky
+    #[test]
+    fn grid_furnace() {
+        let mut rng = Rng::new(21);
+        let voxels = (0..64).map(|_| rng.next_f32() * 2.0).collect();
+        let transform = glm::translation(&glm::vec3(-0.5, -0.5, -0.5));
+
+        let mut w = World::new();
+        w.add_light(Environment::build(Image::build(8, 4, vec![glm::vec3(1.0, 1.0, 1.0); 32])));
+        let grid = DensityGrid::build(4, 4, 4, voxels);
+        w.add_grid(GridVolume::build(grid, &transform, 2.0, &glm::vec3(1.0, 1.0, 1.0), 0.3).unwrap());
+
+        let p = PathTracer::new();
+        let count = 4000;
+
+        let mean = (0..count)
+            .map(|_| {
+                let x = rng.next_f32() - 0.5;
+                let y = rng.next_f32() - 0.5;
+                p.li(&w, &Ray::build(&glm::vec3(x, y, -5.0), &glm::vec3(0.0, 0.0, 1.0))).x
+            })
+            .sum::<f32>()
+            / count as f32;
+
+        float_cmp::assert_approx_eq!(f32, mean, 1.0, epsilon = 0.02);
+    }
+}
+
+#[cfg(test)]
+mod sdf_test {
+    extern crate nalgebra_glm as glm;
+
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use crate::sdf::{Sdf, SdfObject};
+    use crate::world::World;
+
+    fn assert_vec_eq(a: &glm::Vec3, b: &glm::Vec3) {
+        float_cmp::assert_approx_eq!(f32, a.x, b.x, epsilon = 0.001);
+        float_cmp::assert_approx_eq!(f32, a.y, b.y, epsilon = 0.001);
+        float_cmp::assert_approx_eq!(f32, a.z, b.z, epsilon = 0.001);
+    }
+
+    // Checks each primitive's distance at a few points inside, on and outside
+    #[test]
+    fn primitive_distances() {
+        let sphere = Sdf::sphere(1.0);
+        assert_eq!(sphere.distance(&glm::vec3(0.0, 3.0, 0.0)), 2.0);
+        assert_eq!(sphere.distance(&glm::Vec3::zeros()), -1.0);
+
+        let cuboid = Sdf::cuboid(&glm::vec3(1.0, 2.0, 3.0));
+        assert_eq!(cuboid.distance(&glm::vec3(2.0, 0.0, 0.0)), 1.0);
+        assert_eq!(cuboid.distance(&glm::vec3(0.0, 0.0, 0.0)), -1.0);
+        float_cmp::assert_approx_eq!(f32, cuboid.distance(&glm::vec3(4.0, 6.0, 0.0)), 5.0, epsilon = 0.0001);
+
+        let torus = Sdf::torus(2.0, 0.5);
+        assert_eq!(torus.distance(&glm::vec3(2.0, 0.0, 0.0)), -0.5);
+        assert_eq!(torus.distance(&glm::vec3(0.0, 0.0, -2.0)), -0.5);
+        assert_eq!(torus.distance(&glm::Vec3::zeros()), 1.5);
+        assert_eq!(torus.distance(&glm::vec3(2.0, 1.5, 0.0)), 1.0);
+
+        let capsule = Sdf::capsule(&glm::vec3(0.0, -1.0, 0.0), &glm::vec3(0.0, 1.0, 0.0), 0.5);
+        assert_eq!(capsule.distance(&glm::vec3(1.0, 0.0, 0.0)), 0.5);
+        assert_eq!(capsule.distance(&glm::vec3(0.0, 3.0, 0.0)), 1.5);
+
+        let moved = Sdf::sphere(1.0).translate(&glm::vec3(5.0, 0.0, 0.0));
+        assert_eq!(moved.distance(&glm::vec3(5.0, 0.0, 0.0)), -1.0);
+    }
+
+    // Two unit spheres 1.5 apart
+    // Checks that a hard union and subtraction are the min and max, and that
+    // blending fills in the gap between them
+    #[test]
+    fn operators() {
+        let a = Sdf::sphere(1.0);
+        let b = Sdf::sphere(1.0).translate(&glm::vec3(1.5, 0.0, 0.0));
+        let p = glm::vec3(0.75, 0.8, 0.0);
+
+        let hard = a.clone().smooth_union(b.clone(), 0.0);
+        assert_eq!(hard.distance(&p), a.distance(&p).min(b.distance(&p)));
+
+        let smooth = a.clone().smooth_union(b.clone(), 0.5);
+        assert!(smooth.distance(&p) < hard.distance(&p));
+
+        // Far from the seam blending changes nothing
+        let far = glm::vec3(-3.0, 0.0, 0.0);
+        assert_eq!(smooth.distance(&far), a.distance(&far));
+
+        let carved = a.clone().smooth_subtraction(b.clone(), 0.0);
+        assert_eq!(carved.distance(&glm::vec3(1.0, 0.0, 0.0)), 0.5);
+        assert_eq!(carved.distance(&glm::vec3(-0.5, 0.0, 0.0)), -0.5);
+
+        // Blending rounds off the rim of the bite, carving deeper than the
+        // hard cut at the seam
+        let seam = glm::vec3(0.75, 0.6, 0.0);
+        assert!(a.smooth_subtraction(b, 0.3).distance(&seam) > carved.distance(&seam));
+    }
+
+    // Checks that repetition copies the shape along the repeated axes only
+    #[test]
+    fn repetition() {
+        let balls = Sdf::sphere(0.5).repeat(&glm::vec3(4.0, 0.0, 0.0));
+
+        assert_eq!(balls.distance(&glm::vec3(8.0, 0.0, 0.0)), -0.5);
+        assert_eq!(balls.distance(&glm::vec3(-4.0, 0.0, 0.0)), -0.5);
+        assert_eq!(balls.distance(&glm::vec3(2.0, 0.0, 0.0)), 1.5);
+        assert_eq!(balls.distance(&glm::vec3(8.0, 4.0, 0.0)), 3.5);
+    }
+
+    // Checks that central differences give the surface normal
+    #[test]
+    fn gradient_normals() {
+        let s = SdfObject::new(Sdf::cuboid(&glm::vec3(1.0, 1.0, 1.0)));
+        assert_vec_eq(&s.normal_at(&glm::vec3(1.0, 0.2, -0.3)), &glm::vec3(1.0, 0.0, 0.0));
+        assert_vec_eq(&s.normal_at(&glm::vec3(0.5, -1.0, 0.1)), &glm::vec3(0.0, -1.0, 0.0));
+
+        let mut t = SdfObject::new(Sdf::torus(2.0, 0.5));
+        t.transform = glm::translation(&glm::vec3(0.0, 1.0, 0.0));
+        assert_vec_eq(&t.normal_at(&glm::vec3(0.0, 1.5, 2.0)), &glm::vec3(0.0, 1.0, 0.0));
+        assert_vec_eq(&t.normal_at(&glm::vec3(2.5, 1.0, 0.0)), &glm::vec3(1.0, 0.0, 0.0));
+    }
+
+    // Ray through an SDF sphere and a box stretched by its transform
+    // Checks that sphere tracing finds both crossings where the analytic
+    // shapes would
+    #[test]
+    fn sphere_tracing() {
+        let s = SdfObject::new(Sdf::sphere(1.0));
+        let r = Ray::build(&glm::vec3(0.0, 0.0, -5.0), &glm::vec3(0.0, 0.0, 1.0));
+        let xs = s.intersect(&r);
+
+        assert_eq!(xs.len(), 2);
+        float_cmp::assert_approx_eq!(f32, xs[0].t(), 4.0, epsilon = 0.001);
+        float_cmp::assert_approx_eq!(f32, xs[1].t(), 6.0, epsilon = 0.001);
+
+        let miss = Ray::build(&glm::vec3(0.0, 1.5, -5.0), &glm::vec3(0.0, 0.0, 1.0));
+        assert!(s.intersect(&miss).is_empty());
+
+        let stretched = SdfObject::build(Sdf::cuboid(&glm::vec3(1.0, 1.0, 1.0)), &glm::scaling(&glm::vec3(1.0, 1.0, 3.0)));
+        let xs = stretched.intersect(&r);
+        float_cmp::assert_approx_eq!(f32, xs[0].t(), 2.0, epsilon = 0.001);
+        float_cmp::assert_approx_eq!(f32, xs[1].t(), 8.0, epsilon = 0.001);
+    }
+
+    // Ray starting inside, and one through a torus's hole along y and across
+    // it along x
+    // Checks that crossings still come in pairs
+    #[test]
+    fn crossings_pair_up() {
+        let s = SdfObject::new(Sdf::sphere(1.0));
+        let inside = Ray::build(&glm::Vec3::zeros(), &glm::vec3(0.0, 1.0, 0.0));
+        let xs = s.intersect(&inside);
+
+        assert_eq!(xs.len(), 2);
+        float_cmp::assert_approx_eq!(f32, xs[0].t(), -1.0, epsilon = 0.001);
+        float_cmp::assert_approx_eq!(f32, xs[1].t(), 1.0, epsilon = 0.001);
+
+        let torus = SdfObject::new(Sdf::torus(2.0, 0.5));
+        assert!(torus.intersect(&Ray::build(&glm::vec3(0.0, -5.0, 0.0), &glm::vec3(0.0, 1.0, 0.0))).is_empty());
+
+        let across: Vec<f32> = torus
+            .intersect(&Ray::build(&glm::vec3(-5.0, 0.0, 0.0), &glm::vec3(1.0, 0.0, 0.0)))
+            .iter()
+            .map(|i| i.t())
+            .collect();
+
+        assert_eq!(across.len(), 4);
+        for (t, expected) in across.iter().zip([2.5, 3.5, 6.5, 7.5]) {
+            float_cmp::assert_approx_eq!(f32, *t, expected, epsilon = 0.001);
+        }
+    }
+
+    // Rays skimming a sphere and sliding along a box's top face, within
+    // epsilon of the surface but never going in
+    // Checks that grazes aren't reported as crossings, and that rays near the
+    // tangent always get crossings in pairs
+    #[test]
+    fn grazes_are_not_crossings() {
+        let sphere = SdfObject::new(Sdf::sphere(1.0));
+        let skim = Ray::build(&glm::vec3(-3.0, 1.00005, 0.0), &glm::vec3(1.0, 0.0, 0.0));
+        assert!(sphere.intersect(&skim).is_empty());
+
+        let cuboid = SdfObject::new(Sdf::cuboid(&glm::vec3(0.5, 0.5, 0.5)));
+        let slide = Ray::build(&glm::vec3(-3.0, 0.50005, 0.1), &glm::vec3(1.0, 0.0, 0.0));
+        assert!(cuboid.intersect(&slide).is_empty());
+
+        for k in 0..60 {
+            let y = 1.0 - 0.0003 + k as f32 * 0.00001;
+            let r = Ray::build(&glm::vec3(-3.0, y, 0.0), &glm::vec3(1.0, 0.0, 0.0));
+            assert_eq!(sphere.intersect(&r).len() % 2, 0, "height {}", y);
+        }
+    }
+
+    // Checks that an SDF object shades in the world like any other
+    #[test]
+    fn shades_in_world() {
+        let mut w = World::new();
+        w.add(SdfObject::new(Sdf::sphere(1.0)));
+        w.add_light(crate::light::PointLight::build(&glm::vec3(-10.0, 10.0, -10.0), &glm::vec3(1.0, 1.0, 1.0)));
+
+        let r = Ray::build(&glm::vec3(0.0, 0.0, -5.0), &glm::vec3(0.0, 0.0, 1.0));
+        let hit = w.hit(&r).unwrap();
+        let comps = hit.prepare(&r);
+
+        assert_vec_eq(&comps.normalv, &glm::vec3(0.0, 0.0, -1.0));
+        assert!(w.color_at(&r).x > 0.1);
+    }
+}
+
+#[cfg(test)]
+mod polynomial_test {
+    use crate::polynomial::{solve_cubic, solve_quadratic, solve_quartic};
+
+    fn assert_roots(roots: &[f64], expected: &[f64], epsilon: f64) {
+        assert_eq!(roots.len(), expected.len(), "{:?} vs {:?}", roots, expected);
+
+        for (r, e) in roots.iter().zip(expected) {
+            float_cmp::assert_approx_eq!(f64, *r, *e, epsilon = epsilon);
+        }
+    }
+
+    // Checks ordinary, double, missing and degenerate quadratic roots
+    #[test]
+    fn quadratic() {
+        assert_roots(&solve_quadratic(1.0, -3.0, 2.0), &[1.0, 2.0], 1e-12);
+        assert_roots(&solve_quadratic(-2.0, 0.0, 8.0), &[-2.0, 2.0], 1e-12);
+        assert_roots(&solve_quadratic(1.0, -2.0, 1.0), &[1.0, 1.0], 1e-12);
+        assert_roots(&solve_quadratic(0.0, 2.0, -4.0), &[2.0], 1e-12);
+        assert_roots(&solve_quadratic(0.0, 0.0, 0.0), &[], 0.0);
+        assert!(solve_quadratic(1.0, 0.0, 1.0).is_empty());
+    }
+
+    // Roots 1e-8 and 1e8
+    // Checks that the small root keeps its digits, where the textbook
+    // formula would cancel it to nothing
+    #[test]
+    fn quadratic_without_cancellation() {
+        let roots = solve_quadratic(1.0, -(1e8 + 1e-8), 1.0);
+
+        float_cmp::assert_approx_eq!(f64, roots[0], 1e-8, epsilon = 1e-20);
+        float_cmp::assert_approx_eq!(f64, roots[1], 1e8, epsilon = 1e-4);
+
+        let textbook = (1e8 + 1e-8 - ((1e8_f64 + 1e-8).powi(2) - 4.0).sqrt()) / 2.0;
+        assert!((textbook - 1e-8).abs() > 1e-9);
+    }
+
+    // Checks cubics with one, three and repeated real roots
+    #[test]
+    fn cubic() {
+        // (x - 1)(x - 2)(x - 3)
+        assert_roots(&solve_cubic(1.0, -6.0, 11.0, -6.0), &[1.0, 2.0, 3.0], 1e-9);
+        // (x - 2)(x^2 + 1)
+        assert_roots(&solve_cubic(1.0, -2.0, 1.0, -2.0), &[2.0], 1e-9);
+        // (x + 1)^2 (x - 2)
+        assert_roots(&solve_cubic(2.0, 0.0, -6.0, -4.0), &[-1.0, 2.0], 1e-9);
+        // x^3
+        assert_roots(&solve_cubic(1.0, 0.0, 0.0, 0.0), &[0.0], 1e-9);
+    }
+
+    // Checks quartics with four, two and no real roots, a double root and a
+    // root at zero
+    #[test]
+    fn quartic() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(&solve_quartic(1.0, -10.0, 35.0, -50.0, 24.0), &[1.0, 2.0, 3.0, 4.0], 1e-9);
+        // (x^2 - 4)(x^2 + 1)
+        assert_roots(&solve_quartic(1.0, 0.0, -3.0, 0.0, -4.0), &[-2.0, 2.0], 1e-9);
+        // (x^2 + 1)(x^2 + 4)
+        assert!(solve_quartic(1.0, 0.0, 5.0, 0.0, 4.0).is_empty());
+        // (x - 1)^2 (x - 3)(x + 2)
+        let roots = solve_quartic(1.0, -3.0, -3.0, 11.0, -6.0);
+        assert!(roots.len() >= 3);
+        for r in roots {
+            assert!([1.0, 3.0, -2.0].iter().any(|e| (r - e).abs() < 1e-5), "{}", r);
+        }
+        // x (x - 1)(x + 1)(x - 5), scaled
+        assert_roots(&solve_quartic(3.0, -15.0, -3.0, 15.0, 0.0), &[-1.0, 0.0, 1.0, 5.0], 1e-9);
+    }
+
+    // Roots of a quartic with two pairs only 1e-4 apart
+    // Checks that polishing gets all four to well below their spacing
+    #[test]
+    fn quartic_close_roots() {
+        let expected = [-2.0001, -1.9999, 1.9999, 2.0001];
+        // Product of (x - r) over the roots, expanded
+        let (a, b) = (expected[0] * expected[1], expected[2] * expected[3]);
+        let roots = solve_quartic(1.0, 0.0, a + b - 16.0, 4.0 * (b - a), a * b);
+
+        assert_roots(&roots, &expected, 1e-7);
+    }
+}
+
+#[cfg(test)]
+mod torus_test {
+    extern crate nalgebra_glm as glm;
+
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use crate::torus::Torus;
+
+    fn assert_vec_eq(a: &glm::Vec3, b: &glm::Vec3) {
+        float_cmp::assert_approx_eq!(f32, a.x, b.x, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.y, b.y, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.z, b.z, epsilon = 0.0001);
+    }
+
+    fn ts(torus: &Torus, origin: &glm::Vec3, direction: &glm::Vec3) -> Vec<f32> {
+        torus.intersect(&Ray::build(origin, direction)).iter().map(|i| i.t()).collect()
+    }
+
+    // How far a point is from the torus's surface
+    fn surface_distance(torus: &Torus, p: &glm::Vec3) -> f32 {
+        glm::vec2(glm::vec2(p.x, p.z).norm() - torus.major(), p.y).norm() - torus.minor()
+    }
+
+    // Checks a ray across the whole torus, one through the hole and one that
+    // misses it
+    #[test]
+    fn intersect() {
+        let t = Torus::build(2.0, 0.5, &glm::Mat4::identity());
+
+        let across = ts(&t, &glm::vec3(-5.0, 0.0, 0.0), &glm::vec3(1.0, 0.0, 0.0));
+        assert_eq!(across.len(), 4);
+        for (t, e) in across.iter().zip([2.5, 3.5, 6.5, 7.5]) {
+            float_cmp::assert_approx_eq!(f32, *t, e, epsilon = 0.0001);
+        }
+
+        assert!(ts(&t, &glm::vec3(0.0, -5.0, 0.0), &glm::vec3(0.0, 1.0, 0.0)).is_empty());
+        assert!(ts(&t, &glm::vec3(-5.0, 1.0, 0.0), &glm::vec3(1.0, 0.0, 0.0)).is_empty());
+
+        let down = ts(&t, &glm::vec3(2.0, 5.0, 0.0), &glm::vec3(0.0, -1.0, 0.0));
+        assert_eq!(down.len(), 2);
+        float_cmp::assert_approx_eq!(f32, down[0], 4.5, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, down[1], 5.5, epsilon = 0.0001);
+    }
+
+    // Checks that the transform moves and scales the torus
+    #[test]
+    fn transformed() {
+        let m = glm::translation(&glm::vec3(0.0, 0.0, 10.0)) * glm::scaling(&glm::vec3(2.0, 2.0, 2.0));
+        let t = Torus::build(1.0, 0.25, &m);
+
+        let xs = ts(&t, &glm::vec3(0.0, 0.0, 0.0), &glm::vec3(0.0, 0.0, 1.0));
+        assert_eq!(xs.len(), 4);
+        for (t, e) in xs.iter().zip([7.5, 8.5, 11.5, 12.5]) {
+            float_cmp::assert_approx_eq!(f32, *t, e, epsilon = 0.0001);
+        }
+    }
+
+    // Rays skimming the top of the tube just inside it, from near and far,
+    // at a slant
+    // Checks that both close crossings on each side are found and lie on the
+    // surface
+    #[test]
+    fn grazing() {
+        let t = Torus::build(2.0, 0.5, &glm::Mat4::identity());
+
+        for (origin, height) in [(-5.0, 0.4999), (-500.0, 0.4999), (-5.0, 0.49)] {
+            let xs = ts(&t, &glm::vec3(origin, height, 0.0), &glm::vec3(1.0, 0.0, 0.0));
+            assert_eq!(xs.len(), 4, "{:?}", xs);
+
+            let half = (0.25_f32 - height * height).sqrt();
+            for (x, e) in xs.iter().zip([-2.0 - half, -2.0 + half, 2.0 - half, 2.0 + half]) {
+                float_cmp::assert_approx_eq!(f32, *x + origin, e, epsilon = 0.001);
+            }
+        }
+
+        let d = glm::vec3(1.0, 0.001, 0.3).normalize();
+        let o = glm::vec3(-6.0, 0.495, -1.8);
+        assert!(!t.intersect(&Ray::build(&o, &d)).is_empty());
+        for x in ts(&t, &o, &d) {
+            assert!(surface_distance(&t, &(o + d * x)).abs() < 0.001);
+        }
+
+        // Just above the tube there's nothing to hit
+        assert!(ts(&t, &glm::vec3(-5.0, 0.5001, 0.0), &glm::vec3(1.0, 0.0, 0.0)).is_empty());
+    }
+
+    // Checks normals on the outside, inside, top and bottom of the tube
+    #[test]
+    fn normals() {
+        let t = Torus::build(2.0, 0.5, &glm::Mat4::identity());
+
+        assert_vec_eq(&t.normal_at(&glm::vec3(2.5, 0.0, 0.0)), &glm::vec3(1.0, 0.0, 0.0));
+        assert_vec_eq(&t.normal_at(&glm::vec3(1.5, 0.0, 0.0)), &glm::vec3(-1.0, 0.0, 0.0));
+        assert_vec_eq(&t.normal_at(&glm::vec3(0.0, 0.5, -2.0)), &glm::vec3(0.0, 1.0, 0.0));
+        assert_vec_eq(&t.normal_at(&glm::vec3(0.0, -0.5, 2.0)), &glm::vec3(0.0, -1.0, 0.0));
+    }
+}
+
+#[cfg(test)]
+mod quadric_test {
+    extern crate nalgebra_glm as glm;
+
+    use crate::disk::Disk;
+    use crate::hittable::Hittable;
+    use crate::quadric::Quadric;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+
+    fn assert_vec_eq(a: &glm::Vec3, b: &glm::Vec3) {
+        float_cmp::assert_approx_eq!(f32, a.x, b.x, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.y, b.y, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.z, b.z, epsilon = 0.0001);
+    }
+
+    fn ts(object: &dyn Hittable, origin: &glm::Vec3, direction: &glm::Vec3) -> Vec<f32> {
+        object.intersect(&Ray::build(origin, direction)).iter().map(|i| i.t()).collect()
+    }
+
+    fn assert_ts(ts: &[f32], expected: &[f32]) {
+        assert_eq!(ts.len(), expected.len(), "{:?} vs {:?}", ts, expected);
+
+        for (t, e) in ts.iter().zip(expected) {
+            float_cmp::assert_approx_eq!(f32, *t, *e, epsilon = 0.0001);
+        }
+    }
+
+    // Unit sphere written out as a quadric, and a transformed one
+    // Checks that it's hit exactly where a Sphere is, with the same normals
+    #[test]
+    fn matches_sphere() {
+        let m = glm::translation(&glm::vec3(1.0, 0.5, 0.0)) * glm::scaling(&glm::vec3(2.0, 1.0, 1.0));
+        let mut q = Quadric::ellipsoid(1.0, 1.0, 1.0);
+        q.transform = m;
+        let s = Sphere::build(&glm::Vec3::zeros(), 1.0, &m);
+
+        for (o, d) in [
+            (glm::vec3(0.0, 0.5, -5.0), glm::vec3(0.0, 0.0, 1.0)),
+            (glm::vec3(-4.0, 0.2, 0.3), glm::vec3(1.0, 0.1, 0.0)),
+            (glm::vec3(0.0, 3.0, -5.0), glm::vec3(0.0, 0.0, 1.0)),
+        ] {
+            let expected = ts(&s, &o, &d);
+            assert_ts(&ts(&q, &o, &d), &expected);
+
+            for t in expected {
+                let p = o + d * t;
+                assert_vec_eq(&q.normal_at(&p), &s.normal_at(&p));
+            }
+        }
+    }
+
+    // Checks ellipsoid, paraboloid and hyperboloid hits along the axes
+    #[test]
+    fn shapes() {
+        let e = Quadric::ellipsoid(2.0, 1.0, 0.5);
+        assert_ts(&ts(&e, &glm::vec3(-5.0, 0.0, 0.0), &glm::vec3(1.0, 0.0, 0.0)), &[3.0, 7.0]);
+        assert_ts(&ts(&e, &glm::vec3(0.0, 0.0, -5.0), &glm::vec3(0.0, 0.0, 1.0)), &[4.5, 5.5]);
+
+        // A ray down the axis of the bowl is linear in t
+        let p = Quadric::paraboloid(1.0, 1.0);
+        assert_ts(&ts(&p, &glm::vec3(0.0, 5.0, 0.0), &glm::vec3(0.0, -1.0, 0.0)), &[5.0]);
+        assert_ts(&ts(&p, &glm::vec3(-5.0, 4.0, 0.0), &glm::vec3(1.0, 0.0, 0.0)), &[3.0, 7.0]);
+
+        let one = Quadric::hyperboloid_one_sheet(1.0, 1.0, 1.0);
+        assert_ts(&ts(&one, &glm::vec3(-5.0, 0.0, 0.0), &glm::vec3(1.0, 0.0, 0.0)), &[4.0, 6.0]);
+        let waist = 2.0_f32.sqrt();
+        assert_ts(&ts(&one, &glm::vec3(-5.0, 1.0, 0.0), &glm::vec3(1.0, 0.0, 0.0)), &[5.0 - waist, 5.0 + waist]);
+        assert!(ts(&one, &glm::vec3(0.0, -5.0, 0.0), &glm::vec3(0.0, 1.0, 0.0)).is_empty());
+
+        let two = Quadric::hyperboloid_two_sheets(1.0, 1.0, 1.0);
+        assert_ts(&ts(&two, &glm::vec3(0.0, -5.0, 0.0), &glm::vec3(0.0, 1.0, 0.0)), &[4.0, 6.0]);
+        assert!(ts(&two, &glm::vec3(-5.0, 0.0, 0.0), &glm::vec3(1.0, 0.0, 0.0)).is_empty());
+
+        let cone = Quadric::cone(1.0);
+        assert_ts(&ts(&cone, &glm::vec3(-5.0, 1.0, 0.0), &glm::vec3(1.0, 0.0, 0.0)), &[4.0, 6.0]);
+    }
+
+    // Checks that the 10 coefficients mean what they say, cross terms included
+    #[test]
+    fn coefficients() {
+        // x y = 1, a hyperbolic cylinder along z
+        let q = Quadric::build([0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0], &glm::Mat4::identity());
+        assert_eq!(q.value(&glm::vec3(2.0, 0.5, 7.0)), 0.0);
+        assert_eq!(q.value(&glm::vec3(1.0, 3.0, 0.0)), 2.0);
+        assert_ts(&ts(&q, &glm::vec3(-5.0, 1.0, 0.0), &glm::vec3(1.0, 0.0, 0.0)), &[6.0]);
+
+        // x + 2y + 3z + 4 = 0, a plane
+        let plane = Quadric::build([0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 4.0], &glm::Mat4::identity());
+        assert_ts(&ts(&plane, &glm::vec3(0.0, 0.0, 0.0), &glm::vec3(-1.0, 0.0, 0.0)), &[4.0]);
+        assert_vec_eq(&plane.normal_at(&glm::vec3(-4.0, 0.0, 0.0)), &glm::vec3(1.0, 2.0, 3.0).normalize());
+        assert_eq!(plane.coefficients()[7], 2.0);
+    }
+
+    // Infinite cylinder cut down to 0 <= y <= 1
+    // Checks that hits outside the box are dropped
+    #[test]
+    fn clipping() {
+        let c = Quadric::cylinder(1.0).clip(&glm::vec3(-10.0, 0.0, -10.0), &glm::vec3(10.0, 1.0, 10.0));
+
+        assert_ts(&ts(&c, &glm::vec3(-5.0, 0.5, 0.0), &glm::vec3(1.0, 0.0, 0.0)), &[4.0, 6.0]);
+        assert!(ts(&c, &glm::vec3(-5.0, 1.5, 0.0), &glm::vec3(1.0, 0.0, 0.0)).is_empty());
+
+        // In through the side, out through the open top
+        let d = glm::vec3(1.0, 1.0, 0.0).normalize();
+        assert_eq!(ts(&c, &glm::vec3(-1.5, 0.0, 0.0), &d).len(), 1);
+
+        // Straight up the middle it never meets the wall
+        assert!(ts(&c, &glm::vec3(0.0, -5.0, 0.0), &glm::vec3(0.0, 1.0, 0.0)).is_empty());
+    }
+
+    // Checks the paraboloid's normal at its tip and on its side
+    #[test]
+    fn normals() {
+        let p = Quadric::paraboloid(1.0, 1.0);
+        assert_vec_eq(&p.normal_at(&glm::vec3(0.0, 0.0, 0.0)), &glm::vec3(0.0, -1.0, 0.0));
+        assert_vec_eq(&p.normal_at(&glm::vec3(0.5, 0.25, 0.0)), &glm::vec3(1.0, -1.0, 0.0).normalize());
+    }
+
+    // Checks hits inside a disk, outside it, in an annulus's hole and along
+    // its plane
+    #[test]
+    fn disk() {
+        let d = Disk::new();
+        let down = glm::vec3(0.0, -1.0, 0.0);
+
+        assert_ts(&ts(&d, &glm::vec3(0.5, 2.0, 0.5), &down), &[2.0]);
+        assert!(ts(&d, &glm::vec3(1.0, 2.0, 0.5), &down).is_empty());
+        assert!(ts(&d, &glm::vec3(-5.0, 0.0, 0.0), &glm::vec3(1.0, 0.0, 0.0)).is_empty());
+
+        let ring = Disk::build(0.5, 1.0, &glm::Mat4::identity());
+        assert!(ts(&ring, &glm::vec3(0.2, 2.0, 0.2), &down).is_empty());
+        assert_ts(&ts(&ring, &glm::vec3(0.0, 2.0, 0.75), &down), &[2.0]);
+    }
+
+    // Disk turned to face -z and pushed back
+    // Checks the hit and the transformed normal
+    #[test]
+    fn disk_transformed() {
+        let m = glm::translation(&glm::vec3(0.0, 0.0, 3.0)) * glm::rotation(-std::f32::consts::FRAC_PI_2, &glm::vec3(1.0, 0.0, 0.0));
+        let d = Disk::build(0.0, 2.0, &m);
+
+        assert_ts(&ts(&d, &glm::vec3(1.0, 1.0, -2.0), &glm::vec3(0.0, 0.0, 1.0)), &[5.0]);
+        assert_vec_eq(&d.normal_at(&glm::vec3(0.0, 0.0, 3.0)), &glm::vec3(0.0, 0.0, -1.0));
+    }
+}
+
+#[cfg(test)]
+mod triangle_test {
+    extern crate nalgebra_glm as glm;
+
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use crate::triangle::Triangle;
+
+    fn assert_vec_eq(a: &glm::Vec3, b: &glm::Vec3) {
+        float_cmp::assert_approx_eq!(f32, a.x, b.x, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.y, b.y, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.z, b.z, epsilon = 0.0001);
+    }
+
+    fn book_triangle() -> Triangle {
+        Triangle::build(&glm::vec3(0.0, 1.0, 0.0), &glm::vec3(-1.0, 0.0, 0.0), &glm::vec3(1.0, 0.0, 0.0))
+    }
+
+    // Checks the normal of a flat triangle is the same everywhere
+    #[test]
+    fn flat_normal() {
+        let t = book_triangle();
+
+        assert_vec_eq(&t.normal_at(&glm::vec3(0.0, 0.5, 0.0)), &glm::vec3(0.0, 0.0, -1.0));
+        assert_vec_eq(&t.normal_at(&glm::vec3(-0.5, 0.75, 0.0)), &glm::vec3(0.0, 0.0, -1.0));
+        assert!(t.normals().is_none());
+    }
+
+    // Checks rays parallel to the triangle and past each edge miss it, and
+    // one through it hits
+    #[test]
+    fn intersect() {
+        let t = book_triangle();
+        let hit = |o: glm::Vec3, d: glm::Vec3| t.intersect(&Ray::build(&o, &d)).iter().map(|i| i.t()).collect::<Vec<f32>>();
+
+        assert!(hit(glm::vec3(0.0, -1.0, -2.0), glm::vec3(0.0, 1.0, 0.0)).is_empty());
+        assert!(hit(glm::vec3(1.0, 1.0, -2.0), glm::vec3(0.0, 0.0, 1.0)).is_empty());
+        assert!(hit(glm::vec3(-1.0, 1.0, -2.0), glm::vec3(0.0, 0.0, 1.0)).is_empty());
+        assert!(hit(glm::vec3(0.0, -1.0, -2.0), glm::vec3(0.0, 0.0, 1.0)).is_empty());
+        assert_eq!(hit(glm::vec3(0.0, 0.5, -2.0), glm::vec3(0.0, 0.0, 1.0)), vec![2.0]);
+    }
+
+    // The book's smooth triangle
+    // Checks the normal is blended from the corners by where the point is
+    #[test]
+    fn smooth_normal() {
+        let t = Triangle::smooth(
+            &glm::vec3(0.0, 1.0, 0.0),
+            &glm::vec3(-1.0, 0.0, 0.0),
+            &glm::vec3(1.0, 0.0, 0.0),
+            &glm::vec3(0.0, 1.0, 0.0),
+            &glm::vec3(-1.0, 0.0, 0.0),
+            &glm::vec3(1.0, 0.0, 0.0),
+        );
+
+        let (a, b, c) = t.barycentric(&glm::vec3(-0.2, 0.3, 0.0));
+        float_cmp::assert_approx_eq!(f32, a, 0.3, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, b, 0.45, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, c, 0.25, epsilon = 0.0001);
+
+        assert_vec_eq(&t.normal_at(&glm::vec3(-0.2, 0.3, 0.0)), &glm::vec3(-0.5547, 0.83205, 0.0));
+    }
+
+    // Checks that the transform moves the triangle and turns its normal
+    #[test]
+    fn transformed() {
+        let mut t = book_triangle();
+        t.transform = glm::translation(&glm::vec3(0.0, 0.0, 5.0)) * glm::rotation(std::f32::consts::PI, &glm::vec3(0.0, 1.0, 0.0));
+
+        let xs = t.intersect(&Ray::build(&glm::vec3(0.0, 0.5, 0.0), &glm::vec3(0.0, 0.0, 1.0)));
+        float_cmp::assert_approx_eq!(f32, xs[0].t(), 5.0, epsilon = 0.0001);
+        assert_vec_eq(&t.normal_at(&glm::vec3(0.0, 0.5, 5.0)), &glm::vec3(0.0, 0.0, 1.0));
+    }
+}
+
+#[cfg(test)]
+mod bezier_test {
+    extern crate nalgebra_glm as glm;
+
+    use crate::bezier::{self, BezierPatch};
+    use crate::ray::Ray;
+    use crate::world::World;
+
+    fn assert_vec_eq(a: &glm::Vec3, b: &glm::Vec3) {
+        float_cmp::assert_approx_eq!(f32, a.x, b.x, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.y, b.y, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.z, b.z, epsilon = 0.0001);
+    }
+
+    // Control points evenly spaced on the unit square, raised by `height`
+    fn patch(height: impl Fn(usize, usize) -> f32) -> BezierPatch {
+        let mut points = [glm::Vec3::zeros(); 16];
+
+        for (k, p) in points.iter_mut().enumerate() {
+            let (row, column) = (k / 4, k % 4);
+            *p = glm::vec3(column as f32 / 3.0, row as f32 / 3.0, height(row, column));
+        }
+
+        BezierPatch::build(points)
+    }
+
+    // Flat, evenly spaced patch
+    // Checks that it maps (u, v) straight onto the square and faces +z
+    #[test]
+    fn flat_patch() {
+        let p = patch(|_, _| 0.0);
+
+        assert_vec_eq(&p.point(0.25, 0.75), &glm::vec3(0.25, 0.75, 0.0));
+        assert_vec_eq(&p.normal(0.6, 0.1), &glm::vec3(0.0, 0.0, 1.0));
+
+        let (du, dv) = p.derivatives(0.3, 0.3);
+        assert_vec_eq(&du, &glm::vec3(1.0, 0.0, 0.0));
+        assert_vec_eq(&dv, &glm::vec3(0.0, 1.0, 0.0));
+    }
+
+    // Bumpy patch
+    // Checks that it passes through its corners and that the derivatives
+    // match finite differences
+    #[test]
+    fn curved_patch() {
+        let p = patch(|r, c| ((r * 7 + c * 3) % 5) as f32 * 0.3);
+
+        assert_vec_eq(&p.point(0.0, 0.0), &p.points[0]);
+        assert_vec_eq(&p.point(1.0, 0.0), &p.points[3]);
+        assert_vec_eq(&p.point(0.0, 1.0), &p.points[12]);
+        assert_vec_eq(&p.point(1.0, 1.0), &p.points[15]);
+
+        let (u, v, h) = (0.4, 0.7, 0.001);
+        let (du, dv) = p.derivatives(u, v);
+        let fd_u = (p.point(u + h, v) - p.point(u - h, v)) / (2.0 * h);
+        let fd_v = (p.point(u, v + h) - p.point(u, v - h)) / (2.0 * h);
+
+        assert!((du - fd_u).norm() < 0.01);
+        assert!((dv - fd_v).norm() < 0.01);
+        float_cmp::assert_approx_eq!(f32, glm::dot(&p.normal(u, v), &du), 0.0, epsilon = 0.0001);
+    }
+
+    // Flat patch cut 4 by 4
+    // Checks the triangle count, and that a ray hits the mesh where it would
+    // hit the patch, facing the same way
+    #[test]
+    fn tessellate() {
+        let p = patch(|_, _| 0.0);
+        let triangles = p.tessellate(4);
+        assert_eq!(triangles.len(), 32);
+
+        let mut w = World::new();
+        for t in triangles {
+            w.add(t);
+        }
+
+        let r = Ray::build(&glm::vec3(0.3, 0.6, -2.0), &glm::vec3(0.0, 0.0, 1.0));
+        let hit = w.hit(&r).unwrap();
+        float_cmp::assert_approx_eq!(f32, hit.t(), 2.0, epsilon = 0.0001);
+        assert_vec_eq(&hit.obj().normal_at(&r.position(hit.t())), &glm::vec3(0.0, 0.0, 1.0));
+    }
+
+    // Patch whose first row of control points is all one point, like the
+    // top of the teapot's lid
+    // Checks that the normal there is still defined and slivers are dropped
+    #[test]
+    fn pinched_patch() {
+        let mut p = patch(|r, _| 1.0 - r as f32 / 3.0);
+        for k in 0..4 {
+            p.points[k] = glm::vec3(0.5, 0.0, 1.0);
+        }
+
+        let n = p.normal(0.5, 0.0);
+        float_cmp::assert_approx_eq!(f32, n.norm(), 1.0, epsilon = 0.0001);
+
+        let triangles = p.tessellate(4);
+        assert_eq!(triangles.len(), 28);
+        assert!(triangles.iter().all(|t| !t.is_degenerate()));
+    }
+
+    // Checks both file formats give the same patch, and broken files are
+    // refused
+    #[test]
+    fn parse() {
+        let p = patch(|r, c| (r + c) as f32 * 0.1);
+        let vertices: String = p.points.iter().map(|v| format!("{}, {}, {}\n", v.x, v.y, v.z)).collect();
+        let indices: Vec<String> = (1..=16).rev().map(|i| i.to_string()).collect();
+
+        // Newell's layout, with the points listed backwards and indexed in
+        // reverse to match
+        let reversed: String = p.points.iter().rev().map(|v| format!("{},{},{}\n", v.x, v.y, v.z)).collect();
+        let newell = format!("1\n{}\n16\n{}", indices.join(","), reversed);
+        assert_eq!(bezier::parse_patches(&newell).unwrap(), vec![p]);
+
+        let bpt = format!("1\n3 3\n{}", vertices.replace(',', ""));
+        assert_eq!(bezier::parse_patches(&bpt).unwrap(), vec![p]);
+
+        assert!(bezier::parse_patches("").is_err());
+        assert!(bezier::parse_patches("1\n3 3\n0 0 0\n").is_err());
+        assert!(bezier::parse_patches(&newell.replace("16\n", "17\n")).is_err());
+        assert!(bezier::parse_patches(&format!("1\n{}\n16\n{}", indices.join(",").replace("16", "40"), reversed)).is_err());
+        assert!(bezier::parse_patches("1\n4 4\n").is_err());
+    }
+}
+
+#[cfg(test)]
+mod mesh_test {
+    extern crate nalgebra_glm as glm;
+
+    use crate::hittable::Hittable;
+    use crate::mesh::{Mesh, MeshSpec, Subdivision};
+
+    const CUBE: &str = "# cube
+v -1 -1 -1
+v 1 -1 -1
+v 1 1 -1
+v -1 1 -1
+v -1 -1 1
+v 1 -1 1
+v 1 1 1
+v -1 1 1
+f 1 4 3 2
+f 5 6 7 8
+f 1 2 6 5
+f 2/1 3/2 7/3 6/4
+f 3//1 4//2 8//3 7//4
+f -8 -4 -1 -5
+";
+
+    const TETRAHEDRON: &str = "v 1 1 1
+v 1 -1 -1
+v -1 1 -1
+v -1 -1 1
+f 1 2 3
+f 1 4 2
+f 1 3 4
+f 2 4 3
+";
+
+    fn assert_vec_eq(a: &glm::Vec3, b: &glm::Vec3) {
+        float_cmp::assert_approx_eq!(f32, a.x, b.x, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.y, b.y, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.z, b.z, epsilon = 0.0001);
+    }
+
+    // Flat 2 by 2 grid of quads on z = 0, open all round
+    fn grid() -> Mesh {
+        let positions = (0..9).map(|i| glm::vec3((i % 3) as f32, (i / 3) as f32, 0.0)).collect();
+        let faces = vec![vec![0, 1, 4, 3], vec![1, 2, 5, 4], vec![3, 4, 7, 6], vec![4, 5, 8, 7]];
+
+        Mesh::build(positions, faces)
+    }
+
+    // OBJ cube using every way of writing a face corner
+    // Checks that the vertices and faces come through with indices from zero
+    #[test]
+    fn parse_obj() {
+        let cube = Mesh::parse_obj(CUBE).unwrap();
+
+        assert_eq!(cube.positions.len(), 8);
+        assert_vec_eq(&cube.positions[6], &glm::vec3(1.0, 1.0, 1.0));
+        assert_eq!(cube.faces.len(), 6);
+        assert_eq!(cube.faces[0], vec![0, 3, 2, 1]);
+        assert_eq!(cube.faces[3], vec![1, 2, 6, 5]);
+        assert_eq!(cube.faces[5], vec![0, 4, 7, 3]);
+    }
+
+    // Broken OBJ files
+    // Checks that each is rejected rather than read wrong
+    #[test]
+    fn parse_obj_errors() {
+        assert!(Mesh::parse_obj("v 1 2\n").is_err());
+        assert!(Mesh::parse_obj("v 1 x 2\n").is_err());
+        assert!(Mesh::parse_obj("v 0 0 0\nv 1 0 0\nf 1 2\n").is_err());
+        assert!(Mesh::parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n").is_err());
+        assert!(Mesh::parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 -4\n").is_err());
+        assert!(Mesh::parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 0\n").is_err());
+    }
+
+    // OBJ faces that repeat a corner, one still a triangle without it and
+    // one left with only two corners
+    // Checks that the repeat is dropped and the collapsed face skipped
+    #[test]
+    fn parse_obj_repeated_corners() {
+        let mesh = Mesh::parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 2 3\nf 2 4 4\n").unwrap();
+
+        assert_eq!(mesh.faces, vec![vec![0, 1, 2]]);
+    }
+
+    // Mesh built by hand with a degenerate triangle next to a real one
+    // Checks that both schemes get through it without panicking
+    #[test]
+    fn subdivide_degenerate_face() {
+        let positions = vec![glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0)];
+        let mesh = Mesh::build(positions, vec![vec![0, 1, 2], vec![0, 1, 1]]);
+
+        assert_eq!(mesh.subdivide(Subdivision::Loop, 2).faces.len(), 32);
+        assert_eq!(mesh.subdivide(Subdivision::CatmullClark, 1).faces.len(), 6);
+    }
+
+    // Mesh specs as given to `--obj`
+    // Checks the defaults for a missing scheme or level, and that bad ones
+    // are refused
+    #[test]
+    fn parse_spec() {
+        let plain = MeshSpec::parse("models/cube.obj").unwrap();
+        assert_eq!(plain.path, std::path::PathBuf::from("models/cube.obj"));
+        assert_eq!((plain.scheme, plain.levels), (Subdivision::None, 0));
+
+        let smooth = MeshSpec::parse("cube.obj,catmull-clark").unwrap();
+        assert_eq!((smooth.scheme, smooth.levels), (Subdivision::CatmullClark, 2));
+
+        let loop_spec = MeshSpec::parse("cube.obj,loop,3").unwrap();
+        assert_eq!((loop_spec.scheme, loop_spec.levels), (Subdivision::Loop, 3));
+
+        assert!(MeshSpec::parse("cube.obj,butterfly").is_err());
+        assert!(MeshSpec::parse("cube.obj,loop,x").is_err());
+        assert!(MeshSpec::parse("cube.obj,loop,1,2").is_err());
+    }
+
+    // One OBJ file loaded through two specs with different levels
+    // Checks that each load is smoothed by its own setting
+    #[test]
+    fn specs_subdivide_separately() {
+        let path = std::env::temp_dir().join(format!("mesh_test_{}.obj", std::process::id()));
+        std::fs::write(&path, CUBE).unwrap();
+        let name = path.to_str().unwrap();
+
+        let once = MeshSpec::parse(&format!("{},catmull-clark,1", name)).unwrap().load().unwrap();
+        let twice = MeshSpec::parse(&format!("{},catmull-clark,2", name)).unwrap().load().unwrap();
+        let plain = MeshSpec::parse(name).unwrap().load().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(once.faces.len(), 24);
+        assert_eq!(twice.faces.len(), 96);
+        assert_eq!(plain.faces.len(), 6);
+    }
+
+    // Scheme names as given on the command line
+    // Checks that each known name parses and others don't
+    #[test]
+    fn parse_scheme() {
+        assert_eq!(Subdivision::parse("loop"), Some(Subdivision::Loop));
+        assert_eq!(Subdivision::parse("catmull-clark"), Some(Subdivision::CatmullClark));
+        assert_eq!(Subdivision::parse("none"), Some(Subdivision::None));
+        assert_eq!(Subdivision::parse("butterfly"), None);
+    }
+
+    // Cube of quads
+    // Checks that fanning it out gives two triangles a face
+    #[test]
+    fn triangulate() {
+        let cube = Mesh::parse_obj(CUBE).unwrap().triangulated();
+
+        assert_eq!(cube.faces.len(), 12);
+        assert!(cube.faces.iter().all(|f| f.len() == 3));
+    }
+
+    // Tetrahedron after one and two rounds of Loop subdivision
+    // Checks that each triangle becomes four and every edge gains a vertex
+    #[test]
+    fn loop_counts() {
+        let tetrahedron = Mesh::parse_obj(TETRAHEDRON).unwrap();
+        let once = tetrahedron.subdivide(Subdivision::Loop, 1);
+        let twice = tetrahedron.subdivide(Subdivision::Loop, 2);
+
+        assert_eq!(once.faces.len(), 16);
+        assert_eq!(once.positions.len(), 4 + 6);
+        assert_eq!(twice.faces.len(), 64);
+        assert_eq!(twice.positions.len(), 10 + 24);
+    }
+
+    // Regular tetrahedron after a round of Loop subdivision
+    // Checks the weights: a corner with three neighbors keeps 7/16 of itself,
+    // and an edge point is 3/8 of its ends and 1/8 of the far corners
+    #[test]
+    fn loop_positions() {
+        let tetrahedron = Mesh::parse_obj(TETRAHEDRON).unwrap();
+        let once = tetrahedron.loop_step();
+
+        // The other three corners sum to -(1, 1, 1)
+        assert_vec_eq(&once.positions[0], &(glm::vec3(1.0, 1.0, 1.0) * (7.0 / 16.0) - glm::vec3(1.0, 1.0, 1.0) * (3.0 / 16.0)));
+
+        // Edge (0, 1), lowest numbered, is the first new point
+        let expected = (glm::vec3(1.0, 1.0, 1.0) + glm::vec3(1.0, -1.0, -1.0)) * 0.375
+            + (glm::vec3(-1.0, 1.0, -1.0) + glm::vec3(-1.0, -1.0, 1.0)) * 0.125;
+        assert_vec_eq(&once.positions[4], &expected);
+    }
+
+    // Subdivision repeated on a closed surface
+    // Checks that the result keeps shrinking inside the original hull and that
+    // every face still winds outward
+    #[test]
+    fn loop_closed_surface() {
+        let tetrahedron = Mesh::parse_obj(TETRAHEDRON).unwrap();
+        let smooth = tetrahedron.subdivide(Subdivision::Loop, 3);
+
+        for p in smooth.positions.iter() {
+            assert!(p.norm() < 3.0_f32.sqrt());
+            assert!(p.x.abs() <= 1.0 && p.y.abs() <= 1.0 && p.z.abs() <= 1.0);
+        }
+
+        for f in smooth.faces.iter() {
+            let (a, b, c) = (smooth.positions[f[0]], smooth.positions[f[1]], smooth.positions[f[2]]);
+            let centroid = (a + b + c) / 3.0;
+            assert!(glm::dot(&glm::cross(&(b - a), &(c - a)), &centroid) > 0.0);
+        }
+    }
+
+    // Flat open grid, cut into triangles
+    // Checks that Loop subdivision keeps it flat and its boundary on the
+    // original square
+    #[test]
+    fn loop_boundary() {
+        let smooth = grid().subdivide(Subdivision::Loop, 2);
+
+        for p in smooth.positions.iter() {
+            float_cmp::assert_approx_eq!(f32, p.z, 0.0, epsilon = 0.0001);
+            assert!(p.x >= -0.0001 && p.x <= 2.0001 && p.y >= -0.0001 && p.y <= 2.0001);
+        }
+
+        // Corners left with a single triangle stay where they are
+        assert_vec_eq(&smooth.positions[2], &glm::vec3(2.0, 0.0, 0.0));
+        assert_vec_eq(&smooth.positions[6], &glm::vec3(0.0, 2.0, 0.0));
+
+        // The middle of a side is pulled along it, not into the grid
+        assert_vec_eq(&smooth.positions[1], &glm::vec3(1.0, 0.0, 0.0));
+    }
+
+    // Cube after one and two rounds of Catmull-Clark subdivision
+    // Checks that an n-sided face becomes n quads, with a point for every
+    // old vertex, edge and face
+    #[test]
+    fn catmull_clark_counts() {
+        let cube = Mesh::parse_obj(CUBE).unwrap();
+        let once = cube.subdivide(Subdivision::CatmullClark, 1);
+        let twice = cube.subdivide(Subdivision::CatmullClark, 2);
+
+        assert_eq!(once.faces.len(), 24);
+        assert_eq!(once.positions.len(), 8 + 12 + 6);
+        assert!(once.faces.iter().all(|f| f.len() == 4));
+        assert_eq!(twice.faces.len(), 96);
+        assert_eq!(twice.positions.len(), 26 + 48 + 24);
+
+        let tetrahedron = Mesh::parse_obj(TETRAHEDRON).unwrap().catmull_clark_step();
+        assert_eq!(tetrahedron.faces.len(), 12);
+        assert!(tetrahedron.faces.iter().all(|f| f.len() == 4));
+    }
+
+    // Cube from -1 to 1 after a round of Catmull-Clark subdivision
+    // Checks the new corner, edge and face points against working them out
+    // by hand
+    #[test]
+    fn catmull_clark_positions() {
+        let cube = Mesh::parse_obj(CUBE).unwrap().catmull_clark_step();
+
+        // Corner: (Q + 2R + (n - 3) P) / n with Q = 1/3 and R = 2/3 a side
+        assert_vec_eq(&cube.positions[6], &(glm::vec3(1.0, 1.0, 1.0) * (5.0 / 9.0)));
+
+        // Edge: its ends and the two face points averaged
+        assert!(cube
+            .positions
+            .iter()
+            .any(|p| glm::distance(p, &glm::vec3(0.0, 0.75, 0.75)) < 0.0001));
+
+        // Faces: their centers
+        assert_vec_eq(&cube.positions[20], &glm::vec3(0.0, 0.0, -1.0));
+        assert_vec_eq(&cube.positions[21], &glm::vec3(0.0, 0.0, 1.0));
+    }
+
+    // Flat open grid of quads
+    // Checks that Catmull-Clark keeps it flat, keeps the corners and moves the
+    // middle of each side only along it
+    #[test]
+    fn catmull_clark_boundary() {
+        let smooth = grid().subdivide(Subdivision::CatmullClark, 2);
+
+        for p in smooth.positions.iter() {
+            float_cmp::assert_approx_eq!(f32, p.z, 0.0, epsilon = 0.0001);
+        }
+
+        assert_vec_eq(&smooth.positions[0], &glm::vec3(0.0, 0.0, 0.0));
+        assert_vec_eq(&smooth.positions[1], &glm::vec3(1.0, 0.0, 0.0));
+        assert_vec_eq(&smooth.positions[4], &glm::vec3(1.0, 1.0, 0.0));
+    }
+
+    // No subdivision asked for
+    // Checks that the mesh comes back unchanged
+    #[test]
+    fn subdivide_none() {
+        let cube = Mesh::parse_obj(CUBE).unwrap();
+
+        assert_eq!(cube.subdivide(Subdivision::None, 3), cube);
+        assert_eq!(cube.subdivide(Subdivision::CatmullClark, 0), cube);
+    }
+
+    // Cube turned into triangles, flat and smooth
+    // Checks that the flat triangles face out of the cube and the smooth
+    // ones carry the averaged corner normals
+    #[test]
+    fn to_triangles() {
+        let cube = Mesh::parse_obj(CUBE).unwrap();
+
+        let flat = cube.to_triangles(false);
+        assert_eq!(flat.len(), 12);
+        for t in flat.iter() {
+            let [a, b, c] = t.points();
+            let centroid = (a + b + c) / 3.0;
+            assert!(glm::dot(&t.normal_at(&centroid), &centroid) > 0.0);
+            assert!(t.normals().is_none());
+        }
+
+        let smooth = cube.to_triangles(true);
+        let corner = glm::vec3(1.0, 1.0, 1.0);
+        let t = smooth.iter().find(|t| t.points().contains(&corner)).unwrap();
+        assert_vec_eq(&t.normal_at(&corner), &corner.normalize());
+    }
+
+    // Cube smoothed by Catmull-Clark, then triangulated
+    // Checks that the shading normals point away from the middle everywhere
+    #[test]
+    fn smoothed_normals_face_out() {
+        let smooth = Mesh::parse_obj(CUBE).unwrap().subdivide(Subdivision::CatmullClark, 2);
+
+        for (p, n) in smooth.positions.iter().zip(smooth.vertex_normals()) {
+            assert!(glm::dot(p, &n) > 0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod heightfield_test {
+    extern crate nalgebra_glm as glm;
+
+    use crate::heightfield::Heightfield;
+    use crate::hittable::Hittable;
+    use crate::image::Image;
+    use crate::ray::Ray;
+    use crate::triangle::Triangle;
+
+    fn assert_vec_eq(a: &glm::Vec3, b: &glm::Vec3) {
+        float_cmp::assert_approx_eq!(f32, a.x, b.x, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.y, b.y, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, a.z, b.z, epsilon = 0.0001);
+    }
+
+    fn hits(object: &dyn Hittable, r: &Ray) -> Vec<f32> {
+        object.intersect(r).iter().map(|i| i.t()).collect()
+    }
+
+    // Heights sampled from `f` on a `width` by `depth` grid over the unit square
+    fn field(width: usize, depth: usize, f: impl Fn(f32, f32) -> f32) -> Heightfield {
+        let heights = (0..width * depth)
+            .map(|k| f((k % width) as f32 / (width - 1) as f32, (k / width) as f32 / (depth - 1) as f32))
+            .collect();
+
+        Heightfield::build(width, depth, heights, &glm::Mat4::identity())
+    }
+
+    // Text PGM with comments in its header
+    // Checks that the values come out row by row scaled by the maximum
+    #[test]
+    fn plain_pgm() {
+        let image = Image::from_pgm(b"P2\n# made by hand\n3 2\n# max\n4\n0 1 2\n3 4 9\n").unwrap();
+
+        assert_eq!((image.width, image.height), (3, 2));
+        assert_vec_eq(&image.get(2, 0), &glm::vec3(0.5, 0.5, 0.5));
+        assert_vec_eq(&image.get(0, 1), &glm::vec3(0.75, 0.75, 0.75));
+        assert_vec_eq(&image.get(2, 1), &glm::vec3(1.0, 1.0, 1.0));
+    }
+
+    // Binary PGMs with one and two bytes a value
+    // Checks that both read back, the wide one big-endian
+    #[test]
+    fn binary_pgm() {
+        let mut bytes = b"P5 2 1 255\n".to_vec();
+        bytes.extend([51, 255]);
+        let image = Image::from_pgm(&bytes).unwrap();
+        float_cmp::assert_approx_eq!(f32, image.get(0, 0).x, 0.2, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, image.get(1, 0).x, 1.0, epsilon = 0.0001);
+
+        let mut bytes = b"P5\n2 1\n1000\n".to_vec();
+        bytes.extend([1, 244, 3, 232]);
+        let image = Image::from_pgm(&bytes).unwrap();
+        float_cmp::assert_approx_eq!(f32, image.get(0, 0).x, 0.5, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, image.get(1, 0).x, 1.0, epsilon = 0.0001);
+    }
+
+    // Broken PGMs
+    // Checks that each is rejected
+    #[test]
+    fn pgm_errors() {
+        assert!(Image::from_pgm(b"P6\n1 1\n255\n\x00\x00\x00").is_err());
+        assert!(Image::from_pgm(b"P2\n2 2\n255\n1 2 3\n").is_err());
+        assert!(Image::from_pgm(b"P2\n1 1\n0\n0\n").is_err());
+        assert!(Image::from_pgm(b"P5\n2 2\n255\n\x00\x00").is_err());
+        assert!(Image::from_pgm(b"P5\n1 1\n70000\n\x00\x00").is_err());
+
+        // Sizes that overflow once multiplied out
+        let huge = usize::MAX / 2;
+        assert!(Image::from_pgm(format!("P5\n{} {}\n255\n", huge, huge).as_bytes()).is_err());
+        assert!(Image::from_pgm(format!("P5\n{} 1\n65535\n", huge).as_bytes()).is_err());
+        assert!(Image::from_pgm(format!("P2\n{} {}\n255\n", huge, huge).as_bytes()).is_err());
+    }
+
+    // Grayscale image saved as a .pgm
+    // Checks that it loads as a heightfield with rows running along z
+    #[test]
+    fn load_pgm() {
+        let path = std::env::temp_dir().join(format!("heightfield_test_{}.pgm", std::process::id()));
+        std::fs::write(&path, "P2\n2 3\n10\n0 10\n5 5\n10 0\n").unwrap();
+        let field = Heightfield::load(&path, &glm::Mat4::identity()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((field.width(), field.depth()), (2, 3));
+        float_cmp::assert_approx_eq!(f32, field.height(1, 0), 1.0, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, field.height(0, 1), 0.5, epsilon = 0.0001);
+        float_cmp::assert_approx_eq!(f32, field.height(1, 2), 0.0, epsilon = 0.0001);
+
+        assert!(Heightfield::from_image(&Image::new(1, 5), &glm::Mat4::identity()).is_err());
+    }
+
+    // Flat field at height 0.5, rays straight down and at a slant
+    // Checks where they hit and that the normal is straight up
+    #[test]
+    fn flat_field() {
+        let field = field(5, 5, |_, _| 0.5);
+
+        let r = Ray::build(&glm::vec3(0.3, 2.0, 0.7), &glm::vec3(0.0, -1.0, 0.0));
+        assert_eq!(hits(&field, &r).len(), 1);
+        float_cmp::assert_approx_eq!(f32, hits(&field, &r)[0], 1.5, epsilon = 0.0001);
+
+        let r = Ray::build(&glm::vec3(-0.5, 1.5, 0.2), &glm::vec3(1.0, -1.0, 0.3));
+        let t = hits(&field, &r);
+        assert_eq!(t.len(), 1);
+        assert_vec_eq(&r.position(t[0]), &glm::vec3(0.5, 0.5, 0.5));
+        assert_vec_eq(&field.normal_at(&r.position(t[0])), &glm::vec3(0.0, 1.0, 0.0));
+    }
+
+    // Rays that pass beside, over and under a bumpy field
+    // Checks that none of them hit
+    #[test]
+    fn misses() {
+        let field = field(6, 6, |x, z| (x * 7.0).sin() * (z * 5.0).cos() * 0.2 + 0.3);
+
+        assert!(hits(&field, &Ray::build(&glm::vec3(1.5, 2.0, 0.5), &glm::vec3(0.0, -1.0, 0.0))).is_empty());
+        assert!(hits(&field, &Ray::build(&glm::vec3(-1.0, 0.9, 0.5), &glm::vec3(1.0, 0.0, 0.1))).is_empty());
+        assert!(hits(&field, &Ray::build(&glm::vec3(-1.0, -0.5, 0.5), &glm::vec3(1.0, 0.0, 0.0))).is_empty());
+    }
+
+    // Ramp rising along x
+    // Checks the hit height and that the normal tilts back down the slope
+    #[test]
+    fn ramp() {
+        let field = field(9, 4, |x, _| x);
+
+        let r = Ray::build(&glm::vec3(0.3, 2.0, 0.6), &glm::vec3(0.0, -1.0, 0.0));
+        let t = hits(&field, &r);
+        assert_eq!(t.len(), 1);
+        float_cmp::assert_approx_eq!(f32, r.position(t[0]).y, 0.3, epsilon = 0.0001);
+        assert_vec_eq(&field.normal_at(&r.position(t[0])), &glm::vec3(-1.0, 1.0, 0.0).normalize());
+    }
+
+    // Bumpy field, and the same triangles tested one by one
+    // Checks that walking the grid finds exactly the hits a brute force
+    // search does, for rays from many directions
+    #[test]
+    fn matches_brute_force() {
+        let (width, depth) = (7, 5);
+        let field = field(width, depth, |x, z| (x * 9.0).sin() * (z * 6.0).cos() * 0.4 + 0.5);
+
+        let point = |i: usize, j: usize| {
+            glm::vec3(i as f32 / (width - 1) as f32, field.height(i, j), j as f32 / (depth - 1) as f32)
+        };
+        let mut triangles = vec![];
+        for j in 0..depth - 1 {
+            for i in 0..width - 1 {
+                triangles.push(Triangle::build(&point(i, j), &point(i + 1, j), &point(i + 1, j + 1)));
+                triangles.push(Triangle::build(&point(i, j), &point(i + 1, j + 1), &point(i, j + 1)));
+            }
+        }
+
+        let mut total = 0;
+        for k in 0..200 {
+            let a = k as f32 * 2.399;
+            let origin = glm::vec3(0.5 + 1.3 * a.cos(), 1.2 + 0.6 * (a * 0.7).sin(), 0.5 + 1.1 * a.sin());
+            let target = glm::vec3(0.1 + 0.8 * (a * 1.3).sin().abs(), 0.3, 0.1 + 0.8 * (a * 0.9).cos().abs());
+            let r = Ray::build(&origin, &(target - origin));
+
+            let mut expected: Vec<f32> = triangles.iter().flat_map(|t| hits(t, &r)).collect();
+            expected.sort_by(|a, b| a.total_cmp(b));
+            expected.dedup_by(|a, b| (*a - *b).abs() < 1e-5);
+
+            let found = hits(&field, &r);
+            assert_eq!(found.len(), expected.len(), "ray {}", k);
+            for (f, e) in found.iter().zip(expected.iter()) {
+                float_cmp::assert_approx_eq!(f32, *f, *e, epsilon = 0.0001);
+            }
+
+            total += found.len();
+        }
+
+        assert!(total > 200);
+    }
+
+    // Field with a NaN height in the middle, crossed by rays over that cell
+    // Checks that intersecting it doesn't panic and the other cells still hit
+    #[test]
+    fn nan_height() {
+        let field = field(5, 5, |x, z| if x == 0.5 && z == 0.5 { f32::NAN } else { 0.2 });
+
+        for k in 0..20 {
+            let x = 0.05 + k as f32 * 0.045;
+            let r = Ray::build(&glm::vec3(x, 1.0, -1.0), &glm::vec3(0.0, -0.4, 1.0));
+            field.intersect(&r);
+        }
+
+        let t = hits(&field, &Ray::build(&glm::vec3(0.1, 1.0, 0.1), &glm::vec3(0.0, -1.0, 0.0)));
+        assert_eq!(t.len(), 1);
+        float_cmp::assert_approx_eq!(f32, t[0], 0.8, epsilon = 0.0001);
+    }
+
+    // Points either side of the line between two cells
+    // Checks that the blended normal doesn't jump there
+    #[test]
+    fn smooth_normals() {
+        let field = field(5, 5, |x, z| (x * 4.0).sin() * 0.3 + z * z * 0.5);
+
+        let pairs = [
+            (glm::vec3(0.4999, 0.0, 0.3), glm::vec3(0.5001, 0.0, 0.3)),
+            (glm::vec3(0.6, 0.0, 0.2499), glm::vec3(0.6, 0.0, 0.2501)),
+        ];
+
+        for &(a, b) in pairs.iter() {
+            let (na, nb) = (field.normal_at(&a), field.normal_at(&b));
+            assert!(glm::dot(&na, &nb) > 0.9999);
+        }
+
+        let n = field.normal_at(&glm::vec3(0.3, 0.0, 0.6));
+        assert!(n.y > 0.0);
+        float_cmp::assert_approx_eq!(f32, n.norm(), 1.0, epsilon = 0.0001);
+    }
+
+    // Field stretched to 10 by 2 by 10 and moved
+    // Checks that the hit and normal follow the transform
+    #[test]
+    fn transformed() {
+        let mut field = field(4, 4, |_, _| 0.5);
+        field.transform = glm::translation(&glm::vec3(-5.0, -1.0, 0.0)) * glm::scaling(&glm::vec3(10.0, 2.0, 10.0));
+
+        let r = Ray::build(&glm::vec3(0.0, 5.0, 5.0), &glm::vec3(0.0, -1.0, 0.0));
+        let t = hits(&field, &r);
+        assert_eq!(t.len(), 1);
+        assert_vec_eq(&r.position(t[0]), &glm::vec3(0.0, 0.0, 5.0));
+        assert_vec_eq(&field.normal_at(&r.position(t[0])), &glm::vec3(0.0, 1.0, 0.0));
+    }
+}
@@ -0,0 +1,108 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Mat4;
+use glm::Vec3;
+
+use crate::hittable::Hittable;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::polynomial;
+use crate::ray::Ray;
+
+// Ring around the y axis: a tube of radius `minor` swept around a circle of
+// radius `major`
+#[allow(dead_code)]
+pub struct Torus {
+    major: f32,
+    minor: f32,
+    pub transform: Mat4,
+    pub material: Material,
+    pub name: String,
+}
+
+#[allow(dead_code)]
+impl Torus {
+    pub fn new() -> Torus {
+        Torus::build(1.0, 0.25, &Mat4::identity())
+    }
+
+    pub fn build(major: f32, minor: f32, t: &Mat4) -> Torus {
+        Torus {
+            major,
+            minor,
+            transform: *t,
+            material: Material::new(),
+            name: String::from("torus"),
+        }
+    }
+
+    pub fn major(&self) -> f32 {
+        self.major
+    }
+
+    pub fn minor(&self) -> f32 {
+        self.minor
+    }
+}
+
+impl Hittable for Torus {
+    // Points on the ray satisfy (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2), a
+    // quartic in t. The ray is first moved up to where it passes closest to
+    // the center, so the coefficients don't blow up for faraway rays.
+    fn intersect(&self, r: &Ray) -> Vec<Intersection<'_>> {
+        let r2 = Ray::transform(r, &glm::inverse(&self.transform_at(r.time)));
+
+        let d = glm::vec3(r2.direction.x as f64, r2.direction.y as f64, r2.direction.z as f64);
+        let o = glm::vec3(r2.origin.x as f64, r2.origin.y as f64, r2.origin.z as f64);
+        let dd = glm::dot(&d, &d);
+
+        if dd == 0.0 {
+            return vec![];
+        }
+
+        let shift = -glm::dot(&o, &d) / dd;
+        let o = o + d * shift;
+
+        let (big, small) = (self.major as f64 * self.major as f64, self.minor as f64 * self.minor as f64);
+        let od = glm::dot(&o, &d);
+        let k = glm::dot(&o, &o) + big - small;
+
+        let roots = polynomial::solve_quartic(
+            dd * dd,
+            4.0 * dd * od,
+            4.0 * od * od + 2.0 * dd * k - 4.0 * big * (d.x * d.x + d.z * d.z),
+            4.0 * od * k - 8.0 * big * (o.x * d.x + o.z * d.z),
+            k * k - 4.0 * big * (o.x * o.x + o.z * o.z),
+        );
+
+        roots
+            .iter()
+            .map(|t| Intersection::build((t + shift) as f32, self))
+            .collect()
+    }
+
+    // Away from the nearest point on the circle through the tube's middle
+    fn normal_at_time(&self, p: &Vec3, time: f32) -> Vec3 {
+        let inverse = glm::inverse(&self.transform_at(time));
+
+        let object_point = glm::vec4_to_vec3(&(inverse * glm::vec4(p.x, p.y, p.z, 1.0)));
+        let ring = glm::vec3(object_point.x, 0.0, object_point.z);
+        let center = if ring.norm() > 0.0 { ring.normalize() * self.major } else { ring };
+        let object_normal = object_point - center;
+        let world_normal = glm::transpose(&inverse) * glm::vec4(object_normal.x, object_normal.y, object_normal.z, 0.0);
+
+        glm::vec4_to_vec3(&world_normal).normalize()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn transform(&self) -> &Mat4 {
+        &self.transform
+    }
+}
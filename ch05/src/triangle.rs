@@ -0,0 +1,146 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Mat4;
+use glm::Vec3;
+
+use crate::hittable::Hittable;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::ray::Ray;
+
+// How close to parallel a ray can be to a triangle's plane and still hit it
+const PARALLEL_EPSILON: f32 = 1e-7;
+
+// The book's triangle from chapter 15, and its smooth variant: with vertex
+// normals set, the normal is blended across the face from them, so a mesh of
+// small triangles shades like the curved surface it approximates.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct Triangle {
+    p1: Vec3,
+    p2: Vec3,
+    p3: Vec3,
+    e1: Vec3,
+    e2: Vec3,
+    normal: Vec3,
+    normals: Option<[Vec3; 3]>,
+    pub transform: Mat4,
+    pub material: Material,
+    pub name: String,
+}
+
+#[allow(dead_code)]
+impl Triangle {
+    pub fn build(p1: &Vec3, p2: &Vec3, p3: &Vec3) -> Triangle {
+        let (e1, e2) = (p2 - p1, p3 - p1);
+
+        Triangle {
+            p1: *p1,
+            p2: *p2,
+            p3: *p3,
+            e1,
+            e2,
+            normal: glm::cross(&e2, &e1).normalize(),
+            normals: None,
+            transform: Mat4::identity(),
+            material: Material::new(),
+            name: String::from("triangle"),
+        }
+    }
+
+    pub fn smooth(p1: &Vec3, p2: &Vec3, p3: &Vec3, n1: &Vec3, n2: &Vec3, n3: &Vec3) -> Triangle {
+        let mut t = Triangle::build(p1, p2, p3);
+        t.normals = Some([*n1, *n2, *n3]);
+        t
+    }
+
+    pub fn points(&self) -> [Vec3; 3] {
+        [self.p1, self.p2, self.p3]
+    }
+
+    pub fn normals(&self) -> Option<[Vec3; 3]> {
+        self.normals
+    }
+
+    // Too thin to have a normal, like the slivers at a patch's pole
+    pub fn is_degenerate(&self) -> bool {
+        glm::cross(&self.e2, &self.e1).norm() < f32::EPSILON
+    }
+
+    // How much of each corner there is in a point on the triangle's plane
+    pub fn barycentric(&self, p: &Vec3) -> (f32, f32, f32) {
+        let v = p - self.p1;
+        let (d11, d12, d22) = (glm::dot(&self.e1, &self.e1), glm::dot(&self.e1, &self.e2), glm::dot(&self.e2, &self.e2));
+        let (dv1, dv2) = (glm::dot(&v, &self.e1), glm::dot(&v, &self.e2));
+        let denom = d11 * d22 - d12 * d12;
+
+        if denom == 0.0 {
+            return (1.0, 0.0, 0.0);
+        }
+
+        let u = (d22 * dv1 - d12 * dv2) / denom;
+        let w = (d11 * dv2 - d12 * dv1) / denom;
+
+        (1.0 - u - w, u, w)
+    }
+}
+
+impl Hittable for Triangle {
+    // Möller and Trumbore's test, as in the book
+    fn intersect(&self, r: &Ray) -> Vec<Intersection<'_>> {
+        let r2 = Ray::transform(r, &glm::inverse(&self.transform_at(r.time)));
+
+        let dir_cross_e2 = glm::cross(&r2.direction, &self.e2);
+        let det = glm::dot(&self.e1, &dir_cross_e2);
+
+        if det.abs() < PARALLEL_EPSILON {
+            return vec![];
+        }
+
+        let f = 1.0 / det;
+        let p1_to_origin = r2.origin - self.p1;
+        let u = f * glm::dot(&p1_to_origin, &dir_cross_e2);
+
+        if !(0.0..=1.0).contains(&u) {
+            return vec![];
+        }
+
+        let origin_cross_e1 = glm::cross(&p1_to_origin, &self.e1);
+        let v = f * glm::dot(&r2.direction, &origin_cross_e1);
+
+        if v < 0.0 || u + v > 1.0 {
+            return vec![];
+        }
+
+        vec![Intersection::build(f * glm::dot(&self.e2, &origin_cross_e1), self)]
+    }
+
+    fn normal_at_time(&self, p: &Vec3, time: f32) -> Vec3 {
+        let inverse = glm::inverse(&self.transform_at(time));
+
+        let object_normal = match self.normals {
+            Some([n1, n2, n3]) => {
+                let object_point = glm::vec4_to_vec3(&(inverse * glm::vec4(p.x, p.y, p.z, 1.0)));
+                let (a, b, c) = self.barycentric(&object_point);
+                n1 * a + n2 * b + n3 * c
+            }
+            None => self.normal,
+        };
+
+        let world_normal = glm::transpose(&inverse) * glm::vec4(object_normal.x, object_normal.y, object_normal.z, 0.0);
+
+        glm::vec4_to_vec3(&world_normal).normalize()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn transform(&self) -> &Mat4 {
+        &self.transform
+    }
+}
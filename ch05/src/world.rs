@@ -0,0 +1,221 @@
+extern crate nalgebra_glm as glm;
+
+use glm::Vec3;
+
+use crate::adaptive::Sample;
+use crate::grid::GridVolume;
+use crate::hittable::Hittable;
+use crate::intersection::Intersection;
+use crate::light::{self, Light, LightSample};
+use crate::medium::{self, Fog, Interaction, Span, Volume};
+use crate::ray::Ray;
+use crate::rng::Rng;
+
+// Fog, volumes and grids only show up with the path tracer; the Whitted renderer
+// sees straight through them
+pub struct World {
+    pub objects: Vec<Box<dyn Hittable>>,
+    pub lights: Vec<Box<dyn Light>>,
+    pub fog: Option<Fog>,
+    pub volumes: Vec<Volume>,
+    pub grids: Vec<GridVolume>,
+}
+
+#[allow(dead_code)]
+impl World {
+    pub fn new() -> World {
+        World {
+            objects: vec![],
+            lights: vec![],
+            fog: None,
+            volumes: vec![],
+            grids: vec![],
+        }
+    }
+
+    pub fn add(&mut self, object: impl Hittable + 'static) {
+        self.objects.push(Box::new(object));
+    }
+
+    pub fn add_light(&mut self, light: impl Light + 'static) {
+        self.lights.push(Box::new(light));
+    }
+
+    pub fn add_volume(&mut self, volume: Volume) {
+        self.volumes.push(volume);
+    }
+
+    pub fn add_grid(&mut self, grid: GridVolume) {
+        self.grids.push(grid);
+    }
+
+    // Every intersection with every object, sorted by t
+    pub fn intersect(&self, r: &Ray) -> Vec<Intersection<'_>> {
+        let mut xs: Vec<Intersection> = self
+            .objects
+            .iter()
+            .flat_map(|o| o.intersect(r))
+            .collect();
+
+        xs.sort_by(|a, b| a.t().total_cmp(&b.t()));
+
+        xs
+    }
+
+    pub fn hit(&self, r: &Ray) -> Option<Intersection<'_>> {
+        Intersection::hit(&self.intersect(r))
+    }
+
+    // Is anything between `point` and `light_position` at `time`?
+    pub fn is_shadowed(&self, point: &Vec3, light_position: &Vec3, time: f32) -> bool {
+        self.is_occluded(point, &LightSample::toward(point, light_position, &Vec3::zeros()), time)
+    }
+
+    // Does anything block `sample` on its way to `point`?
+    pub fn is_occluded(&self, point: &Vec3, sample: &LightSample, time: f32) -> bool {
+        match self.hit(&Ray::build_at(point, &sample.direction, time)) {
+            Some(i) => i.t() < sample.distance,
+            None => false,
+        }
+    }
+
+    // Fraction of `sample`'s light that reaches `point`: none if a surface
+    // blocks it, otherwise what gets through the fog, volumes and grids on
+    // the way. Grids only give an estimate, seeded by the shadow ray.
+    pub fn transmittance(&self, point: &Vec3, sample: &LightSample, time: f32) -> Vec3 {
+        let r = Ray::build_at(point, &sample.direction, time);
+
+        if self.hit(&r).is_some_and(|i| i.t() < sample.distance) {
+            return Vec3::zeros();
+        }
+
+        let mut rng = Rng::seeded(&r.seed());
+        let grids: f32 = self.grids.iter().map(|g| g.transmittance(&r, sample.distance, &mut rng)).product();
+
+        medium::transmittance(&self.media(&r, sample.distance)) * grids
+    }
+
+    // Whether the ray scatters in a medium before `t_max` and how that
+    // changes its throughput, or None if it never enters one.
+    //
+    // Homogeneous media and each grid pick a distance independently and the
+    // nearest wins. Grids track exactly, so their chance of not having
+    // scattered yet is their transmittance and cancels out; only the
+    // homogeneous media's chance needs dividing out of a grid's weight.
+    pub fn sample_media(&self, r: &Ray, t_max: f32, rng: &mut Rng) -> Option<Interaction> {
+        let spans = self.media(r, t_max);
+
+        if spans.is_empty() && self.grids.iter().all(|g| g.span(r, t_max).is_none()) {
+            return None;
+        }
+
+        let homogeneous = if spans.is_empty() {
+            Interaction::Pass {
+                weight: glm::vec3(1.0, 1.0, 1.0),
+            }
+        }
+        else {
+            medium::sample_distance(&spans, rng)
+        };
+
+        let reach = match homogeneous {
+            Interaction::Scatter { t, .. } => t,
+            Interaction::Pass { .. } => t_max,
+        };
+
+        let nearest = self
+            .grids
+            .iter()
+            .filter_map(|g| g.sample_distance(r, reach, rng).map(|t| (t, g)))
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+
+        match nearest {
+            Some((t, g)) => {
+                let before: Vec<Span> = spans
+                    .iter()
+                    .filter(|s| s.start < t)
+                    .map(|s| Span { end: s.end.min(t), ..*s })
+                    .collect();
+                let tr = medium::transmittance(&before);
+
+                Some(Interaction::Scatter {
+                    t,
+                    weight: g.albedo.component_mul(&tr) / tr.mean(),
+                    medium: g.medium_at(&r.position(t)),
+                })
+            }
+            None => Some(homogeneous),
+        }
+    }
+
+    // Parts of the ray between 0 and `t_max` that run through the fog or a
+    // volume
+    pub fn media(&self, r: &Ray, t_max: f32) -> Vec<Span> {
+        let mut spans: Vec<Span> = self.volumes.iter().flat_map(|v| v.spans(r, t_max)).collect();
+
+        if let Some(fog) = &self.fog {
+            spans.extend(fog.spans(r, t_max));
+        }
+
+        spans
+    }
+
+    // Fraction of the shadow rays from `point` that reach `light`
+    pub fn intensity_at(&self, light: &dyn Light, point: &Vec3, time: f32, rng: &mut Rng) -> f32 {
+        let samples = light.samples(point, rng);
+        let visible = samples.iter().filter(|s| !self.is_occluded(point, s, time)).count();
+
+        visible as f32 / samples.len().max(1) as f32
+    }
+
+    // Phong shading summed over the lights, with one shadow ray per light
+    // sample. Area lights have several jittered samples, so their shadows come
+    // out fractional.
+    pub fn shade_hit(&self, hit: &Intersection, r: &Ray) -> Vec3 {
+        let comps = hit.prepare(r);
+        let material = comps.obj.material();
+
+        // Jitter depends only on the ray, so renders are repeatable
+        let mut rng = Rng::seeded(&r.seed());
+
+        self.lights.iter().fold(material.emissive, |sum, l| {
+            let samples = l.samples(&comps.over_point, &mut rng);
+            let visible: Vec<bool> = samples
+                .iter()
+                .map(|s| !self.is_occluded(&comps.over_point, s, r.time))
+                .collect();
+
+            sum + light::lighting(material, l.as_ref(), &samples, &visible, &comps.eyev, &comps.normalv)
+        })
+    }
+
+    // What a ray that misses everything sees: black unless a light such as
+    // an environment map surrounds the scene
+    pub fn background(&self, direction: &Vec3) -> Vec3 {
+        self.lights
+            .iter()
+            .filter_map(|l| l.background(direction))
+            .fold(Vec3::zeros(), |sum, c| sum + c)
+    }
+
+    pub fn color_at(&self, r: &Ray) -> Vec3 {
+        match self.hit(r) {
+            Some(i) => self.shade_hit(&i, r),
+            None => self.background(&r.direction),
+        }
+    }
+
+    // Color plus the object that was hit, for adaptive sampling
+    pub fn sample_at(&self, r: &Ray) -> Sample {
+        match self.hit(r) {
+            Some(i) => Sample {
+                color: self.shade_hit(&i, r),
+                obj: Some(i.obj_id()),
+            },
+            None => Sample {
+                color: self.background(&r.direction),
+                obj: None,
+            },
+        }
+    }
+}